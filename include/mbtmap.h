@@ -0,0 +1,31 @@
+#ifndef MBTMAP_H
+#define MBTMAP_H
+
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct mbtmap_resolver mbtmap_resolver;
+
+/* Open the source map, or WASM module referring to its source map, for frames
+ * in shape of the preset (null for "moonbit"). Null on failure. */
+mbtmap_resolver *mbtmap_open_map(const char *path, const char *preset);
+
+void mbtmap_close(mbtmap_resolver *resolver);
+
+/* Source file of the address, with line and column written through the
+ * pointers. Null if the address does not resolve. */
+char *mbtmap_resolve(const mbtmap_resolver *resolver, uint32_t addr, uint32_t *line, uint32_t *column);
+
+/* Traceback with source location appended to each frame. */
+char *mbtmap_rewrite(const mbtmap_resolver *resolver, const char *text);
+
+void mbtmap_free_string(char *s);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,106 @@
+"""Resolver running in the Python process, over the C ABI of mbtmap built with
+`cargo build --lib --release --features capi`"""
+
+import ctypes
+import os
+import sys
+
+
+def _library_path():
+    if sys.platform == "win32":
+        name = "mbtmap.dll"
+    elif sys.platform == "darwin":
+        name = "libmbtmap.dylib"
+    else:
+        name = "libmbtmap.so"
+    root = os.path.join(os.path.dirname(os.path.abspath(__file__)), "..")
+    return os.path.join(root, "target", "release", name)
+
+
+def load(path=None):
+    """Shared library at `path`, `$MBTMAP_LIBRARY`, or else the one of a release build of the
+    repository"""
+    path = path or os.environ.get("MBTMAP_LIBRARY") or _library_path()
+    lib = ctypes.CDLL(path)
+    lib.mbtmap_open_map.argtypes = [ctypes.c_char_p, ctypes.c_char_p]
+    lib.mbtmap_open_map.restype = ctypes.c_void_p
+    lib.mbtmap_close.argtypes = [ctypes.c_void_p]
+    lib.mbtmap_close.restype = None
+    # strings returned are taken as pointers to release them once read
+    lib.mbtmap_resolve.argtypes = [
+        ctypes.c_void_p,
+        ctypes.c_uint32,
+        ctypes.POINTER(ctypes.c_uint32),
+        ctypes.POINTER(ctypes.c_uint32),
+    ]
+    lib.mbtmap_resolve.restype = ctypes.c_void_p
+    lib.mbtmap_rewrite.argtypes = [ctypes.c_void_p, ctypes.c_char_p]
+    lib.mbtmap_rewrite.restype = ctypes.c_void_p
+    lib.mbtmap_free_string.argtypes = [ctypes.c_void_p]
+    lib.mbtmap_free_string.restype = None
+    return lib
+
+
+_lib = None
+
+
+def _default():
+    global _lib
+    if _lib is None:
+        _lib = load()
+    return _lib
+
+
+def _take(lib, s):
+    """String returned by the library, released once read"""
+    if not s:
+        return None
+    try:
+        return ctypes.string_at(s).decode()
+    finally:
+        lib.mbtmap_free_string(s)
+
+
+class Resolver:
+    """Source map, or WASM module referring to its source map, for frames in shape of the preset,
+    `None` for the default"""
+
+    def __init__(self, path, preset=None, lib=None):
+        self._handle = None
+        self._lib = lib or _default()
+        self._handle = self._lib.mbtmap_open_map(
+            os.fsencode(path), preset.encode() if preset is not None else None
+        )
+        if not self._handle:
+            raise ValueError(f"failed to open {path} for preset {preset or 'moonbit'}")
+
+    def resolve(self, addr):
+        """`(source, line, column)` of the address, or `None` if it does not resolve"""
+        line, column = ctypes.c_uint32(), ctypes.c_uint32()
+        source = _take(
+            self._lib,
+            self._lib.mbtmap_resolve(
+                self._handle, addr, ctypes.byref(line), ctypes.byref(column)
+            ),
+        )
+        if source is None:
+            return None
+        return source, line.value, column.value
+
+    def rewrite(self, text):
+        """Traceback with source location appended to each frame"""
+        return _take(self._lib, self._lib.mbtmap_rewrite(self._handle, text.encode()))
+
+    def close(self):
+        if self._handle:
+            self._lib.mbtmap_close(self._handle)
+            self._handle = None
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *exc):
+        self.close()
+
+    def __del__(self):
+        self.close()
@@ -0,0 +1,48 @@
+"""Run by `python3 -m unittest discover python` after `cargo build --lib --release --features capi`"""
+
+import json
+import os
+import tempfile
+import unittest
+
+from mbtmap import Resolver
+
+# a mapping of offset 0x10 to `src/main.mbt:2:3`, the third column of the second line
+MAP = {
+    "version": 3,
+    "sources": ["src/main.mbt"],
+    "names": ["main"],
+    "mappings": "gBACEA",
+}
+
+
+class ResolverTest(unittest.TestCase):
+    def setUp(self):
+        fd, self.path = tempfile.mkstemp(suffix=".map")
+        with os.fdopen(fd, "w") as f:
+            json.dump(MAP, f)
+
+    def tearDown(self):
+        os.remove(self.path)
+
+    def test_resolve(self):
+        with Resolver(self.path) as resolver:
+            self.assertEqual(resolver.resolve(0x10), ("src/main.mbt", 2, 3))
+            self.assertIsNone(resolver.resolve(0x8))
+
+    def test_rewrite(self):
+        with Resolver(self.path) as resolver:
+            rewritten = resolver.rewrite(
+                "RuntimeError: unreachable\n"
+                "    at main (wasm://wasm/0001:wasm-function[1]:0x10)\n"
+            )
+        self.assertIn("0x10 src/main.mbt:2:3)", rewritten)
+        self.assertTrue(rewritten.startswith("RuntimeError: unreachable\n"))
+
+    def test_unknown_preset(self):
+        with self.assertRaises(ValueError):
+            Resolver(self.path, "no-such-preset")
+
+
+if __name__ == "__main__":
+    unittest.main()
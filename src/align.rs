@@ -0,0 +1,229 @@
+//! Padding of locations appended to frames so that they start at the same terminal column, by the
+//! width characters take on a terminal rather than their count
+//!
+//! Annotations are inserted after `MARK` in place of a space, replaced here by the padding, held
+//! back until the run of frames ends when aligned to the widest frame among them.
+
+use crate::resolver::MARK;
+use std::borrow::Cow;
+use std::env;
+use std::mem;
+
+/// Column of `--align`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// Past the widest frame of each run of frames
+    Auto,
+    /// After N columns, or past the frame if wider
+    Column(usize),
+}
+
+/// Parse `auto` or the number of columns
+pub fn parse_align(s: &str) -> Result<Align, String> {
+    match s {
+        "auto" => Ok(Align::Auto),
+        _ => s
+            .parse()
+            .map(Align::Column)
+            .map_err(|_| format!("expected auto or a number of columns, found `{s}`")),
+    }
+}
+
+fn in_ranges(c: char, ranges: &[(u32, u32)]) -> bool {
+    let c = c as u32;
+    ranges.iter().any(|&(low, high)| (low..=high).contains(&c))
+}
+
+/// East Asian wide and fullwidth characters, and emoji
+const WIDE: &[(u32, u32)] = &[
+    (0x1100, 0x115f),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xa960, 0xa97f),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe10, 0xfe19),
+    (0xfe30, 0xfe6f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x1f300, 0x1f64f),
+    (0x1f680, 0x1f6ff),
+    (0x1f900, 0x1f9ff),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+];
+
+/// Combining marks, zero-width spaces and joiners, and variation selectors
+const ZERO: &[(u32, u32)] = &[
+    (0x0300, 0x036f),
+    (0x0483, 0x0489),
+    (0x0591, 0x05bd),
+    (0x0610, 0x061a),
+    (0x064b, 0x065f),
+    (0x200b, 0x200f),
+    (0x2028, 0x202e),
+    (0x2060, 0x2064),
+    (0x20d0, 0x20ff),
+    (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+    (0xfeff, 0xfeff),
+    (0xe0100, 0xe01ef),
+];
+
+/// Characters of ambiguous width, taking two columns in CJK locales, such as Greek and Cyrillic
+/// letters, box drawing and circled numbers
+const AMBIGUOUS: &[(u32, u32)] = &[
+    (0x00a7, 0x00a8),
+    (0x00b0, 0x00b1),
+    (0x00b4, 0x00b4),
+    (0x00b6, 0x00b6),
+    (0x00d7, 0x00d7),
+    (0x00f7, 0x00f7),
+    (0x0391, 0x03a9),
+    (0x03b1, 0x03c9),
+    (0x0401, 0x0401),
+    (0x0410, 0x044f),
+    (0x0451, 0x0451),
+    (0x2010, 0x2027),
+    (0x2030, 0x203b),
+    (0x2103, 0x2103),
+    (0x2116, 0x2116),
+    (0x2160, 0x2179),
+    (0x2190, 0x21ff),
+    (0x2200, 0x22ff),
+    (0x2460, 0x24ff),
+    (0x2500, 0x257f),
+    (0x25a0, 0x25ff),
+    (0x2600, 0x26ff),
+    (0xe000, 0xf8ff),
+];
+
+/// Whether the locale of `LC_ALL`, `LC_CTYPE` or `LANG` is Chinese, Japanese or Korean
+pub(crate) fn cjk_locale() -> bool {
+    ["LC_ALL", "LC_CTYPE", "LANG"]
+        .into_iter()
+        .filter_map(|var| env::var(var).ok())
+        .find(|value| !value.is_empty())
+        .is_some_and(|locale| ["zh", "ja", "ko"].iter().any(|cjk| locale.starts_with(cjk)))
+}
+
+/// Columns taken by `text` on a terminal, past escape sequences and tabs to the next stop of 8
+fn width(text: &str, cjk: bool) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => match chars.next() {
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\x07' || c == '\x1b' && chars.next_if_eq(&'\\').is_some() {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\t' => width += 8 - width % 8,
+            c if c.is_control() || in_ranges(c, ZERO) => {}
+            c if in_ranges(c, WIDE) || cjk && in_ranges(c, AMBIGUOUS) => width += 2,
+            _ => width += 1,
+        }
+    }
+    width
+}
+
+/// `text` padded or cut to `columns` columns on a terminal, colors reset if cut
+pub(crate) fn fit(text: &str, columns: usize, cjk: bool) -> String {
+    let mut fitted = Cow::Borrowed(text);
+    if width(text, cjk) > columns {
+        let ends = text
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain([text.len()])
+            .collect::<Vec<_>>();
+        let end = ends[ends.partition_point(|&end| width(&text[..end], cjk) <= columns) - 1];
+        fitted = Cow::Owned(text[..end].to_owned());
+        if fitted.contains('\x1b') {
+            fitted.to_mut().push_str("\x1b[0m");
+        }
+    }
+    let padding = columns.saturating_sub(width(&fitted, cjk));
+    format!("{fitted}{}", " ".repeat(padding))
+}
+
+pub struct Aligner {
+    align: Align,
+    cjk: bool,
+    /// Lines of the current run of frames, held back with `Align::Auto`
+    pending: Vec<String>,
+}
+
+impl Aligner {
+    pub fn new(align: Align) -> Self {
+        Self {
+            align,
+            cjk: cjk_locale(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Line with its mark replaced by the padding to `column`, or by a space each if it carries
+    /// more than one, such as frames escaped in a line of JSON
+    fn pad(&self, line: &str, column: usize) -> String {
+        match line.split_once(MARK) {
+            Some((frame, annotation)) if !annotation.contains(MARK) => {
+                let padding = column.saturating_sub(width(frame, self.cjk)).max(1);
+                format!("{frame}{}{annotation}", " ".repeat(padding))
+            }
+            _ => line.replace(MARK, " "),
+        }
+    }
+
+    fn flush(&mut self, out: &mut String) {
+        let pending = mem::take(&mut self.pending);
+        let column = pending
+            .iter()
+            .filter(|line| line.matches(MARK).count() == 1)
+            .filter_map(|line| line.split_once(MARK))
+            .map(|(frame, _)| width(frame, self.cjk) + 1)
+            .max()
+            .unwrap_or_default();
+        for line in pending {
+            out.push_str(&self.pad(&line, column));
+        }
+    }
+
+    /// Align the frames in `text`, those at its end held back until the run of frames ends
+    pub fn push(&mut self, text: &str, is_frame: impl Fn(&str) -> bool) -> String {
+        let mut out = String::new();
+        for line in text.split_inclusive('\n') {
+            match self.align {
+                Align::Column(column) => out.push_str(&self.pad(line, column)),
+                Align::Auto if line.contains(MARK) || is_frame(line) => {
+                    self.pending.push(line.to_owned())
+                }
+                Align::Auto => {
+                    self.flush(&mut out);
+                    out.push_str(line);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        self.flush(&mut out);
+        out
+    }
+}
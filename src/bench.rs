@@ -0,0 +1,131 @@
+//! Time of loading a map and throughput of looking up addresses in it, reported in the same shape
+//! across builds and maps so that regressions show up by comparing reports
+
+use crate::cache::map_bytes;
+use crate::http::Client;
+use crate::{locate, Maps};
+use eyre::{bail, Result};
+use serde_json::{json, Value};
+use std::fmt;
+use std::fs;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct Report {
+    pub map: String,
+    pub tokens: u32,
+    pub sources: u32,
+    pub load: Duration,
+    /// Estimated memory held by the parsed map
+    pub bytes: usize,
+    /// Peak resident memory of the process, where the platform tells
+    pub peak_rss: Option<usize>,
+    pub addresses: usize,
+    /// Addresses found in a mapping
+    pub found: usize,
+    pub lookups: Duration,
+}
+
+/// Addresses spread over `range` in an order fixed across runs, so that lookups hit the map alike
+fn addresses(range: (u32, u32), n: usize) -> Vec<u32> {
+    let mut state: u64 = 0x9e3779b97f4a7c15;
+    (0..n)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            range.0 + (state % (u64::from(range.1 - range.0) + 1)) as u32
+        })
+        .collect()
+}
+
+/// `VmHWM` of the process on Linux
+fn peak_rss() -> Option<usize> {
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: usize = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb << 10)
+}
+
+/// Load the map and look up `n` addresses across the range it covers, locating each one found
+pub fn run(client: &Client, path: &str, n: usize) -> Result<Report> {
+    let start = Instant::now();
+    let maps = Maps::open(client, path, &[])?;
+    let load = start.elapsed();
+    let map = &maps.default;
+    let (Some(first), Some(last)) = (map.get_token(0), map.tokens().last()) else {
+        bail!("{path} has no mappings");
+    };
+    let addrs = addresses((first.get_dst_col(), last.get_dst_col()), n);
+    let start = Instant::now();
+    let mut found = 0;
+    for &addr in &addrs {
+        if let Some(token) = map.lookup_token(0, addr) {
+            black_box(locate(&token, &None));
+            found += 1;
+        }
+    }
+    let lookups = start.elapsed();
+    Ok(Report {
+        map: path.to_owned(),
+        tokens: map.get_token_count(),
+        sources: map.get_source_count(),
+        load,
+        bytes: map_bytes(map),
+        peak_rss: peak_rss(),
+        addresses: n,
+        found,
+        lookups,
+    })
+}
+
+fn mib(bytes: usize) -> f64 {
+    bytes as f64 / (1 << 20) as f64
+}
+
+impl Report {
+    pub fn lookups_per_second(&self) -> f64 {
+        self.addresses as f64 / self.lookups.as_secs_f64().max(f64::EPSILON)
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "map": self.map,
+            "tokens": self.tokens,
+            "sources": self.sources,
+            "load_ms": self.load.as_secs_f64() * 1e3,
+            "bytes": self.bytes,
+            "peak_rss": self.peak_rss,
+            "addresses": self.addresses,
+            "found": self.found,
+            "lookups_ms": self.lookups.as_secs_f64() * 1e3,
+            "lookups_per_second": self.lookups_per_second(),
+        })
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "map       {}", self.map)?;
+        writeln!(
+            f,
+            "mappings  {} tokens, {} sources",
+            self.tokens, self.sources
+        )?;
+        writeln!(f, "load      {:.3} ms", self.load.as_secs_f64() * 1e3)?;
+        write!(f, "memory    {:.2} MiB estimated", mib(self.bytes))?;
+        if let Some(peak_rss) = self.peak_rss {
+            write!(f, ", {:.2} MiB peak resident", mib(peak_rss))?;
+        }
+        writeln!(f)?;
+        writeln!(
+            f,
+            "lookups   {} in {:.3} ms, {} found, {:.0}/s",
+            self.addresses,
+            self.lookups.as_secs_f64() * 1e3,
+            self.found,
+            self.lookups_per_second()
+        )
+    }
+}
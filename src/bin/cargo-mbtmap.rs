@@ -0,0 +1,83 @@
+use clap::{CommandFactory, FromArgMatches, Parser};
+use eyre::{eyre, Result};
+use mbtmap::discover::find_map;
+use mbtmap::filter::{Filter, FilterArgs};
+use mbtmap::http::Client;
+use mbtmap::Maps;
+use std::env::{self, current_dir};
+use std::io::{BufRead, BufReader, Read};
+use std::process::{self, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Parser, Debug)]
+#[command(bin_name = "cargo")]
+enum Cargo {
+    /// Run cargo and attach source location to WASM traceback in its output
+    Mbtmap(Args),
+}
+
+#[derive(clap::Args, Debug)]
+#[command(version)]
+struct Args {
+    /// Path to source map, default to the most recently built one under `target/` and `pkg/`
+    #[arg(short = 'm', long)]
+    map: Option<String>,
+    #[command(flatten)]
+    filter: FilterArgs,
+    /// Cargo subcommand and its arguments, e.g. `test --target wasm32-unknown-unknown`
+    #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+    cargo: Vec<String>,
+}
+
+fn collect(
+    stream: impl Read + Send + 'static,
+    output: Arc<Mutex<String>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut stream = BufReader::new(stream);
+        let mut buf = String::new();
+        while matches!(stream.read_line(&mut buf), Ok(n) if n > 0) {
+            output.lock().unwrap().push_str(&buf);
+            buf.clear();
+        }
+    })
+}
+
+fn main() -> Result<()> {
+    // Wasm-bindgen is likely what Rust developers use, and -m is taken by --map
+    let command = Cargo::command().mut_subcommand("mbtmap", |command| {
+        command
+            .mut_arg("preset", |arg| arg.default_value("wasm-bindgen"))
+            .mut_arg("only_matching", |arg| arg.short(None))
+    });
+    let Cargo::Mbtmap(args) = Cargo::from_arg_matches(&command.get_matches())?;
+    let mut filter = Filter::new(&args.filter, None)?;
+    let mut child = Command::new(env::var("CARGO").unwrap_or_else(|_| "cargo".to_owned()))
+        .args(&args.cargo)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    let output = Arc::new(Mutex::new(String::new()));
+    let readers = [
+        collect(child.stdout.take().unwrap(), output.clone()),
+        collect(child.stderr.take().unwrap(), output.clone()),
+    ];
+    let status = child.wait()?;
+    for reader in readers {
+        reader.join().unwrap();
+    }
+    let map = match args.map {
+        Some(map) => map,
+        None => find_map(&current_dir()?)
+            .ok_or_else(|| eyre!("no source map found under target/ or pkg/, pass --map"))?
+            .to_string_lossy()
+            .into_owned(),
+    };
+    let mut client = Client::from_env();
+    client.warn = Some(|message| eprintln!("warning: {message}"));
+    let maps = Maps::open(&client, &map, &args.filter.map_for)?;
+    filter.process(&maps, &output.lock().unwrap());
+    filter.finish(&maps)?;
+    process::exit(status.code().unwrap_or(1))
+}
@@ -0,0 +1,203 @@
+//! Symbol bundle, a gzip-compressed JSON document carrying source maps of a release along with
+//! function tables of their WASM modules, debug IDs and metadata
+
+use crate::sidecar::Sidecar;
+use crate::wasm::Module;
+use crate::{gzip, read_source_map, sha256};
+use eyre::{bail, eyre, Result};
+use serde_json::{json, Value};
+use sourcemap::SourceMap;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const VERSION: u64 = 1;
+
+pub struct Entry {
+    /// File name of the source map or WASM module without extensions, such as `app_bg`
+    pub name: String,
+    pub debug_id: Option<String>,
+    /// Digest of the source map or WASM module bundled, to compare with the deployed build
+    pub sha256: String,
+    map: SourceMap,
+    module: Option<Module>,
+}
+
+pub struct Bundle {
+    pub metadata: BTreeMap<String, String>,
+    /// Entries in order given, the first being the default source map
+    pub entries: Vec<Entry>,
+}
+
+pub(crate) fn entry_name(path: &str) -> String {
+    let name = Path::new(path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_owned());
+    let name = name.strip_suffix(".map").unwrap_or(&name);
+    let name = name.strip_suffix(".wasm").unwrap_or(name);
+    name.to_owned()
+}
+
+impl Entry {
+    /// Read source map from a file, or the one a WASM module refers to along with its function table,
+    /// applying its sidecar
+    pub fn open(path: &str) -> Result<Self> {
+        let mut map = read_source_map(path)?;
+        if let Some(sidecar) = Sidecar::find(path)? {
+            map = sidecar.apply(map)?;
+        }
+        let module = if path.ends_with(".wasm") {
+            Some(Module::open(path)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            name: entry_name(path),
+            debug_id: map.get_debug_id().map(|id| id.to_string()),
+            sha256: sha256::hex(&fs::read(path)?),
+            map,
+            module,
+        })
+    }
+
+    fn to_json(&self) -> Result<Value> {
+        let mut map = Vec::new();
+        self.map.to_writer(&mut map)?;
+        let map = serde_json::from_slice::<Value>(&map)?;
+        Ok(json!({
+            "name": self.name,
+            "debug_id": self.debug_id,
+            "sha256": self.sha256,
+            "map_sha256": sha256::hex(&serde_json::to_vec(&map)?),
+            "map": map,
+            "module": self.module.as_ref().map(Module::to_json),
+        }))
+    }
+
+    fn from_json(value: &Value) -> Result<Self> {
+        let malformed = || eyre!("malformed entry in symbol bundle");
+        let name = value["name"].as_str().ok_or_else(malformed)?;
+        let map = serde_json::to_vec(&value["map"])?;
+        if value["map_sha256"].as_str() != Some(&sha256::hex(&map)) {
+            bail!("source map of {name} in symbol bundle is corrupted");
+        }
+        Ok(Self {
+            name: name.to_owned(),
+            debug_id: value["debug_id"].as_str().map(str::to_owned),
+            sha256: value["sha256"].as_str().ok_or_else(malformed)?.to_owned(),
+            map: SourceMap::from_slice(&map)?,
+            module: match &value["module"] {
+                Value::Null => None,
+                module => Some(Module::from_json(module)?),
+            },
+        })
+    }
+}
+
+impl Bundle {
+    /// Bundle the source maps or WASM modules, with `created`, `generator` and the release of their
+    /// first sidecar declaring one prepended to metadata
+    pub fn create(paths: &[String], metadata: &[(String, String)]) -> Result<Self> {
+        let mut entries: Vec<Entry> = Vec::new();
+        for path in paths {
+            let entry = Entry::open(path)?;
+            if entries.iter().any(|e| e.name == entry.name) {
+                bail!("duplicate entry {} in symbol bundle", entry.name);
+            }
+            entries.push(entry);
+        }
+        let created = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        let mut bundle = Self {
+            metadata: BTreeMap::from([
+                ("created".to_owned(), created.to_string()),
+                (
+                    "generator".to_owned(),
+                    format!("mbtmap {}", env!("CARGO_PKG_VERSION")),
+                ),
+            ]),
+            entries,
+        };
+        for path in paths {
+            if let Some(release) = Sidecar::find(path)?.and_then(|sidecar| sidecar.release) {
+                bundle.metadata.insert("release".to_owned(), release);
+                break;
+            }
+        }
+        bundle.metadata.extend(metadata.iter().cloned());
+        Ok(bundle)
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        Self::parse(&fs::read(path)?, path)
+    }
+
+    /// Parse the bundle read from `path`, optionally compressed
+    pub fn parse(data: &[u8], path: &str) -> Result<Self> {
+        let data = if gzip::is_gzip(data) {
+            &gzip::decompress(data)?
+        } else {
+            data
+        };
+        let value: Value = serde_json::from_slice(data)?;
+        if value["version"].as_u64() != Some(VERSION) {
+            bail!("unsupported symbol bundle version in {path}");
+        }
+        let metadata = value["metadata"]
+            .as_object()
+            .map(|metadata| {
+                metadata
+                    .iter()
+                    .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_owned())))
+                    .collect()
+            })
+            .unwrap_or_default();
+        let entries = value["entries"]
+            .as_array()
+            .ok_or_else(|| eyre!("malformed symbol bundle {path}"))?
+            .iter()
+            .map(Entry::from_json)
+            .collect::<Result<Vec<_>>>()?;
+        if entries.is_empty() {
+            bail!("symbol bundle {path} has no entries");
+        }
+        Ok(Self { metadata, entries })
+    }
+
+    pub fn write(&self, path: &str) -> Result<()> {
+        let value = json!({
+            "version": VERSION,
+            "metadata": self.metadata,
+            "entries": self.entries.iter().map(Entry::to_json).collect::<Result<Vec<_>>>()?,
+        });
+        fs::write(path, gzip::compress(&serde_json::to_vec(&value)?))?;
+        Ok(())
+    }
+
+    /// Function table of the default entry
+    pub fn module(&self) -> Option<&Module> {
+        self.entries[0].module.as_ref()
+    }
+
+    /// The default source map, and the others routed by entry name or, overriding them, by
+    /// `--map-for`
+    #[cfg(feature = "cli")]
+    pub fn into_maps(
+        self,
+        client: &crate::http::Client,
+        scripts: &[(String, String)],
+    ) -> Result<crate::Maps> {
+        let mut entries = self.entries.into_iter();
+        let default = entries.next().unwrap().map;
+        let mut maps = crate::Maps {
+            default,
+            scripts: entries.map(|entry| (entry.name, entry.map)).collect(),
+        };
+        for (script, path) in scripts {
+            maps.scripts
+                .insert(script.clone(), client.source_map(path)?);
+        }
+        Ok(maps)
+    }
+}
@@ -0,0 +1,144 @@
+//! Maps shared by the connections of a long-running process, loaded on first use and evicted least
+//! recently used first to stay within a memory budget
+
+use crate::Maps;
+use eyre::Result;
+use sourcemap::{RawToken, SourceMap};
+use std::collections::HashMap;
+use std::mem;
+use std::sync::{Arc, Mutex};
+
+/// Counts of lookups and evictions since the cache was created
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+    /// Estimated memory of the maps held
+    pub bytes: usize,
+}
+
+struct Entry {
+    maps: Arc<Maps>,
+    bytes: usize,
+    /// Tick of the last lookup of the entry
+    used: u64,
+}
+
+#[derive(Default)]
+struct Inner {
+    entries: HashMap<String, Entry>,
+    tick: u64,
+    stats: Stats,
+}
+
+/// Maps by key, such as release version, shared as long as any user holds them even once evicted
+pub struct Cache {
+    budget: usize,
+    inner: Mutex<Inner>,
+}
+
+/// Parse a size in bytes, with an optional suffix of `K`, `M` or `G` in powers of 1024
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let (n, shift) = match s.trim_end_matches(['B', 'b']).to_ascii_uppercase() {
+        n if n.ends_with('K') => (n[..n.len() - 1].to_owned(), 10),
+        n if n.ends_with('M') => (n[..n.len() - 1].to_owned(), 20),
+        n if n.ends_with('G') => (n[..n.len() - 1].to_owned(), 30),
+        n => (n, 0),
+    };
+    n.parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(1 << shift))
+        .ok_or_else(|| format!("expected size such as 512M, found `{s}`"))
+}
+
+/// Rough memory held by a parsed map
+pub(crate) fn map_bytes(map: &SourceMap) -> usize {
+    let mut bytes = map.get_token_count() as usize * mem::size_of::<RawToken>();
+    for i in 0..map.get_source_count() {
+        bytes += map.get_source(i).map_or(0, str::len);
+        bytes += map.get_source_contents(i).map_or(0, str::len);
+    }
+    for i in 0..map.get_name_count() {
+        bytes += map.get_name(i).map_or(0, str::len);
+    }
+    bytes
+}
+
+fn maps_bytes(maps: &Maps) -> usize {
+    map_bytes(&maps.default) + maps.scripts.values().map(map_bytes).sum::<usize>()
+}
+
+impl Cache {
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            inner: Mutex::default(),
+        }
+    }
+
+    /// Maps of the key, loaded by `load` unless held, outside the lock so that other lookups
+    /// are not blocked, then evicting others until within budget
+    pub fn get(&self, key: &str, load: impl FnOnce() -> Result<Maps>) -> Result<Arc<Maps>> {
+        {
+            let mut inner = self.inner.lock().unwrap();
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some(entry) = inner.entries.get_mut(key) {
+                entry.used = tick;
+                let maps = entry.maps.clone();
+                inner.stats.hits += 1;
+                return Ok(maps);
+            }
+            inner.stats.misses += 1;
+        }
+        Ok(self.insert(key, load()?).0)
+    }
+
+    /// Maps of the key if held, not counted among the lookups loading on a miss
+    pub fn peek(&self, key: &str) -> Option<Arc<Maps>> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.entries.get_mut(key)?;
+        entry.used = tick;
+        Some(entry.maps.clone())
+    }
+
+    /// Hold `maps` for the key in place of those held if any, telling whether they are replaced,
+    /// then evict others until within budget
+    pub fn insert(&self, key: &str, maps: Maps) -> (Arc<Maps>, bool) {
+        let maps = Arc::new(maps);
+        let bytes = maps_bytes(&maps);
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let entry = Entry {
+            maps: maps.clone(),
+            bytes,
+            used: inner.tick,
+        };
+        // such as loaded meanwhile by another lookup of the key
+        let previous = inner.entries.insert(key.to_owned(), entry);
+        if let Some(previous) = &previous {
+            inner.stats.bytes -= previous.bytes;
+        }
+        inner.stats.bytes += bytes;
+        while inner.stats.bytes > self.budget && inner.entries.len() > 1 {
+            let oldest = inner
+                .entries
+                .iter()
+                .filter(|(k, _)| *k != key)
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(k, _)| k.clone())
+                .unwrap();
+            let evicted = inner.entries.remove(&oldest).unwrap();
+            inner.stats.bytes -= evicted.bytes;
+            inner.stats.evictions += 1;
+        }
+        (maps, previous.is_some())
+    }
+
+    pub fn stats(&self) -> Stats {
+        self.inner.lock().unwrap().stats
+    }
+}
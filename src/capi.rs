@@ -0,0 +1,107 @@
+//! C ABI over [`Resolver`], declared in `include/mbtmap.h`
+//!
+//! Strings returned are owned by the caller and released by [`mbtmap_free_string`], and
+//! resolvers by [`mbtmap_close`].
+
+pub use crate::ffi::mbtmap_close;
+
+use crate::preset::Preset;
+use crate::resolver::Resolver;
+use std::ffi::{c_char, CStr, CString};
+use std::ptr;
+
+unsafe fn str<'a>(s: *const c_char) -> Option<&'a str> {
+    if s.is_null() {
+        return None;
+    }
+    CStr::from_ptr(s).to_str().ok()
+}
+
+fn into_raw(s: String) -> *mut c_char {
+    CString::new(s.replace('\0', "")).unwrap().into_raw()
+}
+
+/// Open the source map, or WASM module referring to its source map, for frames in shape of the preset
+///
+/// `preset` may be null for the default. Null is returned if the source map can not be read.
+///
+/// # Safety
+///
+/// `path` and `preset` must be null or valid NUL-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_open_map(
+    path: *const c_char,
+    preset: *const c_char,
+) -> *mut Resolver {
+    let Some(path) = str(path) else {
+        return ptr::null_mut();
+    };
+    let preset = match str(preset) {
+        Some(preset) => match preset.parse::<Preset>() {
+            Ok(preset) => preset,
+            Err(_) => return ptr::null_mut(),
+        },
+        None => Preset::default(),
+    };
+    match Resolver::open(path, preset) {
+        Ok(resolver) => Box::into_raw(Box::new(resolver)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Source file of the address, with line and column written through the pointers
+///
+/// Null is returned if the address does not resolve.
+///
+/// # Safety
+///
+/// `resolver` must be returned by [`mbtmap_open_map`], `line` and `column` must be null or valid.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_resolve(
+    resolver: *const Resolver,
+    addr: u32,
+    line: *mut u32,
+    column: *mut u32,
+) -> *mut c_char {
+    let Some(location) = resolver
+        .as_ref()
+        .and_then(|resolver| resolver.resolve(addr))
+    else {
+        return ptr::null_mut();
+    };
+    if !line.is_null() {
+        *line = location.line;
+    }
+    if !column.is_null() {
+        *column = location.column;
+    }
+    into_raw(location.source)
+}
+
+/// Traceback with source location appended to each frame
+///
+/// # Safety
+///
+/// `resolver` must be returned by [`mbtmap_open_map`], `text` must be a valid NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_rewrite(
+    resolver: *const Resolver,
+    text: *const c_char,
+) -> *mut c_char {
+    let (Some(resolver), Some(text)) = (resolver.as_ref(), str(text)) else {
+        return ptr::null_mut();
+    };
+    into_raw(resolver.rewrite(text))
+}
+
+/// Release the string returned
+///
+/// # Safety
+///
+/// `s` must be null or returned by this library and not yet released.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_free_string(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
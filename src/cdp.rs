@@ -0,0 +1,275 @@
+//! Chrome or Node.js inspector targets through the Chrome DevTools Protocol, for their exceptions
+//! and console messages, each printed as its message followed by its traceback, and for setting
+//! breakpoints in their WASM modules
+
+use crate::http::{self, Client};
+use crate::websocket::{self, Message};
+use eyre::{bail, eyre, Result};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::io::BufReader;
+use std::net::TcpStream;
+
+/// Connection to a target exchanging messages of the protocol
+#[derive(Debug)]
+struct Session {
+    reader: BufReader<TcpStream>,
+    stream: TcpStream,
+    /// Fragments of the message being read, kept across pings between them
+    fragments: Vec<u8>,
+    /// ID of the next request
+    id: u64,
+}
+
+#[derive(Debug)]
+pub struct Cdp {
+    session: Session,
+    /// Lines of the last event not read yet
+    lines: VecDeque<String>,
+}
+
+/// Debugger of a target, whose breakpoints last as long as it is attached
+#[derive(Debug)]
+pub struct Debugger {
+    session: Session,
+    /// ID and URL of each WASM module parsed by the target
+    modules: Vec<(String, String)>,
+}
+
+/// WebSocket URL of the first page or Node.js target listed by the inspector at `url`
+fn discover(client: &Client, url: &str) -> Result<String> {
+    let list = url::Url::parse(url)?.join("/json/list")?;
+    let targets: Value = serde_json::from_slice(&client.read(list.as_str())?)?;
+    targets
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|target| matches!(target["type"].as_str(), Some("page" | "node")))
+        .find_map(|target| target["webSocketDebuggerUrl"].as_str())
+        .map(str::to_owned)
+        .ok_or_else(|| eyre!("no inspectable target at {url}"))
+}
+
+/// Text of a remote object as `console.log` prints it
+fn remote_object(object: &Value) -> String {
+    match (&object["value"], object["description"].as_str()) {
+        (Value::String(value), _) => value.clone(),
+        (_, Some(description)) => description.to_owned(),
+        (Value::Null, None) => object["type"].as_str().unwrap_or_default().to_owned(),
+        (value, None) => value.to_string(),
+    }
+}
+
+/// Lines of a stack trace as V8 prints them, WASM frames with the byte offset in the module
+fn stack_trace(stack: &Value) -> String {
+    let mut trace = String::new();
+    for frame in stack["callFrames"].as_array().into_iter().flatten() {
+        let name = frame["functionName"].as_str().unwrap_or_default();
+        let name = if name.is_empty() { "<anonymous>" } else { name };
+        let url = frame["url"].as_str().unwrap_or_default();
+        let line = frame["lineNumber"].as_u64().unwrap_or_default() + 1;
+        let column = frame["columnNumber"].as_u64().unwrap_or_default();
+        if url.starts_with("wasm://") {
+            trace.push_str(&format!("\n    at {name} ({url}:{line}:{column:#x})"));
+        } else {
+            trace.push_str(&format!("\n    at {name} ({url}:{line}:{})", column + 1));
+        }
+    }
+    trace
+}
+
+/// Text printed for an event, or `None` if not an exception or console message
+fn event(message: &Value) -> Option<String> {
+    let params = &message["params"];
+    match message["method"].as_str()? {
+        "Runtime.exceptionThrown" => {
+            let details = &params["exceptionDetails"];
+            let text = details["text"].as_str().unwrap_or("Uncaught");
+            Some(match details["exception"]["description"].as_str() {
+                Some(description) => format!("{text} {description}"),
+                None => format!("{text}{}", stack_trace(&details["stackTrace"])),
+            })
+        }
+        "Runtime.consoleAPICalled" => {
+            let args = params["args"].as_array().into_iter().flatten();
+            let mut text = args.map(remote_object).collect::<Vec<_>>().join(" ");
+            let kind = params["type"].as_str().unwrap_or_default();
+            // errors logged have their own traceback in their description
+            if matches!(kind, "error" | "trace" | "assert") && !text.contains("\n    at ") {
+                text.push_str(&stack_trace(&params["stackTrace"]));
+            }
+            Some(text)
+        }
+        _ => None,
+    }
+}
+
+impl Session {
+    /// Connect to the target at a `ws://` URL, or the first target of the inspector at an
+    /// `http://` one
+    fn open(client: &Client, target: &str) -> Result<Self> {
+        let target = if http::is_url(target) {
+            discover(client, target)?
+        } else {
+            target.to_owned()
+        };
+        let (reader, stream) = websocket::connect(&target)?;
+        Ok(Self {
+            reader,
+            stream,
+            fragments: Vec::new(),
+            id: 1,
+        })
+    }
+
+    /// Send a request, returning its ID
+    fn send(&mut self, method: &str, params: Value) -> Result<u64> {
+        let id = self.id;
+        self.id += 1;
+        let request = json!({ "id": id, "method": method, "params": params }).to_string();
+        websocket::write_frame(&mut self.stream, 0x1, request.as_bytes(), true)?;
+        Ok(id)
+    }
+
+    /// Next response or event, or `None` once the target is gone
+    fn recv(&mut self) -> Result<Option<Value>> {
+        loop {
+            match websocket::read_message(&mut self.reader, &mut self.fragments)? {
+                None | Some(Message::Close) => return Ok(None),
+                Some(Message::Ping(payload)) => {
+                    websocket::write_frame(&mut self.stream, 0xa, &payload, true)?
+                }
+                Some(Message::Text(text)) => {
+                    let message: Value = serde_json::from_str(&text)?;
+                    if let Some(error) = message.get("error") {
+                        bail!(
+                            "inspector: {}",
+                            error["message"].as_str().unwrap_or_default()
+                        );
+                    }
+                    return Ok(Some(message));
+                }
+            }
+        }
+    }
+
+    /// Send a request and wait for its result, calling `on_event` with each event meanwhile
+    fn call(
+        &mut self,
+        method: &str,
+        params: Value,
+        mut on_event: impl FnMut(&Value),
+    ) -> Result<Value> {
+        let id = self.send(method, params)?;
+        loop {
+            let Some(message) = self.recv()? else {
+                bail!("target gone before responding to {method}");
+            };
+            if message["id"].as_u64() == Some(id) {
+                return Ok(message["result"].clone());
+            }
+            on_event(&message);
+        }
+    }
+}
+
+impl Cdp {
+    /// Connect to the target at a `ws://` URL, or the first target of the inspector at an
+    /// `http://` one, and subscribe to its exceptions and console messages
+    pub fn connect(client: &Client, target: &str) -> Result<Self> {
+        let mut session = Session::open(client, target)?;
+        session.send("Runtime.enable", json!({}))?;
+        session.send("Runtime.runIfWaitingForDebugger", json!({}))?;
+        Ok(Self {
+            session,
+            lines: VecDeque::new(),
+        })
+    }
+
+    /// Append the next line to `buf`, returning its length or 0 once the target is gone
+    pub fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                buf.push_str(&line);
+                return Ok(line.len());
+            }
+            let Some(message) = self.session.recv()? else {
+                return Ok(0);
+            };
+            if let Some(event) = event(&message) {
+                self.lines
+                    .extend(event.lines().map(|line| format!("{line}\n")));
+            }
+        }
+    }
+}
+
+impl Debugger {
+    /// Attach to the target at a `ws://` URL, or the first target of the inspector at an `http://`
+    /// one, learning the WASM modules it has parsed so far
+    pub fn attach(client: &Client, target: &str) -> Result<Self> {
+        let mut session = Session::open(client, target)?;
+        let mut modules = Vec::new();
+        session.call("Debugger.enable", json!({}), |message| {
+            let script = &message["params"];
+            if message["method"] == "Debugger.scriptParsed"
+                && (script["scriptLanguage"] == "WebAssembly"
+                    || script["url"]
+                        .as_str()
+                        .is_some_and(|url| url.starts_with("wasm://")))
+            {
+                let id = script["scriptId"].as_str().unwrap_or_default().to_owned();
+                let url = script["url"].as_str().unwrap_or_default().to_owned();
+                modules.push((id, url));
+            }
+        })?;
+        if modules.is_empty() {
+            bail!("no WASM module parsed by the target yet");
+        }
+        Ok(Self { session, modules })
+    }
+
+    /// Set a breakpoint at the offset in each WASM module, returning the URL of each module and
+    /// the ID of its breakpoint
+    pub fn set_breakpoint(&mut self, offset: u32) -> Result<Vec<(String, String)>> {
+        let mut set = Vec::new();
+        for (id, url) in &self.modules {
+            let location = json!({ "scriptId": id, "lineNumber": 0, "columnNumber": offset });
+            let result = self.session.call(
+                "Debugger.setBreakpoint",
+                json!({ "location": location }),
+                |_| {},
+            )?;
+            let breakpoint = result["breakpointId"].as_str().unwrap_or_default();
+            set.push((url.clone(), breakpoint.to_owned()));
+        }
+        Ok(set)
+    }
+
+    /// Wait for the next pause, returning the URL and offset where the top frame is paused, or
+    /// `None` once the target is gone
+    pub fn pause(&mut self) -> Result<Option<(String, u32)>> {
+        loop {
+            let Some(message) = self.session.recv()? else {
+                return Ok(None);
+            };
+            if message["method"] != "Debugger.paused" {
+                continue;
+            }
+            let frame = &message["params"]["callFrames"][0];
+            let script = frame["location"]["scriptId"].as_str();
+            let url = match self
+                .modules
+                .iter()
+                .find(|(id, _)| Some(id.as_str()) == script)
+            {
+                Some((_, url)) => url.clone(),
+                None => frame["url"].as_str().unwrap_or_default().to_owned(),
+            };
+            let offset = frame["location"]["columnNumber"]
+                .as_u64()
+                .unwrap_or_default();
+            return Ok(Some((url, offset as u32)));
+        }
+    }
+}
@@ -0,0 +1,91 @@
+//! System clipboard through the command line tools of each platform
+
+use eyre::{bail, eyre, Result};
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+/// Candidate commands of reading or writing the clipboard, the first one installed taken
+fn tools(write: bool) -> Vec<&'static [&'static str]> {
+    if cfg!(target_os = "macos") {
+        return vec![if write { &["pbcopy"] } else { &["pbpaste"] }];
+    }
+    if cfg!(windows) {
+        return vec![if write {
+            &[
+                "powershell",
+                "-NoProfile",
+                "-Command",
+                "$input | Set-Clipboard",
+            ]
+        } else {
+            &["powershell", "-NoProfile", "-Command", "Get-Clipboard -Raw"]
+        }];
+    }
+    let mut tools: Vec<&'static [&'static str]> = Vec::new();
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        tools.push(if write {
+            &["wl-copy"]
+        } else {
+            &["wl-paste", "--no-newline"]
+        });
+    }
+    tools.push(if write {
+        &["xclip", "-selection", "clipboard", "-in"]
+    } else {
+        &["xclip", "-selection", "clipboard", "-out"]
+    });
+    tools.push(if write {
+        &["xsel", "--clipboard", "--input"]
+    } else {
+        &["xsel", "--clipboard", "--output"]
+    });
+    tools
+}
+
+fn not_found() -> eyre::Report {
+    eyre!("no clipboard tool found, install wl-clipboard, xclip or xsel")
+}
+
+/// Text on the clipboard
+pub fn read() -> Result<String> {
+    for tool in tools(false) {
+        let output = match Command::new(tool[0])
+            .args(&tool[1..])
+            .stderr(Stdio::inherit())
+            .output()
+        {
+            Ok(output) => output,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => bail!("failed to run {}: {e}", tool[0]),
+        };
+        if !output.status.success() {
+            bail!("{} exited with {}", tool[0], output.status);
+        }
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    Err(not_found())
+}
+
+/// Put the text on the clipboard
+pub fn write(text: &str) -> Result<()> {
+    for tool in tools(true) {
+        let mut child = match Command::new(tool[0])
+            .args(&tool[1..])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+        {
+            Ok(child) => child,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => bail!("failed to run {}: {e}", tool[0]),
+        };
+        child.stdin.take().unwrap().write_all(text.as_bytes())?;
+        let status = child.wait()?;
+        if !status.success() {
+            bail!("{} exited with {status}", tool[0]);
+        }
+        return Ok(());
+    }
+    Err(not_found())
+}
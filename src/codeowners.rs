@@ -0,0 +1,89 @@
+//! Owners of resolved sources by the `CODEOWNERS` file of their repository, the last pattern
+//! matching a path taking effect as on GitHub and GitLab
+
+use crate::git::source_path;
+use eyre::{eyre, Result};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub struct CodeOwners {
+    /// Repository root the patterns are relative to
+    root: PathBuf,
+    rules: Vec<(Regex, Vec<String>)>,
+}
+
+/// Regex of a gitignore-style pattern, matching the path relative to the root and anything under
+/// a directory it matches
+fn pattern(pattern: &str) -> Result<Regex> {
+    let anchored = pattern.starts_with('/') || pattern.trim_end_matches('/').contains('/');
+    let dir = pattern.ends_with('/');
+    let pattern = pattern.trim_matches('/');
+    let mut re = String::from(if anchored { "^" } else { "^(?:.*/)?" });
+    let mut rest = pattern;
+    while let Some(c) = rest.chars().next() {
+        let (part, len) = if let Some(after) = rest.strip_prefix("**/") {
+            ("(?:.*/)?".to_owned(), rest.len() - after.len())
+        } else if rest.starts_with("**") {
+            (".*".to_owned(), 2)
+        } else if c == '*' {
+            ("[^/]*".to_owned(), 1)
+        } else if c == '?' {
+            ("[^/]".to_owned(), 1)
+        } else {
+            (regex::escape(&c.to_string()), c.len_utf8())
+        };
+        re.push_str(&part);
+        rest = &rest[len..];
+    }
+    re.push_str(if dir { "/.*$" } else { "(?:/.*)?$" });
+    Ok(Regex::new(&re)?)
+}
+
+impl CodeOwners {
+    /// Rules of the file, taken relative to its directory, or the parent of `.github/` or `docs/`
+    pub fn open(path: &str) -> Result<Self> {
+        let text = fs::read_to_string(path).map_err(|e| eyre!("failed to read {path}: {e}"))?;
+        let path = std::path::absolute(path)?;
+        let mut root = path.parent().unwrap_or(Path::new("/"));
+        if root.ends_with(".github") || root.ends_with("docs") {
+            root = root.parent().unwrap_or(root);
+        }
+        let mut rules = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let re = pattern(fields.next().unwrap())
+                .map_err(|e| eyre!("{}:{}: {e}", path.display(), i + 1))?;
+            let owners = fields
+                .take_while(|field| !field.starts_with('#'))
+                .map(str::to_owned)
+                .collect();
+            rules.push((re, owners));
+        }
+        Ok(Self {
+            root: root.to_owned(),
+            rules,
+        })
+    }
+
+    /// Owners of the source, taken relative to `cwd` or the current directory, empty if it is
+    /// outside the repository or owned by none
+    pub fn owners(&self, source: &str, cwd: &Option<PathBuf>) -> &[String] {
+        let Some(path) = source_path(source, cwd) else {
+            return &[];
+        };
+        let Some(path) = path.strip_prefix(&self.root).ok().and_then(Path::to_str) else {
+            return &[];
+        };
+        let path = path.replace('\\', "/");
+        self.rules
+            .iter()
+            .rev()
+            .find(|(re, _)| re.is_match(&path))
+            .map_or(&[], |(_, owners)| owners)
+    }
+}
@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::mem;
+
+/// Line of output, telling how it relates to the context kept by `--trace-context`
+pub enum Line {
+    /// Line printed, such as a frame resolved, around which context is kept
+    Kept(String),
+    /// Line left out by `--only-matching` or `--drop-outside`, unless near a line kept
+    Suppressed(String),
+    /// Line matching `--trace-delimiter` that starts a traceback, otherwise suppressed, printed
+    /// along with the lines up to the first frame of the traceback and the context before it
+    Header(String),
+}
+
+/// Lines left out kept around tracebacks like `grep --context`, with `--` where lines are skipped
+///
+/// Context is kept before the first line of each traceback, its header matching
+/// `--trace-delimiter`, or else the line leading its first frame, and after its last frame.
+pub struct Context {
+    lines: usize,
+    before: VecDeque<String>,
+    /// Lines of `before` from the header of the current traceback on, held until its first frame
+    header: Option<usize>,
+    /// Whether no frame has been printed since the current traceback started
+    starting: bool,
+    /// Lines to print after the last line kept
+    after: usize,
+    /// Whether lines have been skipped since the last line printed
+    skipped: bool,
+    printed: bool,
+}
+
+impl Context {
+    pub fn new(lines: usize) -> Self {
+        Self {
+            lines,
+            before: VecDeque::new(),
+            header: None,
+            starting: true,
+            after: 0,
+            skipped: false,
+            printed: false,
+        }
+    }
+
+    fn print(&mut self, out: &mut String, line: &str) {
+        if self.skipped && self.printed {
+            out.push_str("--\n");
+        }
+        self.skipped = false;
+        self.printed = true;
+        out.push_str(line);
+    }
+
+    /// Leave out the lines before the last `keep` held
+    fn trim(&mut self, keep: usize) {
+        while self.before.len() > keep {
+            self.before.pop_front();
+            self.skipped = true;
+        }
+    }
+
+    fn hold(&mut self, line: String) {
+        self.before.push_back(line);
+        let lead = match &mut self.header {
+            Some(header) => {
+                *header += 1;
+                *header
+            }
+            // the line leading the first frame, unknown until the frame is read
+            None => 1,
+        };
+        self.trim(self.lines + lead);
+    }
+
+    pub fn push(&mut self, line: Line) -> String {
+        let mut out = String::new();
+        match line {
+            Line::Kept(line) => {
+                // a traceback starts past lines skipped even without a header
+                if !self.starting && !self.skipped && self.header.is_none() {
+                    self.trim(self.lines);
+                }
+                for before in mem::take(&mut self.before) {
+                    self.print(&mut out, &before);
+                }
+                self.print(&mut out, &line);
+                self.header = None;
+                self.starting = false;
+                self.after = self.lines;
+            }
+            Line::Suppressed(line) | Line::Header(line) if self.after > 0 => {
+                self.after -= 1;
+                self.print(&mut out, &line);
+            }
+            Line::Suppressed(line) => self.hold(line),
+            Line::Header(line) => {
+                self.header = Some(0);
+                self.hold(line);
+            }
+        }
+        out
+    }
+
+    /// End the current traceback
+    pub fn end(&mut self) {
+        if self.header.take().is_some() {
+            self.trim(self.lines + 1);
+        }
+        self.starting = true;
+    }
+}
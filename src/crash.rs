@@ -0,0 +1,122 @@
+//! Crash reports posted by apps in a format cheap to emit from WASM, turned into symbolicated
+//! reports without DWARF or text tracebacks on the client
+//!
+//! A report is `{"module": "app.wasm", "addrs": [156813, "0x2a182"], "message": "...", "meta":
+//! {...}}`, with all but `addrs` optional, the addresses given innermost first as numbers or
+//! strings in hex with `0x` prefix, and `module` routing them to a map as `--map-for` does.
+
+use crate::{locate, parse_addr, Maps};
+use eyre::{bail, eyre, Result};
+use serde_json::{json, Map, Value};
+use std::fmt::Write;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub struct Crash {
+    pub module: Option<String>,
+    pub addrs: Vec<u32>,
+    pub message: Option<String>,
+    pub meta: Map<String, Value>,
+}
+
+impl Crash {
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        let value: Value =
+            serde_json::from_slice(data).map_err(|e| eyre!("invalid crash report: {e}"))?;
+        let Some(addrs) = value["addrs"].as_array() else {
+            bail!("crash report without addrs");
+        };
+        let addrs = addrs
+            .iter()
+            .map(|addr| {
+                let parsed = match addr {
+                    Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+                    Value::String(s) => parse_addr(s),
+                    _ => None,
+                };
+                parsed.ok_or_else(|| eyre!("invalid address {addr} in crash report"))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            module: value["module"].as_str().map(str::to_owned),
+            addrs,
+            message: value["message"].as_str().map(str::to_owned),
+            meta: value["meta"].as_object().cloned().unwrap_or_default(),
+        })
+    }
+
+    /// The report along with the frame of each address, those not covered by the map left
+    /// unresolved, sources taken relative to `cwd` if given
+    pub fn symbolicate(&self, maps: &Maps, cwd: &Option<PathBuf>) -> Value {
+        let map = maps.get(self.module.as_deref());
+        let frames = self
+            .addrs
+            .iter()
+            .enumerate()
+            .map(|(index, &addr)| {
+                let mut frame = json!({ "index": index, "address": format!("{addr:#x}") });
+                if let Some(token) = map.lookup_token(0, addr) {
+                    let location = locate(&token, cwd);
+                    frame["source"] = json!(location.source);
+                    frame["line"] = json!(location.line);
+                    frame["column"] = json!(location.column);
+                    frame["name"] = json!(token.get_name());
+                }
+                frame
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "message": self.message,
+            "module": self.module,
+            "meta": self.meta,
+            "frames": frames,
+        })
+    }
+}
+
+/// Frames of a symbolicated report resolved
+pub fn resolved(report: &Value) -> usize {
+    report["frames"].as_array().map_or(0, |frames| {
+        frames
+            .iter()
+            .filter(|frame| frame["source"].is_string())
+            .count()
+    })
+}
+
+/// A symbolicated report as text, the message and metadata followed by a numbered frame per line
+pub fn render_text(report: &Value) -> String {
+    let mut out = String::new();
+    let message = report["message"].as_str().unwrap_or("crash");
+    match report["module"].as_str() {
+        Some(module) => writeln!(out, "{message} in {module}").unwrap(),
+        None => writeln!(out, "{message}").unwrap(),
+    }
+    for (key, value) in report["meta"].as_object().into_iter().flatten() {
+        match value.as_str() {
+            Some(value) => writeln!(out, "  {key}: {value}").unwrap(),
+            None => writeln!(out, "  {key}: {value}").unwrap(),
+        }
+    }
+    for frame in report["frames"].as_array().into_iter().flatten() {
+        let (index, address) = (
+            &frame["index"],
+            frame["address"].as_str().unwrap_or_default(),
+        );
+        let Some(source) = frame["source"].as_str() else {
+            writeln!(out, "    #{index} {address} ??").unwrap();
+            continue;
+        };
+        write!(
+            out,
+            "    #{index} {address} {source}:{}:{}",
+            frame["line"], frame["column"]
+        )
+        .unwrap();
+        if let Some(name) = frame["name"].as_str() {
+            write!(out, " [func: {name}]").unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
@@ -0,0 +1,147 @@
+//! Resident process holding parsed maps, serving invocations forwarded by clients over a Unix socket
+//!
+//! A client sends its arguments, working directory and input as a line of JSON, and is sent back a
+//! line of JSON for each piece of output, telling whether it goes to stdout, followed by a last
+//! line with the error if it failed.
+
+use eyre::{bail, eyre, Result};
+use serde_json::{json, Value};
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::thread;
+
+/// Output queued for the connection before the filter waits for it to be written
+const QUEUE: usize = 4096;
+
+/// Invocation forwarded by a client
+#[derive(Debug)]
+pub struct Request {
+    pub args: Vec<String>,
+    pub cwd: PathBuf,
+    /// Traceback read by the client, or `None` if read from the input given in `args`
+    pub input: Option<String>,
+}
+
+/// Socket in `$XDG_RUNTIME_DIR`, or in the temporary directory named after the user
+pub fn default_socket() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => PathBuf::from(dir).join("mbtmap.sock"),
+        None => env::temp_dir().join(format!(
+            "mbtmap-{}.sock",
+            env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+/// Forward the request to the daemon, printing its output, or return `None` if no daemon runs
+pub fn forward(socket: &Path, request: &Request) -> Result<Option<()>> {
+    let mut stream = match UnixStream::connect(socket) {
+        Ok(stream) => stream,
+        Err(e)
+            if matches!(
+                e.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::ConnectionRefused
+            ) =>
+        {
+            return Ok(None)
+        }
+        Err(e) => bail!("failed to connect to daemon at {}: {e}", socket.display()),
+    };
+    let line = json!({
+        "args": request.args,
+        "cwd": request.cwd,
+        "input": request.input,
+    });
+    writeln!(stream, "{line}")?;
+    for line in BufReader::new(stream).lines() {
+        let line: Value = serde_json::from_str(&line?)?;
+        if let Some(error) = line["error"].as_str() {
+            bail!("{error}");
+        }
+        let text = line["text"].as_str().unwrap_or_default();
+        if line["stdout"].as_bool().unwrap_or_default() {
+            io::stdout().write_all(text.as_bytes())?;
+        } else {
+            io::stderr().write_all(text.as_bytes())?;
+        }
+    }
+    Ok(Some(()))
+}
+
+fn parse(line: &str) -> Result<Request> {
+    let request: Value = serde_json::from_str(line)?;
+    let args = request["args"]
+        .as_array()
+        .ok_or_else(|| eyre!("missing args"))?
+        .iter()
+        .map(|arg| Some(arg.as_str()?.to_owned()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| eyre!("malformed args"))?;
+    let cwd = request["cwd"]
+        .as_str()
+        .ok_or_else(|| eyre!("missing cwd"))?
+        .into();
+    let input = request["input"].as_str().map(str::to_owned);
+    Ok(Request { args, cwd, input })
+}
+
+fn connection(
+    stream: UnixStream,
+    handle: &(impl Fn(Request, SyncSender<(bool, String)>) -> Result<()> + Sync),
+) -> Result<()> {
+    let mut line = String::new();
+    // connections closed without a request check whether a daemon is listening
+    if BufReader::new(stream.try_clone()?).read_line(&mut line)? == 0 {
+        return Ok(());
+    }
+    let request = parse(&line)?;
+    let (writer, output) = mpsc::sync_channel::<(bool, String)>(QUEUE);
+    let writing = thread::spawn({
+        let mut stream = stream.try_clone()?;
+        move || -> io::Result<()> {
+            for (stdout, text) in output {
+                writeln!(stream, "{}", json!({ "stdout": stdout, "text": text }))?;
+            }
+            Ok(())
+        }
+    });
+    let result = handle(request, writer);
+    writing.join().unwrap()?;
+    if let Err(e) = result {
+        writeln!(&stream, "{}", json!({ "error": e.to_string() }))?;
+    }
+    Ok(())
+}
+
+/// Listen on the socket, handling each request on a thread of its own until the process is killed
+pub fn serve(
+    socket: &Path,
+    handle: impl Fn(Request, SyncSender<(bool, String)>) -> Result<()> + Sync,
+) -> Result<()> {
+    if UnixStream::connect(socket).is_ok() {
+        bail!("a daemon is already listening on {}", socket.display());
+    }
+    // left behind by a daemon killed before
+    _ = fs::remove_file(socket);
+    let listener = UnixListener::bind(socket)
+        .map_err(|e| eyre!("failed to listen on {}: {e}", socket.display()))?;
+    fs::set_permissions(socket, fs::Permissions::from_mode(0o600))?;
+    eprintln!("listening on {}", socket.display());
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let handle = &handle;
+            scope.spawn(move || {
+                if let Err(e) = connection(stream, handle) {
+                    eprintln!("warning: {e}");
+                }
+            });
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,70 @@
+use crate::wasm::Module;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Directories under `target/` never containing final artifacts
+const SKIPPED: &[&str] = &["incremental", "build", ".fingerprint"];
+
+fn walk(dir: &Path, found: &mut Vec<(SystemTime, PathBuf)>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if !SKIPPED.iter().any(|skipped| entry.file_name() == *skipped) {
+                walk(&path, found);
+            }
+        } else if path.to_string_lossy().ends_with(".wasm.map") {
+            found.push((metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), path));
+        }
+    }
+}
+
+/// Most recently built source map under `pkg/` and `target/` of the project
+pub fn find_map(root: &Path) -> Option<PathBuf> {
+    let mut found = Vec::new();
+    for dir in ["pkg", "target"] {
+        walk(&root.join(dir), &mut found);
+    }
+    found.into_iter().max().map(|(_, path)| path)
+}
+
+fn newest(files: impl Iterator<Item = PathBuf>) -> Option<PathBuf> {
+    files
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .max()
+        .map(|(_, path)| path)
+}
+
+/// Source map of a wasm-pack project containing `dir`, either `pkg/*.wasm.map` or the WASM
+/// module in `pkg/` referring to its source map
+pub fn wasm_pack(dir: &Path) -> Option<PathBuf> {
+    let pkg = dir
+        .ancestors()
+        .map(|dir| dir.join("pkg"))
+        .find(|pkg| pkg.is_dir())?;
+    let files = fs::read_dir(pkg)
+        .ok()?
+        .flatten()
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+    let with_suffix = |suffix: &'static str| {
+        files
+            .iter()
+            .filter(move |path| path.to_string_lossy().ends_with(suffix))
+            .cloned()
+    };
+    newest(with_suffix("_bg.wasm.map"))
+        .or_else(|| newest(with_suffix(".wasm.map")))
+        .or_else(|| {
+            newest(with_suffix(".wasm").filter(|path| {
+                Module::open(&path.to_string_lossy())
+                    .is_ok_and(|module| module.source_mapping_url().is_some())
+            }))
+        })
+}
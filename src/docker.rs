@@ -0,0 +1,228 @@
+//! Log stream of a container read from the Docker Engine API over its Unix socket, each line
+//! prefixed by the time it was logged
+//!
+//! Unless the container has a TTY, the stream is multiplexed from stdout and stderr in frames of
+//! an 8-byte header, holding the stream and the length of the payload, followed by the payload.
+
+use crate::window::rfc3339;
+use eyre::{bail, eyre, Result};
+use serde_json::Value;
+use std::env;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_SOCKET: &str = "/var/run/docker.sock";
+
+/// Interval of checking whether a stopped container is running again
+const POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug)]
+pub struct Logs {
+    socket: PathBuf,
+    container: String,
+    follow: bool,
+    tty: bool,
+    stream: Option<BufReader<UnixStream>>,
+    /// Partial line of stdout and stderr
+    pending: [Vec<u8>; 2],
+    /// Time of the last line read, the stream resumed after it on reconnect
+    last: Option<(i64, i64)>,
+    /// Lines logged at or before the time are read again on reconnect, and skipped
+    skip: Option<(i64, i64)>,
+}
+
+/// Socket of `DOCKER_HOST` if a `unix://` one, or the default one
+pub fn default_socket() -> PathBuf {
+    env::var("DOCKER_HOST")
+        .ok()
+        .and_then(|host| Some(PathBuf::from(host.strip_prefix("unix://")?)))
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_SOCKET))
+}
+
+struct Response {
+    status: u16,
+    stream: BufReader<UnixStream>,
+}
+
+impl Response {
+    fn body(mut self) -> Result<Vec<u8>> {
+        let mut body = Vec::new();
+        self.stream.read_to_end(&mut body)?;
+        Ok(body)
+    }
+
+    /// Error with the message the engine responded with
+    fn error(self, container: &str) -> eyre::Report {
+        let status = self.status;
+        let message = self
+            .body()
+            .ok()
+            .and_then(|body| serde_json::from_slice::<Value>(&body).ok())
+            .and_then(|body| Some(body["message"].as_str()?.to_owned()));
+        match message {
+            Some(message) => eyre!("docker: {message}"),
+            None => eyre!("docker responded with status {status} for container {container}"),
+        }
+    }
+}
+
+impl Logs {
+    /// Logs of the container, waiting for new lines and reconnecting after restarts if `follow`
+    pub fn open(socket: PathBuf, container: &str, follow: bool) -> Result<Self> {
+        if container.is_empty()
+            || !container
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "_.-".contains(c))
+        {
+            bail!("invalid container {container}");
+        }
+        let mut logs = Self {
+            socket,
+            container: container.to_owned(),
+            follow,
+            tty: false,
+            stream: None,
+            pending: [Vec::new(), Vec::new()],
+            last: None,
+            skip: None,
+        };
+        let Some(state) = logs.inspect()? else {
+            bail!("no such container {container}");
+        };
+        logs.tty = state["Config"]["Tty"].as_bool().unwrap_or_default();
+        logs.connect()?;
+        Ok(logs)
+    }
+
+    /// HTTP/1.0 so that the engine streams the body until closing the connection
+    fn request(&self, path: &str) -> Result<Response> {
+        let mut stream = UnixStream::connect(&self.socket).map_err(|e| {
+            eyre!(
+                "failed to connect to docker at {}: {e}",
+                self.socket.display()
+            )
+        })?;
+        write!(stream, "GET {path} HTTP/1.0\r\nHost: docker\r\n\r\n")?;
+        let mut stream = BufReader::new(stream);
+        let mut line = String::new();
+        stream.read_line(&mut line)?;
+        let status = line
+            .split(' ')
+            .nth(1)
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| eyre!("malformed HTTP status line from docker"))?;
+        loop {
+            line.clear();
+            if stream.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+        }
+        Ok(Response { status, stream })
+    }
+
+    /// State of the container, or `None` if it no longer exists
+    fn inspect(&self) -> Result<Option<Value>> {
+        let response = self.request(&format!("/containers/{}/json", self.container))?;
+        match response.status {
+            200 => Ok(Some(serde_json::from_slice(&response.body()?)?)),
+            404 => Ok(None),
+            _ => Err(response.error(&self.container)),
+        }
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        let mut path = format!(
+            "/containers/{}/logs?stdout=1&stderr=1&timestamps=1&follow={}",
+            self.container,
+            u8::from(self.follow)
+        );
+        if let Some((seconds, nanos)) = self.last {
+            path.push_str(&format!("&since={seconds}.{nanos:09}"));
+            self.skip = self.last;
+        }
+        let response = self.request(&path)?;
+        if response.status != 200 {
+            return Err(response.error(&self.container));
+        }
+        self.stream = Some(response.stream);
+        Ok(())
+    }
+
+    /// Wait for the container to run again after the stream ends, telling whether it does
+    fn reconnect(&mut self) -> Result<bool> {
+        self.stream = None;
+        loop {
+            let Some(state) = self.inspect()? else {
+                return Ok(false);
+            };
+            if state["State"]["Running"].as_bool().unwrap_or_default() {
+                self.connect()?;
+                return Ok(true);
+            }
+            thread::sleep(POLL);
+        }
+    }
+
+    /// Next payload of the stream and the index of the stream it comes from, or `None` at the end
+    fn frame(&mut self) -> Result<Option<(usize, Vec<u8>)>> {
+        let Some(stream) = &mut self.stream else {
+            return Ok(None);
+        };
+        if self.tty {
+            let buf = stream.fill_buf()?;
+            if buf.is_empty() {
+                return Ok(None);
+            }
+            let payload = buf.to_vec();
+            stream.consume(payload.len());
+            return Ok(Some((0, payload)));
+        }
+        let mut header = [0; 8];
+        match stream.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_be_bytes(header[4..].try_into().unwrap()) as usize;
+        let mut payload = vec![0; len];
+        stream.read_exact(&mut payload)?;
+        Ok(Some((usize::from(header[0] == 2), payload)))
+    }
+
+    /// Append the next line to `buf`, returning its length or 0 at the end of the logs
+    pub fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        'read: loop {
+            for pending in &mut self.pending {
+                let Some(end) = pending.iter().position(|&b| b == b'\n') else {
+                    continue;
+                };
+                let line = String::from_utf8_lossy(&pending[..=end]).into_owned();
+                pending.drain(..=end);
+                let instant = rfc3339(&line);
+                if let Some(instant) = instant {
+                    if self.skip.is_some_and(|skip| instant <= skip) {
+                        continue 'read;
+                    }
+                    self.skip = None;
+                    self.last = Some(instant);
+                }
+                buf.push_str(&line);
+                return Ok(line.len());
+            }
+            match self.frame()? {
+                Some((stream, payload)) => self.pending[stream].extend_from_slice(&payload),
+                None if self.follow && self.reconnect()? => {}
+                None => {
+                    // last lines without line ending
+                    let Some(pending) = self.pending.iter_mut().find(|p| !p.is_empty()) else {
+                        return Ok(0);
+                    };
+                    pending.push(b'\n');
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,460 @@
+//! Source map generated from the DWARF line table of a WASM module, for toolchains emitting DWARF
+//! only
+//!
+//! Addresses in the line table of WASM modules are offsets into the contents of the code section,
+//! while columns of source maps are offsets into the module.
+
+use crate::resolver::{FrameResolver, Lookup};
+use crate::wasm::{self, Module};
+use eyre::{bail, eyre, Result};
+use sourcemap::{SourceMap, SourceMapBuilder};
+
+/// Tombstones of code removed by the linker
+const TOMBSTONES: [u64; 3] = [0, 0xffff_fffe, 0xffff_ffff];
+
+/// Row of the line table
+#[derive(Debug, Clone)]
+struct Row {
+    address: u64,
+    file: u64,
+    line: u32,
+    column: u32,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos.saturating_add(len))
+            .ok_or_else(|| eyre!("unexpected end of .debug_line"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    /// Unsigned little-endian integer of `len` bytes
+    fn uint(&mut self, len: usize) -> Result<u64> {
+        let mut value = [0; 8];
+        value[..len.min(8)].copy_from_slice(&self.bytes(len)?[..len.min(8)]);
+        Ok(u64::from_le_bytes(value))
+    }
+
+    fn uleb(&mut self) -> Result<u64> {
+        let (mut value, mut shift) = (0u64, 0);
+        loop {
+            let b = self.u8()?;
+            if shift < 64 {
+                value |= u64::from(b & 0x7f) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64> {
+        let (mut value, mut shift) = (0i64, 0);
+        loop {
+            let b = self.u8()?;
+            if shift < 64 {
+                value |= i64::from(b & 0x7f) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                if shift < 64 && b & 0x40 != 0 {
+                    value |= -1 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Result<String> {
+        let len = self.data[self.pos..]
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or_else(|| eyre!("unterminated string in .debug_line"))?;
+        let s = String::from_utf8_lossy(self.bytes(len)?).into_owned();
+        self.pos += 1;
+        Ok(s)
+    }
+}
+
+/// String at the offset of a string section
+fn string_at(section: Option<&[u8]>, offset: u64) -> Result<String> {
+    let section = section.ok_or_else(|| eyre!("missing string section of .debug_line"))?;
+    let mut reader = Reader {
+        data: section,
+        pos: offset as usize,
+    };
+    if reader.pos >= section.len() {
+        bail!("string offset {offset} out of bounds");
+    }
+    reader.cstr()
+}
+
+/// String sections referred to by DWARF 5 forms
+struct Strings<'a> {
+    debug_str: Option<&'a [u8]>,
+    debug_line_str: Option<&'a [u8]>,
+}
+
+/// Directory and file entries of a DWARF 5 header, as the path and directory index of each
+fn entries(r: &mut Reader, offset_size: usize, strings: &Strings) -> Result<Vec<(String, u64)>> {
+    let formats = (0..r.u8()?)
+        .map(|_| Ok((r.uleb()?, r.uleb()?)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut entries = Vec::new();
+    for _ in 0..r.uleb()? {
+        let (mut path, mut directory) = (String::new(), 0);
+        for &(content, form) in &formats {
+            let (text, value) = match form {
+                0x08 => (Some(r.cstr()?), 0),
+                0x0e => (Some(string_at(strings.debug_str, r.uint(offset_size)?)?), 0),
+                0x1f => (
+                    Some(string_at(strings.debug_line_str, r.uint(offset_size)?)?),
+                    0,
+                ),
+                0x0b => (None, r.uint(1)?),
+                0x05 => (None, r.uint(2)?),
+                0x06 => (None, r.uint(4)?),
+                0x07 => (None, r.uint(8)?),
+                0x0f => (None, r.uleb()?),
+                0x1e => (None, r.bytes(16).map(|_| 0)?),
+                0x09 => {
+                    let len = r.uleb()? as usize;
+                    (None, r.bytes(len).map(|_| 0)?)
+                }
+                form => bail!("unsupported form {form:#x} in .debug_line"),
+            };
+            match (content, text) {
+                (1, Some(text)) => path = text,
+                (2, None) => directory = value,
+                _ => {}
+            }
+        }
+        entries.push((path, directory));
+    }
+    Ok(entries)
+}
+
+fn join(directory: &str, file: &str) -> String {
+    if directory.is_empty() || file.starts_with('/') {
+        file.to_owned()
+    } else {
+        format!("{}/{file}", directory.trim_end_matches('/'))
+    }
+}
+
+/// Rows of each unit in the line table, along with the file names rows refer to by index
+fn line_table(data: &[u8], strings: &Strings) -> Result<Vec<(Vec<String>, Vec<Row>)>> {
+    let mut units = Vec::new();
+    let mut r = Reader { data, pos: 0 };
+    while r.pos < data.len() {
+        let (mut len, mut offset_size) = (r.uint(4)?, 4);
+        if len == 0xffff_ffff {
+            (len, offset_size) = (r.uint(8)?, 8);
+        }
+        let end = r.pos + len as usize;
+        let version = r.uint(2)?;
+        if !(2..=5).contains(&version) {
+            bail!("unsupported .debug_line version {version}");
+        }
+        if version >= 5 {
+            r.u8()?;
+            r.u8()?;
+        }
+        let header_len = r.uint(offset_size)?;
+        let program = r.pos + header_len as usize;
+        let min_length = u64::from(r.u8()?);
+        if version >= 4 {
+            r.u8()?;
+        }
+        // every row is kept, statement or not, to tell the line of each instruction
+        r.u8()?;
+        let line_base = r.u8()? as i8 as i64;
+        let line_range = u64::from(r.u8()?);
+        let opcode_base = r.u8()?;
+        let lengths = r
+            .bytes(usize::from(opcode_base.saturating_sub(1)))?
+            .to_vec();
+        let files = if version >= 5 {
+            let directories = entries(&mut r, offset_size, strings)?;
+            entries(&mut r, offset_size, strings)?
+                .into_iter()
+                .map(|(file, directory)| {
+                    let directory = directories.get(directory as usize);
+                    join(directory.map_or("", |(path, _)| path), &file)
+                })
+                .collect()
+        } else {
+            let mut directories = vec![String::new()];
+            loop {
+                let directory = r.cstr()?;
+                if directory.is_empty() {
+                    break;
+                }
+                directories.push(directory);
+            }
+            // file indices start at 1 before DWARF 5
+            let mut files = vec![String::new()];
+            loop {
+                let file = r.cstr()?;
+                if file.is_empty() {
+                    break;
+                }
+                let directory = r.uleb()? as usize;
+                r.uleb()?;
+                r.uleb()?;
+                files.push(join(directories.get(directory).map_or("", |d| d), &file));
+            }
+            files
+        };
+        if line_range == 0 {
+            bail!("malformed .debug_line header");
+        }
+        r.pos = program;
+        let mut rows = Vec::new();
+        let initial = Row {
+            address: 0,
+            file: 1,
+            line: 1,
+            column: 0,
+        };
+        let mut row = initial.clone();
+        // rows of the current sequence, dropped if removed by the linker
+        let mut sequence = Vec::new();
+        while r.pos < end {
+            match r.u8()? {
+                0 => {
+                    let len = r.uleb()? as usize;
+                    let next = r.pos + len;
+                    match r.u8()? {
+                        1 => {
+                            if sequence
+                                .first()
+                                .is_some_and(|first: &Row| !TOMBSTONES.contains(&first.address))
+                            {
+                                rows.append(&mut sequence);
+                            }
+                            sequence.clear();
+                            row = initial.clone();
+                        }
+                        2 => row.address = r.uint(len - 1)?,
+                        _ => {}
+                    }
+                    r.pos = next;
+                }
+                1 => sequence.push(row.clone()),
+                2 => row.address = row.address.wrapping_add(r.uleb()? * min_length),
+                3 => row.line = (i64::from(row.line) + r.sleb()?) as u32,
+                4 => row.file = r.uleb()?,
+                5 => row.column = r.uleb()? as u32,
+                8 => {
+                    let step = u64::from(255 - opcode_base) / line_range * min_length;
+                    row.address = row.address.wrapping_add(step);
+                }
+                9 => row.address = row.address.wrapping_add(r.uint(2)?),
+                opcode if opcode < opcode_base => {
+                    for _ in 0..lengths[usize::from(opcode) - 1] {
+                        r.uleb()?;
+                    }
+                }
+                opcode => {
+                    let adjusted = u64::from(opcode - opcode_base);
+                    row.address = row.address.wrapping_add(adjusted / line_range * min_length);
+                    row.line =
+                        (i64::from(row.line) + line_base + (adjusted % line_range) as i64) as u32;
+                    sequence.push(row.clone());
+                }
+            }
+        }
+        units.push((files, rows));
+        r.pos = end;
+    }
+    Ok(units)
+}
+
+/// Source map of the WASM module from its `.debug_line` section
+pub fn source_map(module: &[u8]) -> Result<SourceMap> {
+    let sections = wasm::sections(module)?;
+    let code = sections
+        .code
+        .ok_or_else(|| eyre!("WASM module has no code section"))?;
+    let debug_line = sections
+        .custom
+        .get(".debug_line")
+        .ok_or_else(|| eyre!("WASM module has no .debug_line section, build it with debug info"))?;
+    let strings = Strings {
+        debug_str: sections.custom.get(".debug_str").copied(),
+        debug_line_str: sections.custom.get(".debug_line_str").copied(),
+    };
+    let mut rows = Vec::new();
+    for (files, unit) in line_table(debug_line, &strings)? {
+        for row in unit {
+            let file = files.get(row.file as usize).filter(|file| !file.is_empty());
+            let offset = u32::try_from(row.address)
+                .ok()
+                .and_then(|a| a.checked_add(code));
+            if let (Some(file), Some(offset)) = (file, offset) {
+                rows.push((offset, file.clone(), row.line, row.column));
+            }
+        }
+    }
+    rows.sort_by_key(|&(offset, ..)| offset);
+    // the last row at an address takes effect
+    let mut tokens: Vec<(u32, String, u32, u32)> = Vec::new();
+    for row in rows {
+        match tokens.last_mut() {
+            Some(last) if last.0 == row.0 => *last = row,
+            _ => tokens.push(row),
+        }
+    }
+    let mut builder = SourceMapBuilder::new(None);
+    let mut last = None;
+    for (offset, file, line, column) in tokens {
+        // consecutive rows at the same source location add nothing
+        if last.as_ref() == Some(&(file.clone(), line, column)) {
+            continue;
+        }
+        builder.add(
+            0,
+            offset,
+            line.saturating_sub(1),
+            column.saturating_sub(1),
+            Some(&file),
+            None,
+            false,
+        );
+        last = Some((file, line, column));
+    }
+    Ok(builder.into_sourcemap())
+}
+
+/// Resolver of addresses by the DWARF line table of a module, named by its `name` section
+pub struct Dwarf {
+    map: SourceMap,
+    module: Module,
+}
+
+impl Dwarf {
+    pub fn parse(module: &[u8]) -> Result<Self> {
+        Ok(Self {
+            map: source_map(module)?,
+            module: Module::parse(module)?,
+        })
+    }
+}
+
+impl FrameResolver for Dwarf {
+    fn resolve_frame(&self, addr: u32) -> Option<Lookup> {
+        let name = self
+            .module
+            .function_at(addr)
+            .and_then(|index| self.module.function_name(index))
+            .map(str::to_owned);
+        let location = self
+            .map
+            .resolve_frame(addr)
+            .and_then(|lookup| lookup.location);
+        (location.is_some() || name.is_some()).then_some(Lookup { location, name })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// WASM module of a code section of `code` bytes followed by a `.debug_line` section
+    fn module(code: &[u8], debug_line: &[u8]) -> Vec<u8> {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        module.push(10);
+        module.push(code.len() as u8);
+        module.extend(code);
+        let name = b".debug_line";
+        module.push(0);
+        module.push((1 + name.len() + debug_line.len()) as u8);
+        module.push(name.len() as u8);
+        module.extend(name);
+        module.extend(debug_line);
+        module
+    }
+
+    /// Line table of DWARF 4 with a single file `src/main.c`, running `program`
+    fn debug_line(program: &[u8]) -> Vec<u8> {
+        let mut header = vec![1, 1, 1, -5i8 as u8, 14, 13];
+        header.extend([0, 1, 1, 1, 1, 0, 0, 0, 1, 0, 0, 1]);
+        header.extend(b"src\0\0");
+        header.extend(b"main.c\0\x01\0\0\0");
+        let mut unit = 4u16.to_le_bytes().to_vec();
+        unit.extend((header.len() as u32).to_le_bytes());
+        unit.extend(header);
+        unit.extend(program);
+        let mut table = (unit.len() as u32).to_le_bytes().to_vec();
+        table.extend(unit);
+        table
+    }
+
+    #[test]
+    fn line_table_v4() {
+        let program = [
+            // set_address 0x10, advance_line 9, copy
+            &[0, 5, 2, 0x10, 0, 0, 0, 3, 9, 1][..],
+            // set_column 4, special opcode advancing the address by 3 and the line by 1
+            &[5, 4, 13 + (1 + 5) + 14 * 3],
+            // advance_pc 5, advance_line -2, copy, advance_pc 2, end_sequence
+            &[2, 5, 3, 0x7e, 1, 2, 2, 0, 1, 1],
+            // sequence of code removed by the linker, at the tombstone address 0
+            &[0, 5, 2, 0, 0, 0, 0, 3, 99, 1, 2, 4, 0, 1, 1],
+        ]
+        .concat();
+        let code = [1, 2, 0, 0x0b];
+        let map = source_map(&module(&code, &debug_line(&program))).unwrap();
+        // columns are offsets into the module, past the code section header at 10
+        let tokens = map
+            .tokens()
+            .map(|token| {
+                (
+                    token.get_dst_col(),
+                    token.get_source().unwrap().to_owned(),
+                    token.get_src_line(),
+                    token.get_src_col(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tokens,
+            [
+                (0x1a, "src/main.c".to_owned(), 9, 0),
+                (0x1d, "src/main.c".to_owned(), 10, 3),
+                (0x22, "src/main.c".to_owned(), 8, 3),
+            ]
+        );
+        let lookup = Dwarf::parse(&module(&code, &debug_line(&program)))
+            .unwrap()
+            .resolve_frame(0x1e)
+            .unwrap();
+        let location = lookup.location.unwrap();
+        assert_eq!(
+            (location.source.as_str(), location.line),
+            ("src/main.c", 11)
+        );
+    }
+
+    #[test]
+    fn missing_line_table() {
+        let mut module = b"\0asm\x01\0\0\0".to_vec();
+        module.extend([10, 4, 1, 2, 0, 0x0b]);
+        assert!(source_map(&module).is_err());
+        assert!(source_map(&self::module(&[1, 2, 0, 0x0b], &[4, 0, 0, 0, 9, 0])).is_err());
+    }
+}
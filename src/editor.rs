@@ -0,0 +1,50 @@
+//! Editors opened at a source location, with the arguments each of them takes for the position
+
+use crate::Location;
+use eyre::{eyre, Result};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Editor of `$VISUAL` or `$EDITOR`, or VS Code if neither is set
+pub fn default_editor() -> String {
+    env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "code".to_owned())
+}
+
+/// Command opening the editor at the location, `editor` being a program followed by its arguments
+pub fn command(editor: &str, location: &Location) -> Result<Command> {
+    let mut words = editor.split_whitespace();
+    let program = words.next().ok_or_else(|| eyre!("no editor given"))?;
+    let mut command = Command::new(program);
+    command.args(words);
+    let Location {
+        source,
+        line,
+        column,
+    } = location;
+    let name = Path::new(program)
+        .file_stem()
+        .and_then(|name| name.to_str())
+        .unwrap_or(program);
+    match name {
+        "code" | "code-insiders" | "codium" | "cursor" => command
+            .arg("--goto")
+            .arg(format!("{source}:{line}:{column}")),
+        "vi" | "vim" | "nvim" | "gvim" | "mvim" => command
+            .arg(format!("+call cursor({line}, {column})"))
+            .arg(source),
+        "emacs" | "emacsclient" => command.arg(format!("+{line}:{column}")).arg(source),
+        "nano" | "micro" => command.arg(format!("+{line},{column}")).arg(source),
+        "kak" | "ed" | "joe" | "mg" => command.arg(format!("+{line}")).arg(source),
+        "idea" | "clion" | "webstorm" => command
+            .args(["--line", &line.to_string(), "--column", &column.to_string()])
+            .arg(source),
+        // Sublime Text, Zed, Helix and most others take the position after the path
+        _ => command.arg(format!("{source}:{line}:{column}")),
+    };
+    Ok(command)
+}
@@ -0,0 +1,92 @@
+//! Exit codes telling classes of failure apart, for shell scripts and CI steps to branch on
+//!
+//! Errors of a class carry it through to `main`, the innermost class given taking effect, so that
+//! a map failing to load over the network exits as a network error.
+
+use eyre::Report;
+use std::error::Error;
+use std::fmt;
+
+/// Exit codes as listed in `--help`
+pub const HELP: &str = "\
+Exit codes:
+  0  Success
+  1  Other failure
+  2  Invalid arguments
+  3  Source map or bundle failed to load
+  4  Input not found or unreadable
+  5  Network error, such as fetching a map by URL
+  6  No frames matched, with --exit-code
+  7  Some frames left unresolved, with --exit-code";
+
+/// Class of failure, telling the exit code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    MapLoad,
+    Input,
+    Network,
+    NoFrames,
+    Unresolved,
+}
+
+impl Class {
+    pub fn code(self) -> u8 {
+        match self {
+            Class::MapLoad => 3,
+            Class::Input => 4,
+            Class::Network => 5,
+            Class::NoFrames => 6,
+            Class::Unresolved => 7,
+        }
+    }
+}
+
+/// Error of a class, reported as the error it wraps
+#[derive(Debug)]
+struct Classified {
+    class: Class,
+    report: Report,
+}
+
+impl fmt::Display for Classified {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.report, f)
+    }
+}
+
+impl Error for Classified {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        self.report.source()
+    }
+}
+
+/// The error given the class, unless it has one already
+#[track_caller]
+pub fn classify(report: Report, class: Class) -> Report {
+    if report.downcast_ref::<Classified>().is_some() {
+        return report;
+    }
+    Report::new(Classified { class, report })
+}
+
+/// Exit code of the error, 1 if it has no class
+pub fn code(report: &Report) -> u8 {
+    report
+        .downcast_ref::<Classified>()
+        .map_or(1, |classified| classified.class.code())
+}
+
+/// Giving errors of a result a class
+pub trait Classify<T> {
+    fn class(self, class: Class) -> eyre::Result<T>;
+}
+
+impl<T, E: Into<Report>> Classify<T> for Result<T, E> {
+    #[track_caller]
+    fn class(self, class: Class) -> eyre::Result<T> {
+        match self {
+            Ok(value) => Ok(value),
+            Err(e) => Err(classify(e.into(), class)),
+        }
+    }
+}
@@ -0,0 +1,117 @@
+//! Addresses resolved by an external process, for symbol sources of their own such as a symbol
+//! server behind a proprietary API, plugged in without forking
+//!
+//! The command runs once through the shell and is written each address left unresolved as a line
+//! in hex with `0x` prefix, answering with a line of `SOURCE\tLINE\tCOLUMN\tNAME`, lines and
+//! columns counted from 1, any field left empty if unknown and trailing ones left out, such as an
+//! empty line for an address it knows nothing of.
+
+use crate::resolver::{FrameResolver, Lookup};
+use crate::Location;
+use eyre::{eyre, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+pub struct External {
+    command: String,
+    process: RefCell<Option<Process>>,
+    /// Answers so far, as the same address recurs across tracebacks
+    answers: RefCell<HashMap<u32, Option<Lookup>>>,
+}
+
+struct Process {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+/// Answer of the process, `None` if empty
+fn parse(answer: &str) -> Option<Lookup> {
+    let mut fields = answer
+        .split('\t')
+        .map(|field| Some(field).filter(|f| !f.is_empty()));
+    let source = fields.next().flatten();
+    let mut number = || {
+        let field = fields.next().flatten()?;
+        field.parse::<u32>().ok().filter(|&n| n > 0)
+    };
+    let (line, column) = (number(), number());
+    let name = fields.next().flatten().map(str::to_owned);
+    let location = source.map(|source| Location {
+        source: source.to_owned(),
+        line: line.unwrap_or(1),
+        column: column.unwrap_or(1),
+    });
+    (location.is_some() || name.is_some()).then_some(Lookup { location, name })
+}
+
+impl External {
+    pub fn spawn(command: &str) -> Result<Self> {
+        let (shell, flag) = if cfg!(windows) {
+            ("cmd", "/C")
+        } else {
+            ("sh", "-c")
+        };
+        let mut child = Command::new(shell)
+            .args([flag, command])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| eyre!("failed to run {command}: {e}"))?;
+        let process = Process {
+            stdin: child.stdin.take().unwrap(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            child,
+        };
+        Ok(Self {
+            command: command.to_owned(),
+            process: RefCell::new(Some(process)),
+            answers: RefCell::default(),
+        })
+    }
+
+    fn ask(&self, addr: u32) -> Option<Lookup> {
+        let mut process = self.process.borrow_mut();
+        let running = process.as_mut()?;
+        let mut answer = String::new();
+        let asked = writeln!(running.stdin, "{addr:#x}")
+            .and_then(|()| running.stdin.flush())
+            .and_then(|()| running.stdout.read_line(&mut answer));
+        match asked {
+            Ok(n) if n > 0 => parse(answer.trim_end_matches(['\r', '\n'])),
+            // addresses are no longer asked once the process is gone
+            _ => {
+                crate::output::write(
+                    false,
+                    &format!("warning: external resolver {} exited\n", self.command),
+                )
+                .ok();
+                *process = None;
+                None
+            }
+        }
+    }
+}
+
+impl FrameResolver for External {
+    fn resolve_frame(&self, addr: u32) -> Option<Lookup> {
+        if let Some(answer) = self.answers.borrow().get(&addr) {
+            return answer.clone();
+        }
+        let answer = self.ask(addr);
+        self.answers.borrow_mut().insert(addr, answer.clone());
+        answer
+    }
+}
+
+impl Drop for External {
+    fn drop(&mut self) {
+        if let Some(mut process) = self.process.take() {
+            // end of input tells the process to exit
+            drop(process.stdin);
+            process.child.wait().ok();
+        }
+    }
+}
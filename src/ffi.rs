@@ -0,0 +1,16 @@
+//! Entry points shared by the C ABI of `capi` and the exports of `web`, exported once when both
+//! are enabled
+
+use crate::resolver::Resolver;
+
+/// Release the resolver
+///
+/// # Safety
+///
+/// `resolver` must be null or returned by `mbtmap_open_map` or `mbtmap_open` and not yet closed.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_close(resolver: *mut Resolver) {
+    if !resolver.is_null() {
+        drop(Box::from_raw(resolver));
+    }
+}
@@ -0,0 +1,60 @@
+//! Named pipe for programs to write their logs to, opened again each time its last writer closes
+//! it so that it is read forever
+
+use eyre::{bail, eyre, Result};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::os::unix::fs::FileTypeExt;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Debug)]
+pub struct Fifo {
+    path: PathBuf,
+    reader: Option<BufReader<File>>,
+}
+
+impl Fifo {
+    /// Named pipe at `path`, created by `mkfifo` unless already there
+    pub fn create(path: PathBuf) -> Result<Self> {
+        match fs::metadata(&path) {
+            Ok(metadata) if metadata.file_type().is_fifo() => {}
+            Ok(_) => bail!("{} exists and is not a FIFO", path.display()),
+            Err(_) => {
+                let status = Command::new("mkfifo")
+                    .arg(&path)
+                    .status()
+                    .map_err(|e| eyre!("failed to run mkfifo: {e}"))?;
+                if !status.success() {
+                    bail!("mkfifo exited with {status}");
+                }
+            }
+        }
+        Ok(Self { path, reader: None })
+    }
+
+    /// Append the next line to `buf`, waiting for a writer to open the pipe, returning its length
+    pub fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        loop {
+            let reader = match &mut self.reader {
+                Some(reader) => reader,
+                None => self
+                    .reader
+                    .insert(BufReader::new(File::open(&self.path).map_err(|e| {
+                        eyre!("failed to open {}: {e}", self.path.display())
+                    })?)),
+            };
+            let len = reader.read_line(buf)?;
+            if len == 0 {
+                self.reader = None;
+                continue;
+            }
+            if !buf.ends_with('\n') {
+                // last line of a writer without line ending, kept apart from the next writer
+                buf.push('\n');
+                return Ok(len + 1);
+            }
+            return Ok(len);
+        }
+    }
+}
@@ -0,0 +1,1168 @@
+use crate::align::{self, Align, Aligner};
+use crate::bundle::Bundle;
+use crate::codeowners::CodeOwners;
+use crate::context::{Context, Line};
+use crate::exit::{self, Class};
+use crate::external::External;
+use crate::git::{Blame, Git};
+use crate::github::GitHub;
+use crate::ignore::Ignore;
+use crate::numbering::Numbering;
+use crate::preset::Preset;
+use crate::reassemble::Reassembler;
+use crate::redact::Redactor;
+use crate::resolver::{Annotator, Confidence, Coverage, Origin};
+use crate::sample::{self, Sampler};
+use crate::source::Sources;
+use crate::symbols::Symbols;
+use crate::trace::{self, Dedupe, Traces};
+use crate::wasm::Module;
+use crate::window::{self, Timestamp, Window};
+use crate::workerd::{Envelopes, Item};
+use crate::{json, locate, output, report, sarif, table, Columns, Frame, Location, Maps, Radix};
+use clap::ValueEnum;
+use eyre::{bail, eyre, Result};
+use sourcemap::{SourceMap, Token};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::env::{self, current_dir};
+use std::io;
+use std::mem;
+use std::path::PathBuf;
+use std::sync::mpsc::SyncSender;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Location of a bare address, shared by the samples of it
+struct Resolution<'a> {
+    /// Token of the source map, or `None` if resolved by the fallback resolver
+    token: Option<Token<'a>>,
+    location: Location,
+    /// Name of the function, as frames of the input are named when there is no symbol to look up
+    name: Option<String>,
+    link: Option<String>,
+}
+
+/// Form of timestamps prefixing lines of text output
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Timestamps {
+    /// RFC 3339 in UTC, such as `2024-05-01T12:00:00.000000000Z`
+    Rfc3339,
+    /// Seconds since the epoch with milliseconds, such as `1714564800.000`
+    Unix,
+}
+
+impl Timestamps {
+    fn now(self) -> String {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        match self {
+            Timestamps::Rfc3339 => {
+                window::format_rfc3339((now.as_secs() as i64, i64::from(now.subsec_nanos())))
+            }
+            Timestamps::Unix => format!("{}.{:03}", now.as_secs(), now.subsec_millis()),
+        }
+    }
+}
+
+/// Encoding of output, for consumers not reading plain UTF-8
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+    #[value(name = "utf-8")]
+    Utf8,
+    /// UTF-8 prefixed by a byte order mark, as legacy Windows tools expect
+    #[value(name = "utf-8-bom")]
+    Utf8Bom,
+    /// ASCII with other characters escaped as `\u{XXXX}`
+    Ascii,
+}
+
+impl Encoding {
+    fn encode(self, s: &str) -> Cow<'_, str> {
+        if self != Encoding::Ascii || s.is_ascii() {
+            return Cow::Borrowed(s);
+        }
+        let mut escaped = String::with_capacity(s.len());
+        for c in s.chars() {
+            if c.is_ascii() {
+                escaped.push(c);
+            } else {
+                escaped.push_str(&format!("\\u{{{:04x}}}", u32::from(c)));
+            }
+        }
+        Cow::Owned(escaped)
+    }
+}
+
+/// Options of the filter shared by binaries
+#[derive(clap::Args, Debug)]
+pub struct FilterArgs {
+    /// Print filtered result to stdout instead of stderr
+    #[arg(short = 'o', long)]
+    pub stdout: bool,
+    /// Use absolute path to source files resolved instead of relative to current working directory
+    #[arg(short = 'p', long)]
+    pub absolute_path: bool,
+    /// Output format
+    #[arg(short = 'f', long, value_enum, default_value_t = Format::Text)]
+    pub format: Format,
+    /// Print `--format diff` side by side, lines of the input padded or cut to WIDTH columns on the left
+    #[arg(long, value_name = "WIDTH", num_args = 0..=1, require_equals = true, default_missing_value = "60")]
+    pub side_by_side: Option<usize>,
+    /// Preset of trace shape to recognize
+    #[arg(short = 'P', long, value_enum, default_value_t = Preset::Moonbit)]
+    pub preset: Preset,
+    /// Source map for frames logged by the named worker script, may be repeated
+    #[arg(long, value_name = "SCRIPT=PATH", value_parser = parse_key_val)]
+    pub map_for: Vec<(String, String)>,
+    /// Symbol map of function names, as emitted by `emcc --emit-symbol-map`
+    #[arg(long, value_name = "PATH")]
+    pub symbols: Option<String>,
+    /// WASM module the traceback comes from, to resolve and name frames by function index
+    #[arg(long, value_name = "PATH")]
+    pub wasm: Option<String>,
+    /// Hide JS glue frames recognized by the preset, or standard library frames of `assemblyscript`
+    #[arg(long)]
+    pub hide_glue: bool,
+    /// GitHub repository in the form of owner/name to link resolved locations to
+    #[arg(long, value_name = "OWNER/NAME", requires = "github_ref")]
+    pub github_repo: Option<String>,
+    /// Commit or tag the permalinks point to
+    #[arg(long, value_name = "REF", requires = "github_repo")]
+    pub github_ref: Option<String>,
+    /// Git repository of the sources, to mark resolved lines changed or moved in its working tree since the build commit
+    #[arg(long, value_name = "DIR")]
+    pub git_root: Option<PathBuf>,
+    /// Commit the build comes from, by default the `commit` or `release` metadata of the bundle
+    #[arg(long, value_name = "REV", requires = "git_root")]
+    pub git_commit: Option<String>,
+    /// Annotate each frame with the short hash, author and date of the commit last changing its line, blamed at the build commit with `--git-root`
+    #[arg(long)]
+    pub blame: bool,
+    /// CODEOWNERS file to annotate each frame with the owners of its source, counting frames by owner in `csv` and `tsv`
+    #[arg(long, value_name = "FILE")]
+    pub codeowners: Option<String>,
+    /// File of frames to collapse, by default `.mbtmapignore` of the current directory or the nearest ancestor
+    #[arg(long, value_name = "PATH")]
+    pub ignore_file: Option<PathBuf>,
+    /// Resolve all frames regardless of `.mbtmapignore`
+    #[arg(long, conflicts_with = "ignore_file")]
+    pub no_ignore: bool,
+    /// Leave frames ignored out instead of collapsing them into a line counting them
+    #[arg(long)]
+    pub hide_ignored: bool,
+    /// Join frames wrapped across lines by the logging system back before matching
+    #[arg(long)]
+    pub reassemble: bool,
+    /// Command resolving addresses the source map leaves unresolved, written an address per line and answering `SOURCE\tLINE\tCOLUMN\tNAME`
+    #[arg(long, value_name = "CMD")]
+    pub external_resolver: Option<String>,
+    /// Replace each distinct match of the pattern in output by `[redacted-N]`, or only its first group if it has one, repeated for more patterns
+    #[arg(long, value_name = "REGEX")]
+    pub redact: Vec<String>,
+    /// Exit with 6 if no frames matched and 7 if some are left unresolved, as listed in `--help`
+    #[arg(long)]
+    pub exit_code: bool,
+    /// Fail instead of warning when many addresses fall outside the source map, hinting at a map of another build, or when sources are missing with `--verify-sources`
+    #[arg(long)]
+    pub strict: bool,
+    /// Pad frames so that appended locations start at the same terminal column, past the widest frame of each run of frames or after COLUMN columns
+    #[arg(long, value_name = "COLUMN", num_args = 0..=1, require_equals = true, default_missing_value = "auto", value_parser = align::parse_align)]
+    pub align: Option<Align>,
+    /// Renumber frames of each traceback as #0, #1 and so on, with indentation normalized
+    #[arg(long)]
+    pub number_frames: bool,
+    /// Pattern of lines separating tracebacks, a line matching it starts a new one
+    #[arg(long, value_name = "REGEX", default_value = trace::DEFAULT_DELIMITER)]
+    pub trace_delimiter: String,
+    /// Print identical consecutive tracebacks once, noting how many times they are repeated
+    #[arg(long)]
+    pub dedupe_traces: bool,
+    /// Only resolve lines logged at or after the timestamp, in ISO 8601 or syslog form
+    #[arg(long, value_name = "TIMESTAMP", value_parser = window::parse_bound)]
+    pub since: Option<Timestamp>,
+    /// Only resolve lines logged at or before the timestamp, in ISO 8601 or syslog form
+    #[arg(long, value_name = "TIMESTAMP", value_parser = window::parse_bound)]
+    pub until: Option<Timestamp>,
+    /// Drop lines outside `--since` and `--until` instead of passing them through unresolved
+    #[arg(long)]
+    pub drop_outside: bool,
+    /// Print only the source location of each frame resolved, one per line
+    #[arg(short = 'm', long)]
+    pub only_matching: bool,
+    /// Print only the lines with a frame resolved, annotated, leaving out the other lines passed through
+    #[arg(short = 'q', long)]
+    pub quiet_passthrough: bool,
+    /// Flag frames resolved to sources missing on disk, and summarize them at the end
+    #[arg(long)]
+    pub verify_sources: bool,
+    /// Prefix each location printed by `--only-matching` with the address of the frame
+    #[arg(long, requires = "only_matching")]
+    pub with_address: bool,
+    /// Also print N lines around tracebacks that `--only-matching` or `--drop-outside` would leave out
+    #[arg(long, value_name = "N")]
+    pub trace_context: Option<usize>,
+    /// Annotate each frame with the URL of the module it comes from
+    #[arg(long)]
+    pub with_module: bool,
+    /// Name each frame by the original function from the names of the source map, the name of the mapping starting the function with `--wasm`, before the name section
+    #[arg(long)]
+    pub original_names: bool,
+    /// Suffix the annotation of each frame with how much to trust it, `exact`, `nearest`, `name-only` or `unresolved`
+    #[arg(long)]
+    pub with_confidence: bool,
+    /// Column of the first character of a line in output, 0 or 1
+    #[arg(long, value_name = "BASE", default_value_t = 1, value_parser = clap::value_parser!(u32).range(0..=1))]
+    pub column_base: u32,
+    /// Leave columns out of locations in output
+    #[arg(long)]
+    pub no_column: bool,
+    /// Resolve one of every N tracebacks, passing the others through unresolved
+    #[arg(long, value_name = "1/N", value_parser = sample::parse_sample)]
+    pub sample: Option<usize>,
+    /// Resolve at most N tracebacks per second, passing the others through unresolved
+    #[arg(long, value_name = "N/s", value_parser = sample::parse_rate)]
+    pub rate_limit: Option<u32>,
+    /// Prefix each line of text output with the time it is printed, for inputs without timestamps of their own
+    #[arg(long, value_name = "FORMAT", num_args = 0..=1, require_equals = true, default_missing_value = "rfc3339")]
+    pub timestamps: Option<Timestamps>,
+    /// Print the tokens of the source map around the address of each frame resolved to stderr
+    #[arg(short = 'v', long)]
+    pub verbose: bool,
+    /// Base of addresses without `0x` prefix, decimal by default, `auto` taking those of frames as hex and others as decimal unless holding hex letters
+    #[arg(long, value_enum, value_name = "RADIX", default_value_t = Radix::Dec)]
+    pub addr_radix: Radix,
+    /// Encoding of the output
+    #[arg(long, value_enum, value_name = "ENCODING", default_value_t = Encoding::Utf8)]
+    pub output_encoding: Encoding,
+}
+
+pub fn parse_key_val(s: &str) -> Result<(String, String), String> {
+    s.split_once('=')
+        .map(|(key, value)| (key.to_owned(), value.to_owned()))
+        .ok_or_else(|| format!("expected KEY=VALUE, found `{s}`"))
+}
+
+/// Tokens before, at and after the one `addr` is looked up to, the latter marked
+fn neighborhood(map: &SourceMap, addr: u32, cwd: &Option<PathBuf>) -> String {
+    let (mut low, mut high) = (0, map.get_token_count() as usize);
+    while low < high {
+        let mid = (low + high) / 2;
+        let token = map.get_token(mid).unwrap();
+        if (token.get_dst_line(), token.get_dst_col()) > (0, addr) {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    let mut out = format!("tokens around {addr:#x}:\n");
+    for i in low.saturating_sub(2)..low + 1 {
+        let Some(token) = map.get_token(i) else {
+            continue;
+        };
+        let marker = if i + 1 == low { '>' } else { ' ' };
+        out.push_str(&format!(
+            "  {marker} {:#x} {}\n",
+            token.get_dst_col(),
+            locate(&token, cwd)
+        ));
+    }
+    out
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Traceback with source location appended to each frame
+    Text,
+    /// SARIF log with one result per resolved frame, printed after all the input has been read
+    Sarif,
+    /// JSON document of resolved frames with their positions in the input, printed after all the input has been read
+    Json,
+    /// JSON object of each resolved frame with its position in the input per line, printed as frames are resolved
+    Ndjson,
+    /// CSV of `addr,module,source,line,column,name,count` counting each distinct frame, printed after all the input has been read
+    Csv,
+    /// TSV of the same columns as CSV
+    Tsv,
+    /// Markdown report with collapsible source snippet of each frame, printed after all the input has been read
+    Markdown,
+    /// HTML report with collapsible source snippet of each frame, printed after all the input has been read
+    Html,
+    /// Lines of the input changed by annotation, as `-` lines followed by `+` lines of a unified diff among the lines left unchanged, printed as they are resolved
+    Diff,
+    /// Markdown bug report with the environment, the top frame, and source snippets of the top three frames, printed after all the input has been read
+    Issue,
+}
+
+/// Line of the input in `--format diff`, in the order of the lines of output
+enum Original {
+    /// Line the next line of output comes from
+    Line(String),
+    /// Line left out of the output, such as glue with `--hide-glue`
+    Removed(String),
+    /// Next line of output added, such as the note of frames ignored
+    Added,
+}
+
+pub struct Filter {
+    annotator: Annotator,
+    format: Format,
+    /// Width of the column of input lines in `--format diff`, if printed side by side
+    side_by_side: Option<usize>,
+    /// Whether characters of ambiguous width take two columns, padding the column of input lines
+    cjk: bool,
+    /// Lines of the input yet to be paired with their output in `--format diff`
+    originals: VecDeque<Original>,
+    stdout: bool,
+    strict: bool,
+    exit_code: bool,
+    numbering: Option<Numbering>,
+    aligner: Option<Aligner>,
+    traces: Traces,
+    dedupe: Option<Dedupe>,
+    sampler: Option<Sampler>,
+    window: Option<Window>,
+    drop_outside: bool,
+    only_matching: bool,
+    quiet_passthrough: bool,
+    with_address: bool,
+    context: Option<Context>,
+    verbose: bool,
+    redactor: Redactor,
+    /// Tokens around frames resolved, printed after the output of the text of the frames
+    notes: String,
+    /// Name of the stream prefixing each line of text output
+    label: Option<String>,
+    /// Version, platform and build resolved with, heading `--format issue`
+    environment: Vec<(&'static str, String)>,
+    timestamps: Option<Timestamps>,
+    encoding: Encoding,
+    /// Whether the byte order mark of `Encoding::Utf8Bom` has been printed
+    bom: Cell<bool>,
+    writer: Option<SyncSender<(bool, String)>>,
+    /// Whether the next text output starts a line
+    line_start: bool,
+    /// Lines and bytes read from the input before the text being resolved
+    line: usize,
+    offset: usize,
+    envelopes: Option<Envelopes>,
+    reassembler: Option<Reassembler>,
+    sources: Sources,
+    annotated: String,
+    frames: Vec<Frame>,
+}
+
+impl Filter {
+    /// Filter with the WASM module given by `--wasm`, or otherwise the one in the default entry
+    /// of the symbol bundle
+    pub fn new(args: &FilterArgs, bundle: Option<&Bundle>) -> Result<Self> {
+        let module = match &args.wasm {
+            Some(wasm) => Some(Module::open(wasm)?),
+            None => bundle.and_then(Bundle::module).cloned(),
+        };
+        if args.format == Format::Diff {
+            let conflicting = [
+                (args.dedupe_traces, "--dedupe-traces"),
+                (args.only_matching, "--only-matching"),
+                (args.quiet_passthrough, "--quiet-passthrough"),
+                (args.trace_context.is_some(), "--trace-context"),
+            ];
+            if let Some((_, flag)) = conflicting.iter().find(|(given, _)| *given) {
+                bail!("--format diff pairs each line of the input with its output, drop {flag}");
+            }
+        } else if args.side_by_side.is_some() {
+            bail!("--side-by-side requires --format diff");
+        }
+        if args.preset == Preset::V8Log && module.is_none() {
+            bail!("--wasm is required to resolve V8 tick log");
+        }
+        let cwd = if args.absolute_path {
+            None
+        } else {
+            Some(current_dir()?)
+        };
+        let mut annotator = Annotator::new(args.preset, args.hide_glue)?;
+        annotator.cwd = cwd;
+        annotator.github = args
+            .github_repo
+            .clone()
+            .zip(args.github_ref.clone())
+            .map(|(repo, reference)| GitHub { repo, reference });
+        if let Some(root) = &args.git_root {
+            let metadata = bundle.map(|bundle| &bundle.metadata);
+            let Some(commit) = args.git_commit.as_ref().or_else(|| {
+                let metadata = metadata?;
+                metadata.get("commit").or_else(|| metadata.get("release"))
+            }) else {
+                bail!("--git-root requires the build commit, by --git-commit or the commit or release metadata of a bundle");
+            };
+            annotator.git = Some(Git::new(root, commit)?);
+        }
+        annotator.blame = args.blame.then(|| Blame::new(annotator.git.as_ref()));
+        annotator.codeowners = args
+            .codeowners
+            .as_deref()
+            .map(CodeOwners::open)
+            .transpose()?;
+        let ignore_file = match &args.ignore_file {
+            Some(path) => Some(path.clone()),
+            None if args.no_ignore => None,
+            None => Ignore::find(),
+        };
+        annotator.ignore = ignore_file.as_deref().map(Ignore::open).transpose()?;
+        annotator.hide_ignored = args.hide_ignored;
+        if args.format == Format::Diff {
+            annotator.origins = Some(RefCell::default());
+        }
+        annotator.symbols = args.symbols.as_deref().map(Symbols::open).transpose()?;
+        annotator.module = module;
+        if let Some(command) = &args.external_resolver {
+            annotator.fallback = Some(Box::new(External::spawn(command)?));
+        }
+        annotator.with_module = args.with_module;
+        annotator.with_confidence = args.with_confidence;
+        annotator.original_names = args.original_names;
+        annotator.columns = Columns {
+            base: args.column_base,
+            hidden: args.no_column,
+        };
+        annotator.missing = args.verify_sources.then(RefCell::default);
+        annotator.radix = args.addr_radix;
+        annotator.align = args.align.is_some();
+        let mut environment = vec![
+            ("mbtmap", env!("CARGO_PKG_VERSION").to_owned()),
+            (
+                "platform",
+                format!("{}-{}", env::consts::OS, env::consts::ARCH),
+            ),
+        ];
+        if let Some(preset) = args.preset.to_possible_value() {
+            environment.push(("preset", preset.get_name().to_owned()));
+        }
+        if let Some(release) = bundle.and_then(|bundle| bundle.metadata.get("release")) {
+            environment.push(("release", release.clone()));
+        }
+        environment.extend(args.wasm.clone().map(|wasm| ("module", wasm)));
+        Ok(Self {
+            annotator,
+            format: args.format,
+            side_by_side: args.side_by_side,
+            cjk: align::cjk_locale(),
+            originals: VecDeque::new(),
+            stdout: args.stdout,
+            strict: args.strict,
+            exit_code: args.exit_code,
+            numbering: args.number_frames.then(Numbering::new),
+            aligner: args.align.map(Aligner::new),
+            traces: Traces::new(&args.trace_delimiter)?,
+            dedupe: args.dedupe_traces.then(Dedupe::default),
+            sampler: (args.sample.is_some() || args.rate_limit.is_some())
+                .then(|| Sampler::new(args.sample, args.rate_limit)),
+            window: (args.since.is_some() || args.until.is_some())
+                .then(|| Window::new(args.since, args.until)),
+            drop_outside: args.drop_outside,
+            only_matching: args.only_matching,
+            quiet_passthrough: args.quiet_passthrough,
+            with_address: args.with_address,
+            context: args.trace_context.map(Context::new),
+            verbose: args.verbose,
+            redactor: Redactor::new(&args.redact)?,
+            notes: String::new(),
+            label: None,
+            environment,
+            timestamps: args.timestamps,
+            encoding: args.output_encoding,
+            bom: Cell::new(false),
+            writer: None,
+            line_start: true,
+            line: 0,
+            offset: 0,
+            envelopes: (args.preset == Preset::Workerd).then(Envelopes::default),
+            reassembler: args.reassemble.then(Reassembler::new),
+            sources: Sources::default(),
+            annotated: String::new(),
+            frames: Vec::new(),
+        })
+    }
+
+    /// Prefix each line of text output with the name of the stream
+    pub fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_owned());
+        self
+    }
+
+    /// Send output to a writer thread instead of printing it, along with whether it goes to stdout
+    pub fn writer(mut self, writer: SyncSender<(bool, String)>) -> Self {
+        self.writer = Some(writer);
+        self
+    }
+
+    /// Take paths of sources relative to `cwd` in place of the current working directory
+    pub fn cwd(mut self, cwd: PathBuf) -> Self {
+        if self.annotator.cwd.is_some() {
+            self.annotator.cwd = Some(cwd);
+        }
+        self
+    }
+
+    /// Addresses looked up so far
+    pub fn coverage(&self) -> Coverage {
+        self.annotator.coverage.get()
+    }
+
+    fn write(&self, stdout: bool, s: &str) {
+        match &self.writer {
+            // output is discarded once the writer is gone
+            Some(writer) => _ = writer.send((stdout, s.to_owned())),
+            // errors are returned on the flush when finished
+            None => _ = output::write(stdout, s),
+        }
+    }
+
+    fn print(&self, s: &str) {
+        let encoded = self.encoding.encode(s);
+        if self.encoding == Encoding::Utf8Bom && !self.bom.replace(true) {
+            self.write(self.stdout, &format!("\u{feff}{encoded}"));
+        } else {
+            self.write(self.stdout, &encoded);
+        }
+    }
+
+    pub fn process(&mut self, maps: &Maps, input: &str) {
+        match &mut self.reassembler {
+            Some(reassembler) => {
+                let input = reassembler.push(&self.annotator, input);
+                self.process_window(maps, &input)
+            }
+            None => self.process_window(maps, input),
+        }
+    }
+
+    fn process_window(&mut self, maps: &Maps, input: &str) {
+        let Some(window) = &mut self.window else {
+            return self.resolve(maps, input);
+        };
+        for (inside, run) in window.split(input) {
+            if inside {
+                self.resolve(maps, run);
+            } else if !self.drop_outside || self.context.is_some() {
+                self.annotate(None, run);
+            } else {
+                self.advance(run);
+            }
+        }
+    }
+
+    /// Resolve bare addresses by the default source map, each as a line of its own, walking the
+    /// tokens once along the addresses sorted, and locating each distinct address once
+    pub fn resolve_addrs(&mut self, maps: &Maps, addrs: &[u32]) {
+        let mut order = (0..addrs.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| addrs[i]);
+        let mut unique: Vec<(u32, Option<Resolution>)> = Vec::new();
+        let mut resolved = vec![0; addrs.len()];
+        let mut next = 0;
+        let mut last = None;
+        for i in order {
+            let addr = addrs[i];
+            if unique.last().is_none_or(|&(prev, _)| prev != addr) {
+                while let Some(token) = maps.default.get_token(next) {
+                    if (token.get_dst_line(), token.get_dst_col()) > (0, addr) {
+                        break;
+                    }
+                    last = Some(token);
+                    next += 1;
+                }
+                let resolution = match last {
+                    Some(token) => {
+                        let location = locate(&token, &self.annotator.cwd);
+                        let name = self.annotator.address_name(&maps.default, &token, addr);
+                        let name = name.map(str::to_owned);
+                        Some(self.resolution(Some(token), location, name))
+                    }
+                    None => {
+                        let fallback = self.annotator.fallback.as_ref();
+                        fallback
+                            .and_then(|fallback| fallback.resolve_frame(addr))
+                            .and_then(|lookup| Some((lookup.location?, lookup.name)))
+                            .map(|(location, name)| self.resolution(None, location, name))
+                    }
+                };
+                unique.push((addr, resolution));
+            }
+            resolved[i] = unique.len() - 1;
+        }
+        for (&addr, i) in addrs.iter().zip(resolved) {
+            self.resolve_addr(maps, addr, unique[i].1.as_ref());
+        }
+    }
+
+    fn resolution<'a>(
+        &self,
+        token: Option<Token<'a>>,
+        location: Location,
+        name: Option<String>,
+    ) -> Resolution<'a> {
+        let link = self
+            .annotator
+            .github
+            .as_ref()
+            .and_then(|github| github.permalink(&location));
+        Resolution {
+            token,
+            location,
+            name,
+            link,
+        }
+    }
+
+    fn resolve_addr(&mut self, maps: &Maps, addr: u32, resolution: Option<&Resolution>) {
+        let text = format!("{addr:#x}");
+        if self.format == Format::Diff {
+            self.originals
+                .push_back(Original::Line(text.clone() + "\n"));
+        }
+        match resolution {
+            Some(Resolution { token: None, .. }) => self.annotator.count_elsewhere(),
+            _ => self.annotator.count(&maps.default, addr),
+        }
+        let Some(Resolution {
+            token,
+            location,
+            name,
+            link,
+        }) = resolution
+        else {
+            let suffix = if self.annotator.with_confidence {
+                " [confidence: unresolved]"
+            } else {
+                ""
+            };
+            self.emit(&format!("{text} ??{suffix}\n"));
+            self.advance(&(text + "\n"));
+            return;
+        };
+        let confidence = token
+            .as_ref()
+            .map_or(Confidence::Nearest, |token| Confidence::of(token, addr));
+        let columns = self.annotator.columns;
+        let position = columns.format(location);
+        let mut annotation = vec![position.clone()];
+        annotation.extend(self.annotator.age(location));
+        annotation.extend(self.annotator.blame(location));
+        let owners = self.annotator.owners(location);
+        if !owners.is_empty() {
+            annotation.push(format!("[owners: {}]", owners.join(" ")));
+        }
+        annotation.extend(link.clone());
+        if self.annotator.with_confidence {
+            annotation.push(format!("[confidence: {}]", confidence.as_str()));
+        }
+        if self.only_matching && self.with_address {
+            self.emit(&format!("{text} {position}\n"));
+        } else if self.only_matching {
+            self.emit(&format!("{position}\n"));
+        } else {
+            self.emit(&format!("{text} {}\n", annotation.join(" ")));
+        }
+        if !matches!(self.format, Format::Text | Format::Diff) {
+            let snippet = match self.format {
+                Format::Markdown | Format::Html | Format::Issue => {
+                    token.as_ref().and_then(|token| {
+                        self.sources
+                            .snippet(&maps.default, token, &self.annotator.cwd)
+                    })
+                }
+                _ => None,
+            };
+            self.frames.push(Frame {
+                text: text.clone(),
+                addr,
+                module: None,
+                name: name.clone(),
+                location: location.clone(),
+                column: columns.column(location),
+                position,
+                link: link.clone(),
+                confidence,
+                owners,
+                trace: self.traces.index(),
+                panic: self.traces.panic().cloned(),
+                input_line: self.line + 1,
+                input_offset: self.offset,
+                snippet,
+            });
+        }
+        self.advance(&(text + "\n"));
+        self.flush_ndjson();
+        if self.verbose {
+            self.write(
+                false,
+                &neighborhood(&maps.default, addr, &self.annotator.cwd),
+            );
+        }
+    }
+
+    fn resolve(&mut self, maps: &Maps, input: &str) {
+        let Some(envelopes) = &mut self.envelopes else {
+            return self.annotate(Some(&maps.default), input);
+        };
+        for item in envelopes.push(input) {
+            self.annotate_item(maps, item);
+        }
+    }
+
+    fn annotate_item(&mut self, maps: &Maps, item: Item) {
+        match item {
+            Item::Text(text) => self.annotate(Some(&maps.default), &text),
+            Item::Envelope { script, text } => {
+                self.annotate(Some(maps.get(script.as_deref())), &text)
+            }
+        }
+    }
+
+    /// Resolve tracebacks in `input` by `map`, or pass them through unresolved without one
+    fn annotate(&mut self, map: Option<&SourceMap>, input: &str) {
+        for (boundary, trace) in self.traces.split(input) {
+            if boundary {
+                self.end_trace();
+            }
+            self.traces.observe(trace);
+            let sampled_out = match &mut self.sampler {
+                Some(sampler) => self.annotator.is_frame(trace) && !sampler.admit(),
+                None => false,
+            };
+            let map = map.filter(|_| !sampled_out);
+            let result = match map {
+                _ if self.context.is_some() => self.annotate_context(map, trace, boundary),
+                Some(map) if self.quiet_passthrough => self.annotate_quiet(map, trace),
+                Some(map) => self.annotate_trace(map, trace).0,
+                None => {
+                    self.advance(trace);
+                    if self.only_matching || self.quiet_passthrough {
+                        String::new()
+                    } else {
+                        trace.to_owned()
+                    }
+                }
+            };
+            if self.format == Format::Diff {
+                self.pair(trace);
+            }
+            let result = match &mut self.numbering {
+                Some(numbering) => numbering.push(&result, |line| self.annotator.is_frame(line)),
+                None => result,
+            };
+            self.emit(&result);
+            if !self.notes.is_empty() {
+                let notes = mem::take(&mut self.notes);
+                self.write(false, &notes);
+            }
+        }
+    }
+
+    /// Queue the lines of `input` in the order of the lines of output they come from, by the
+    /// origins recorded annotating it, or one for one if passed through
+    fn pair(&mut self, input: &str) {
+        let lines = input.split_inclusive('\n').collect::<Vec<_>>();
+        let origins = match &self.annotator.origins {
+            Some(origins) => mem::take(&mut *origins.borrow_mut()),
+            None => Vec::new(),
+        };
+        if origins.is_empty() {
+            let lines = lines
+                .into_iter()
+                .map(|line| Original::Line(line.to_owned()));
+            return self.originals.extend(lines);
+        }
+        self.originals
+            .extend(origins.into_iter().map(|origin| match origin {
+                Origin::Line(i) => Original::Line(lines[i].to_owned()),
+                Origin::Removed(i) => Original::Removed(lines[i].to_owned()),
+                Origin::Added => Original::Added,
+            }));
+    }
+
+    /// Output of the current traceback, held back if aligning to the widest frame
+    fn emit(&mut self, result: &str) {
+        match &mut self.aligner {
+            Some(aligner) => {
+                let aligned = aligner.push(result, |line| self.annotator.is_frame(line));
+                self.hold(&aligned)
+            }
+            None => self.hold(result),
+        }
+    }
+
+    /// Output of the current traceback once aligned, held back if deduplicating
+    fn hold(&mut self, result: &str) {
+        match &mut self.dedupe {
+            Some(dedupe) => dedupe.push(result),
+            None => self.output(result),
+        }
+    }
+
+    fn end_trace(&mut self) {
+        self.traces.end();
+        if let Some(context) = &mut self.context {
+            context.end();
+        }
+        if let Some(sampler) = &mut self.sampler {
+            sampler.end();
+        }
+        if let Some(result) = self.numbering.as_mut().map(Numbering::finish) {
+            self.emit(&result);
+        }
+        if let Some(result) = self.aligner.as_mut().map(Aligner::finish) {
+            self.hold(&result);
+        }
+        if let Some(result) = self.dedupe.as_mut().map(Dedupe::end) {
+            self.output(&result);
+        }
+    }
+
+    /// Resolve `input` line by line, keeping only the lines with a frame resolved
+    fn annotate_quiet(&mut self, map: &SourceMap, input: &str) -> String {
+        let mut out = String::new();
+        for line in input.split_inclusive('\n') {
+            if let (result, true) = self.annotate_trace(map, line) {
+                out.push_str(&result);
+            }
+        }
+        out
+    }
+
+    /// Resolve `input` line by line, keeping the context of `--trace-context` around tracebacks,
+    /// its first line the header of a traceback if it starts one
+    fn annotate_context(&mut self, map: Option<&SourceMap>, input: &str, boundary: bool) -> String {
+        let mut out = String::new();
+        for (i, line) in input.split_inclusive('\n').enumerate() {
+            if map.is_none() {
+                self.advance(line);
+            }
+            let line = match map {
+                None if self.drop_outside || self.only_matching || self.quiet_passthrough => {
+                    Line::Suppressed(line.to_owned())
+                }
+                None => Line::Kept(line.to_owned()),
+                Some(map) => match self.annotate_trace(map, line) {
+                    (_, false) if self.only_matching || self.quiet_passthrough => {
+                        Line::Suppressed(line.to_owned())
+                    }
+                    (result, _) => Line::Kept(result),
+                },
+            };
+            let line = match line {
+                // a blank delimiter separates tracebacks rather than heading one
+                Line::Suppressed(line) if boundary && i == 0 && !line.trim().is_empty() => {
+                    Line::Header(line)
+                }
+                line => line,
+            };
+            out.push_str(&self.context.as_mut().unwrap().push(line));
+        }
+        out
+    }
+
+    /// Resolve `input`, telling whether any frame is resolved
+    fn annotate_trace(&mut self, map: &SourceMap, input: &str) -> (String, bool) {
+        let Self {
+            annotator,
+            format,
+            sources,
+            frames,
+            traces,
+            only_matching,
+            with_address,
+            verbose,
+            redactor,
+            notes,
+            line,
+            offset,
+            ..
+        } = self;
+        let mut matching = String::new();
+        let mut resolved = false;
+        let result = annotator.annotate(map, input, |frame| {
+            resolved = true;
+            if *verbose {
+                notes.push_str(&neighborhood(map, frame.addr, &annotator.cwd));
+            }
+            if *only_matching {
+                if *with_address {
+                    matching.push_str(&format!("{:#x} ", frame.addr));
+                }
+                matching.push_str(&format!("{}\n", annotator.columns.format(&frame.location)));
+            }
+            let snippet = match format {
+                Format::Text
+                | Format::Diff
+                | Format::Sarif
+                | Format::Json
+                | Format::Ndjson
+                | Format::Csv
+                | Format::Tsv => None,
+                Format::Markdown | Format::Html | Format::Issue => frame
+                    .token
+                    .and_then(|token| sources.snippet(map, token, &annotator.cwd)),
+            };
+            if !matches!(format, Format::Text | Format::Diff) {
+                frames.push(Frame {
+                    text: redactor.redact(frame.line).into_owned(),
+                    addr: frame.addr,
+                    module: frame.module.map(str::to_owned),
+                    name: frame.name.map(str::to_owned),
+                    column: annotator.columns.column(&frame.location),
+                    position: annotator.columns.format(&frame.location),
+                    location: frame.location,
+                    link: frame.link,
+                    confidence: frame.confidence,
+                    owners: frame.owners,
+                    trace: traces.index(),
+                    panic: traces.panic().cloned().map(|mut panic| {
+                        panic.message = redactor.redact(&panic.message).into_owned();
+                        panic
+                    }),
+                    input_line: *line + input[..frame.offset].matches('\n').count() + 1,
+                    input_offset: *offset + frame.offset,
+                    snippet,
+                });
+            }
+        });
+        self.advance(input);
+        self.flush_ndjson();
+        if self.only_matching {
+            (matching, resolved)
+        } else {
+            (result, resolved)
+        }
+    }
+
+    /// Print frames resolved so far one per line for `ndjson`, as soon as they are resolved
+    fn flush_ndjson(&mut self) {
+        if self.format == Format::Ndjson {
+            for frame in mem::take(&mut self.frames) {
+                self.print(&(json::frame(&frame).to_string() + "\n"));
+            }
+        }
+    }
+
+    /// Count `text` as read from the input, before the position of frames after it
+    fn advance(&mut self, text: &str) {
+        self.line += text.matches('\n').count();
+        self.offset += text.len();
+    }
+
+    fn output(&mut self, result: &str) {
+        let result = &*self.redactor.redact(result);
+        let diff;
+        let result = if self.format == Format::Diff {
+            diff = self.diff(result);
+            &diff
+        } else {
+            result
+        };
+        match self.format {
+            Format::Text | Format::Diff if self.label.is_none() && self.timestamps.is_none() => {
+                self.print(result)
+            }
+            Format::Text | Format::Diff => {
+                let mut prefixed = String::new();
+                for line in result.split_inclusive('\n') {
+                    if self.line_start {
+                        if let Some(timestamps) = self.timestamps {
+                            prefixed.push_str(&format!("{} ", timestamps.now()));
+                        }
+                        if let Some(label) = &self.label {
+                            prefixed.push_str(&format!("{label}: "));
+                        }
+                    }
+                    prefixed.push_str(line);
+                    self.line_start = line.ends_with('\n');
+                }
+                self.print(&prefixed)
+            }
+            Format::Markdown | Format::Html | Format::Issue => self.annotated.push_str(result),
+            Format::Sarif | Format::Json | Format::Ndjson | Format::Csv | Format::Tsv => {}
+        }
+    }
+
+    /// Lines of output paired with the lines of the input they come from, marked where changed,
+    /// along with the lines of the input left out up to the next line of output
+    fn diff(&mut self, result: &str) -> String {
+        let mut out = String::new();
+        for line in result.split_inclusive('\n') {
+            self.removed(&mut out);
+            let line = line.trim_end_matches(['\r', '\n']);
+            let Some(Original::Line(original)) = self.originals.pop_front() else {
+                match self.side_by_side {
+                    Some(width) => out.push_str(&format!("{} > {line}\n", " ".repeat(width))),
+                    None => out.push_str(&format!("+{line}\n")),
+                }
+                continue;
+            };
+            let original = self.redactor.redact(&original);
+            let original = original.trim_end_matches(['\r', '\n']);
+            let changed = original != line;
+            match self.side_by_side {
+                Some(width) => {
+                    let marker = if changed { '|' } else { ' ' };
+                    let original = align::fit(original, width, self.cjk);
+                    out.push_str(&format!("{original} {marker} {line}\n"));
+                }
+                None if changed => out.push_str(&format!("-{original}\n+{line}\n")),
+                None => out.push_str(&format!(" {line}\n")),
+            }
+        }
+        self.removed(&mut out);
+        out
+    }
+
+    /// Lines of the input left out before the next line of output
+    fn removed(&mut self, out: &mut String) {
+        while let Some(Original::Removed(original)) = self.originals.front() {
+            let original = self.redactor.redact(original);
+            let original = original.trim_end_matches(['\r', '\n']);
+            match self.side_by_side {
+                Some(width) => {
+                    out.push_str(&format!("{} <\n", align::fit(original, width, self.cjk)))
+                }
+                None => out.push_str(&format!("-{original}\n")),
+            }
+            self.originals.pop_front();
+        }
+    }
+
+    /// Write out what is held back and the summary, then flush the output
+    pub fn finish(mut self, maps: &Maps) -> Result<()> {
+        let finished = self.summarize(maps);
+        match output::flush() {
+            // the rest of output is no longer wanted, such as when piped to `head`
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.into()),
+            _ => finished,
+        }
+    }
+
+    fn summarize(&mut self, maps: &Maps) -> Result<()> {
+        if let Some(held) = self.reassembler.as_mut().and_then(Reassembler::finish) {
+            self.process_window(maps, &held);
+        }
+        if let Some(item) = self.envelopes.as_mut().and_then(Envelopes::finish) {
+            self.annotate_item(maps, item);
+        }
+        self.end_trace();
+        if let Some(result) = self.dedupe.as_mut().map(Dedupe::finish) {
+            self.output(&result);
+        }
+        match self.format {
+            Format::Text | Format::Diff | Format::Ndjson => {}
+            Format::Sarif => self.print(&sarif::render(&self.frames)?),
+            Format::Json => self.print(&json::render(&self.frames)?),
+            Format::Csv => self.print(&table::render(&self.frames, ',')),
+            Format::Tsv => self.print(&table::render(&self.frames, '\t')),
+            Format::Markdown => self.print(&report::markdown(&self.annotated, &self.frames)),
+            Format::Html => self.print(&report::html(&self.annotated, &self.frames)),
+            Format::Issue => self.print(&report::issue(
+                &self.environment,
+                &self.annotated,
+                &self.frames,
+            )),
+        }
+        if matches!(self.format, Format::Csv | Format::Tsv) {
+            if let Some(modules) = table::modules(&self.frames) {
+                self.write(false, &modules);
+            }
+            if self.annotator.codeowners.is_some() {
+                self.write(false, &table::owners(&self.frames));
+            }
+        }
+        let coverage = self.annotator.coverage.get();
+        if coverage.is_suspicious() {
+            let message = format!(
+                "{} of {} addresses fall outside the range covered by the source map, which likely comes from another build than the traceback",
+                coverage.outside, coverage.addresses
+            );
+            if self.strict {
+                bail!(message);
+            }
+            self.write(false, &format!("warning: {message}\n"));
+        }
+        let missing = self.annotator.missing.take().map(RefCell::into_inner);
+        if let Some(missing) = missing.filter(|missing| !missing.is_empty()) {
+            let frames = missing.values().sum::<usize>();
+            let mut message = format!(
+                "{frames} frames resolved to {} sources missing on disk:",
+                missing.len()
+            );
+            for (source, frames) in &missing {
+                message.push_str(&format!("\n  {source} ({frames} frames)"));
+            }
+            if self.strict {
+                bail!(message);
+            }
+            self.write(false, &format!("warning: {message}\n"));
+        }
+        if self.exit_code && coverage.addresses == 0 {
+            return Err(exit::classify(eyre!("no frames matched"), Class::NoFrames));
+        }
+        if self.exit_code && coverage.resolved < coverage.addresses {
+            let message = format!(
+                "{} of {} frames left unresolved",
+                coverage.addresses - coverage.resolved,
+                coverage.addresses
+            );
+            return Err(exit::classify(eyre!(message), Class::Unresolved));
+        }
+        Ok(())
+    }
+}
+
+// output so far is flushed even if not finished, such as on an error reading the input
+impl Drop for Filter {
+    fn drop(&mut self) {
+        _ = output::flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+    use std::sync::mpsc;
+
+    #[derive(Parser)]
+    struct Cli {
+        #[command(flatten)]
+        filter: FilterArgs,
+    }
+
+    /// Output of resolving bare addresses with `args`
+    fn resolve_addrs(args: &[&str], addrs: &[u32]) -> String {
+        let map =
+            r#"{"version":3,"sources":["src/main.mbt"],"names":["main"],"mappings":"gBACEA"}"#;
+        let maps = Maps {
+            default: SourceMap::from_slice(map.as_bytes()).unwrap(),
+            scripts: Default::default(),
+        };
+        let args = Cli::parse_from([&["mbtmap"], args].concat()).filter;
+        let (writer, written) = mpsc::sync_channel(64);
+        let mut filter = Filter::new(&args, None).unwrap().writer(writer);
+        filter.resolve_addrs(&maps, addrs);
+        filter.finish(&maps).unwrap();
+        written
+            .into_iter()
+            .filter(|(stdout, _)| *stdout)
+            .map(|(_, s)| s)
+            .collect()
+    }
+
+    #[test]
+    fn names_bare_addresses() {
+        let csv = resolve_addrs(&["--stdout", "--format", "csv"], &[0x10]);
+        let mut lines = csv.lines();
+        let header = lines.next().unwrap().split(',').collect::<Vec<_>>();
+        let row = lines.next().unwrap().split(',').collect::<Vec<_>>();
+        let name = header.iter().position(|&field| field == "name").unwrap();
+        assert_eq!(row[name], "main");
+    }
+}
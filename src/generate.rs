@@ -0,0 +1,434 @@
+use clap::{Arg, Command, ValueEnum, ValueHint};
+use std::fmt::Write;
+
+/// Shells completion script can be generated for
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+fn options(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| !arg.is_positional() && !arg.is_hide_set())
+}
+
+fn positionals(command: &Command) -> impl Iterator<Item = &Arg> {
+    command
+        .get_arguments()
+        .filter(|arg| arg.is_positional() && !arg.is_hide_set())
+}
+
+fn subcommands(command: &Command) -> impl Iterator<Item = &Command> {
+    command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set() && subcommand.get_name() != "help")
+}
+
+fn takes_value(arg: &Arg) -> bool {
+    arg.get_action().takes_values()
+}
+
+fn values(arg: &Arg) -> Vec<String> {
+    if !takes_value(arg) {
+        return Vec::new();
+    }
+    arg.get_possible_values()
+        .iter()
+        .filter(|value| !value.is_hide_set())
+        .map(|value| value.get_name().to_owned())
+        .collect()
+}
+
+fn help(arg: &Arg) -> String {
+    arg.get_help()
+        .map(|help| help.to_string())
+        .unwrap_or_default()
+}
+
+fn flags(arg: &Arg) -> Vec<String> {
+    let mut flags = Vec::new();
+    flags.extend(arg.get_short().map(|short| format!("-{short}")));
+    flags.extend(arg.get_long().map(|long| format!("--{long}")));
+    flags
+}
+
+fn is_path(arg: &Arg) -> bool {
+    values(arg).is_empty()
+        && matches!(
+            arg.get_value_hint(),
+            ValueHint::Unknown | ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+        )
+}
+
+fn bash_command(out: &mut String, command: &Command) {
+    let words = options(command)
+        .flat_map(flags)
+        .chain(subcommands(command).map(|subcommand| subcommand.get_name().to_owned()))
+        .collect::<Vec<_>>()
+        .join(" ");
+    writeln!(out, "        case \"$prev\" in").unwrap();
+    for arg in options(command).filter(|arg| takes_value(arg)) {
+        let reply = match values(arg) {
+            values if !values.is_empty() => {
+                format!("$(compgen -W \"{}\" -- \"$cur\")", values.join(" "))
+            }
+            _ => "$(compgen -f -- \"$cur\")".to_owned(),
+        };
+        writeln!(
+            out,
+            "            {}) COMPREPLY=({reply}); return ;;",
+            flags(arg).join("|")
+        )
+        .unwrap();
+    }
+    writeln!(out, "        esac").unwrap();
+    let positional = positionals(command)
+        .flat_map(values)
+        .collect::<Vec<_>>()
+        .join(" ");
+    writeln!(
+        out,
+        "        if [[ \"$cur\" == -* ]]; then COMPREPLY=($(compgen -W \"{words}\" -- \"$cur\")); return; fi"
+    )
+    .unwrap();
+    if positional.is_empty() {
+        writeln!(
+            out,
+            "        COMPREPLY=($(compgen -W \"{words}\" -- \"$cur\") $(compgen -f -- \"$cur\"))"
+        )
+        .unwrap();
+    } else {
+        writeln!(
+            out,
+            "        COMPREPLY=($(compgen -W \"{positional}\" -- \"$cur\"))"
+        )
+        .unwrap();
+    }
+}
+
+fn bash(command: &Command) -> String {
+    let name = command.get_name();
+    let function = format!("_{}", name.replace('-', "_"));
+    let mut out = String::new();
+    writeln!(out, "{function}() {{").unwrap();
+    writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"").unwrap();
+    writeln!(out, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"").unwrap();
+    writeln!(out, "    case \"${{COMP_WORDS[1]}}\" in").unwrap();
+    for subcommand in subcommands(command) {
+        writeln!(out, "    {})", subcommand.get_name()).unwrap();
+        bash_command(&mut out, subcommand);
+        writeln!(out, "        ;;").unwrap();
+    }
+    writeln!(out, "    *)").unwrap();
+    bash_command(&mut out, command);
+    writeln!(out, "        ;;\n    esac\n}}").unwrap();
+    writeln!(out, "complete -F {function} -o default {name}").unwrap();
+    out
+}
+
+fn zsh_quote(s: &str) -> String {
+    s.replace('\'', "'\\''")
+        .replace('[', "\\[")
+        .replace(']', "\\]")
+        .replace(':', "\\:")
+}
+
+fn zsh_action(arg: &Arg) -> String {
+    let name = arg
+        .get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| arg.get_id().to_string())
+        .to_lowercase();
+    let values = values(arg);
+    if !values.is_empty() {
+        format!(":{name}:({})", values.join(" "))
+    } else if is_path(arg) {
+        format!(":{name}:_files")
+    } else {
+        format!(":{name}:")
+    }
+}
+
+fn zsh_specs(command: &Command) -> Vec<String> {
+    let mut specs = Vec::new();
+    for arg in options(command) {
+        let flags = flags(arg);
+        let help = zsh_quote(&help(arg));
+        let action = if takes_value(arg) {
+            zsh_action(arg)
+        } else {
+            String::new()
+        };
+        let repeat = if matches!(arg.get_action(), clap::ArgAction::Append) {
+            "*"
+        } else {
+            ""
+        };
+        if flags.len() > 1 {
+            specs.push(format!(
+                "'{repeat}({})'{{{}}}'[{help}]{action}'",
+                flags.join(" "),
+                flags.join(",")
+            ));
+        } else {
+            specs.push(format!("'{repeat}{}[{help}]{action}'", flags[0]));
+        }
+    }
+    for (i, arg) in positionals(command).enumerate() {
+        specs.push(format!("'{}{}'", i + 1, zsh_action(arg)));
+    }
+    specs
+}
+
+fn zsh(command: &Command) -> String {
+    let name = command.get_name();
+    let mut out = String::new();
+    writeln!(out, "#compdef {name}\n").unwrap();
+    writeln!(out, "_{}() {{", name.replace('-', "_")).unwrap();
+    writeln!(
+        out,
+        "    if (( CURRENT > 2 )); then\n        case $words[2] in"
+    )
+    .unwrap();
+    for subcommand in subcommands(command) {
+        writeln!(out, "        {})", subcommand.get_name()).unwrap();
+        writeln!(out, "            shift words; (( CURRENT-- ))").unwrap();
+        writeln!(
+            out,
+            "            _arguments -s {}\n            return ;;",
+            zsh_specs(subcommand).join(" ")
+        )
+        .unwrap();
+    }
+    writeln!(out, "        esac\n    fi").unwrap();
+    let commands = subcommands(command)
+        .map(|subcommand| {
+            format!(
+                "'{}:{}'",
+                subcommand.get_name(),
+                zsh_quote(
+                    &subcommand
+                        .get_about()
+                        .map(|about| about.to_string())
+                        .unwrap_or_default()
+                )
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" ");
+    writeln!(
+        out,
+        "    if (( CURRENT == 2 )) && [[ $words[2] != -* ]]; then"
+    )
+    .unwrap();
+    writeln!(out, "        local -a commands=({commands})").unwrap();
+    writeln!(out, "        _describe command commands").unwrap();
+    writeln!(out, "    fi").unwrap();
+    writeln!(out, "    _arguments -s {}", zsh_specs(command).join(" ")).unwrap();
+    writeln!(out, "}}\n\n_{} \"$@\"", name.replace('-', "_")).unwrap();
+    out
+}
+
+fn fish_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+fn fish_command(out: &mut String, name: &str, condition: &str, command: &Command) {
+    for arg in options(command) {
+        write!(out, "complete -c {name} -n {}", fish_quote(condition)).unwrap();
+        if let Some(short) = arg.get_short() {
+            write!(out, " -s {short}").unwrap();
+        }
+        if let Some(long) = arg.get_long() {
+            write!(out, " -l {long}").unwrap();
+        }
+        if takes_value(arg) {
+            let values = values(arg);
+            if !values.is_empty() {
+                write!(out, " -x -a {}", fish_quote(&values.join(" "))).unwrap();
+            } else if is_path(arg) {
+                write!(out, " -r -F").unwrap();
+            } else {
+                write!(out, " -x").unwrap();
+            }
+        }
+        writeln!(out, " -d {}", fish_quote(&help(arg))).unwrap();
+    }
+    for arg in positionals(command) {
+        let values = values(arg);
+        if !values.is_empty() {
+            writeln!(
+                out,
+                "complete -c {name} -n {} -x -a {}",
+                fish_quote(condition),
+                fish_quote(&values.join(" "))
+            )
+            .unwrap();
+        }
+    }
+}
+
+fn fish(command: &Command) -> String {
+    let name = command.get_name();
+    let mut out = String::new();
+    let names = subcommands(command)
+        .map(|subcommand| subcommand.get_name())
+        .collect::<Vec<_>>()
+        .join(" ");
+    fish_command(
+        &mut out,
+        name,
+        &format!("not __fish_seen_subcommand_from {names}"),
+        command,
+    );
+    for subcommand in subcommands(command) {
+        writeln!(
+            out,
+            "complete -c {name} -n '__fish_use_subcommand' -f -a {} -d {}",
+            subcommand.get_name(),
+            fish_quote(
+                &subcommand
+                    .get_about()
+                    .map(|about| about.to_string())
+                    .unwrap_or_default()
+            )
+        )
+        .unwrap();
+        fish_command(
+            &mut out,
+            name,
+            &format!("__fish_seen_subcommand_from {}", subcommand.get_name()),
+            subcommand,
+        );
+    }
+    out
+}
+
+/// Completion script of the command for the shell
+pub fn completions(command: &Command, shell: Shell) -> String {
+    match shell {
+        Shell::Bash => bash(command),
+        Shell::Zsh => zsh(command),
+        Shell::Fish => fish(command),
+    }
+}
+
+fn roff(s: &str) -> String {
+    let s = s.replace('\\', "\\\\").replace('-', "\\-");
+    if s.starts_with('.') || s.starts_with('\'') {
+        format!("\\&{s}")
+    } else {
+        s
+    }
+}
+
+fn value_name(arg: &Arg) -> String {
+    arg.get_value_names()
+        .and_then(|names| names.first())
+        .map(|name| name.to_string())
+        .unwrap_or_else(|| arg.get_id().to_string().to_uppercase())
+}
+
+fn synopsis(command: &Command, prefix: &str) -> String {
+    let mut synopsis = format!(
+        "\\fB{}\\fR",
+        roff(&format!("{prefix}{}", command.get_name()))
+    );
+    if options(command).next().is_some() {
+        synopsis.push_str(" [\\fIOPTIONS\\fR]");
+    }
+    for arg in positionals(command) {
+        let name = roff(&value_name(arg));
+        if arg.is_required_set() {
+            write!(synopsis, " <\\fI{name}\\fR>").unwrap();
+        } else {
+            write!(synopsis, " [\\fI{name}\\fR]").unwrap();
+        }
+    }
+    synopsis
+}
+
+fn roff_args(out: &mut String, command: &Command) {
+    for arg in options(command).chain(positionals(command)) {
+        out.push_str(".TP\n");
+        if arg.is_positional() {
+            writeln!(out, "<\\fI{}\\fR>", roff(&value_name(arg))).unwrap();
+        } else {
+            let mut line = flags(arg)
+                .iter()
+                .map(|flag| format!("\\fB{}\\fR", roff(flag)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if takes_value(arg) {
+                write!(line, " <\\fI{}\\fR>", roff(&value_name(arg))).unwrap();
+            }
+            writeln!(out, "{line}").unwrap();
+        }
+        writeln!(out, "{}", roff(&help(arg))).unwrap();
+        let values = arg
+            .get_possible_values()
+            .into_iter()
+            .filter(|value| takes_value(arg) && !value.is_hide_set())
+            .collect::<Vec<_>>();
+        if !values.is_empty() {
+            out.push_str(".RS\n");
+            for value in values {
+                write!(out, ".IP \\(bu 2\n\\fB{}\\fR", roff(value.get_name())).unwrap();
+                if let Some(help) = value.get_help() {
+                    write!(out, ": {}", roff(&help.to_string())).unwrap();
+                }
+                out.push('\n');
+            }
+            out.push_str(".RE\n");
+        }
+    }
+}
+
+/// Manual page of the command in roff
+pub fn manpage(command: &Command) -> String {
+    let name = command.get_name();
+    let version = command.get_version().unwrap_or_default();
+    let about = command
+        .get_about()
+        .map(|about| about.to_string())
+        .unwrap_or_default();
+    let mut out = String::new();
+    writeln!(
+        out,
+        ".TH {} 1 \"\" \"{} {}\"",
+        roff(&name.to_uppercase()),
+        roff(name),
+        roff(version)
+    )
+    .unwrap();
+    writeln!(out, ".SH NAME\n{} \\- {}", roff(name), roff(&about)).unwrap();
+    writeln!(out, ".SH SYNOPSIS\n{}", synopsis(command, "")).unwrap();
+    for subcommand in subcommands(command) {
+        writeln!(out, ".br\n{}", synopsis(subcommand, &format!("{name} "))).unwrap();
+    }
+    if let Some(long_about) = command.get_long_about() {
+        writeln!(out, ".SH DESCRIPTION\n{}", roff(&long_about.to_string())).unwrap();
+    }
+    out.push_str(".SH OPTIONS\n");
+    roff_args(&mut out, command);
+    for subcommand in subcommands(command) {
+        writeln!(
+            out,
+            ".SH \"{}\"",
+            roff(&format!("{name} {}", subcommand.get_name()))
+        )
+        .unwrap();
+        if let Some(about) = subcommand.get_about() {
+            writeln!(out, "{}", roff(&about.to_string())).unwrap();
+        }
+        roff_args(&mut out, subcommand);
+    }
+    if !version.is_empty() {
+        writeln!(out, ".SH VERSION\nv{}", roff(version)).unwrap();
+    }
+    out
+}
@@ -0,0 +1,191 @@
+//! Age of resolved lines against the commit a build comes from, telling lines changed or moved
+//! since in the working tree of the repository, and the commits last changing them
+
+use eyre::{bail, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Lines of the build commit replaced by lines of the working tree, with the start of `old` being
+/// the line inserted after if it is empty
+struct Hunk {
+    old: (u32, u32),
+    new: u32,
+}
+
+/// How a line of the build commit stands in the working tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Age {
+    Unchanged,
+    /// Lines before were added or removed, shifting it to the line
+    Moved(u32),
+    Changed,
+}
+
+/// Repository and the commit the build comes from
+pub struct Git {
+    root: PathBuf,
+    /// Commit as given, to print
+    pub commit: String,
+    /// Full hash of the commit, to diff against
+    hash: String,
+    /// Hunks of the files diffed so far, `None` for those outside the repository
+    hunks: RefCell<HashMap<PathBuf, Option<Vec<Hunk>>>>,
+}
+
+/// Last commit changing each line resolved, blamed at the build commit if known
+pub struct Blame {
+    /// Repository and the build commit, or otherwise the working tree of each source
+    git: Option<(PathBuf, String)>,
+    lines: RefCell<HashMap<(PathBuf, u32), Option<String>>>,
+    /// Short hash, author and date of each commit
+    commits: RefCell<HashMap<String, String>>,
+}
+
+/// Absolute path of the source, taken relative to `cwd` or the current directory
+pub(crate) fn source_path(source: &str, cwd: &Option<PathBuf>) -> Option<PathBuf> {
+    match cwd {
+        Some(cwd) => Some(cwd.join(source)),
+        None => std::path::absolute(source).ok(),
+    }
+}
+
+fn git(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(root)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Start and count of a side of `@@ -a,b +c,d @@`, the count being 1 if left out
+fn range(range: &str) -> Option<(u32, u32)> {
+    Some(match range.split_once(',') {
+        Some((start, count)) => (start.parse().ok()?, count.parse().ok()?),
+        None => (range.parse().ok()?, 1),
+    })
+}
+
+fn parse_hunks(diff: &str) -> Vec<Hunk> {
+    diff.lines()
+        .filter_map(|line| {
+            let mut ranges = line.strip_prefix("@@ -")?.split(' ');
+            let old = range(ranges.next()?)?;
+            let new = range(ranges.next()?.strip_prefix('+')?)?;
+            Some(Hunk { old, new: new.1 })
+        })
+        .collect()
+}
+
+impl Git {
+    pub fn new(root: &Path, commit: &str) -> Result<Self> {
+        let hash = git(
+            root,
+            &["rev-parse", "--verify", &format!("{commit}^{{commit}}")],
+        )?;
+        Ok(Self {
+            root: root.to_owned(),
+            commit: commit.to_owned(),
+            hash: hash.trim().to_owned(),
+            hunks: RefCell::default(),
+        })
+    }
+
+    /// Age of the line of the source, taken relative to `cwd` or the current directory
+    pub fn age(&self, source: &str, line: u32, cwd: &Option<PathBuf>) -> Age {
+        let Some(path) = source_path(source, cwd) else {
+            return Age::Unchanged;
+        };
+        let mut hunks = self.hunks.borrow_mut();
+        let hunks = hunks.entry(path).or_insert_with_key(|path| {
+            let path = path.to_str()?;
+            // a path outside the repository fails, taken as not tracked
+            let diff = git(
+                &self.root,
+                &[
+                    "diff",
+                    "--no-color",
+                    "--no-ext-diff",
+                    "-U0",
+                    &self.hash,
+                    "--",
+                    path,
+                ],
+            );
+            diff.ok().map(|diff| parse_hunks(&diff))
+        });
+        let mut shift = 0i64;
+        for hunk in hunks.iter().flatten() {
+            let (start, count) = hunk.old;
+            if count > 0 && (start..start + count).contains(&line) {
+                return Age::Changed;
+            }
+            let before = if count == 0 {
+                start < line
+            } else {
+                start + count <= line
+            };
+            if before {
+                shift += i64::from(hunk.new) - i64::from(count);
+            }
+        }
+        match u32::try_from(i64::from(line) + shift) {
+            Ok(moved) if moved != line => Age::Moved(moved),
+            _ => Age::Unchanged,
+        }
+    }
+}
+
+impl Blame {
+    pub fn new(git: Option<&Git>) -> Self {
+        Self {
+            git: git.map(|git| (git.root.clone(), git.hash.clone())),
+            lines: RefCell::default(),
+            commits: RefCell::default(),
+        }
+    }
+
+    /// Short hash, author and date of the commit last changing the line of the source, or `None`
+    /// if not tracked
+    pub fn blame(&self, source: &str, line: u32, cwd: &Option<PathBuf>) -> Option<String> {
+        let path = source_path(source, cwd)?;
+        self.lines
+            .borrow_mut()
+            .entry((path, line))
+            .or_insert_with_key(|(path, line)| self.run(path, *line))
+            .clone()
+    }
+
+    fn run(&self, path: &Path, line: u32) -> Option<String> {
+        let range = format!("{line},{line}");
+        let (dir, revision) = match &self.git {
+            Some((root, hash)) => (root.as_path(), Some(hash.as_str())),
+            None => (path.parent()?, None),
+        };
+        let mut args = vec!["blame", "--porcelain", "-L", &range];
+        args.extend(revision);
+        args.extend(["--", path.to_str()?]);
+        let porcelain = git(dir, &args).ok()?;
+        let hash = porcelain.split_whitespace().next()?;
+        if hash.bytes().all(|b| b == b'0') {
+            return Some("not committed yet".to_owned());
+        }
+        let mut commits = self.commits.borrow_mut();
+        if let Some(commit) = commits.get(hash) {
+            return Some(commit.clone());
+        }
+        let commit = git(dir, &["show", "-s", "--format=%h %an %as", hash]).ok()?;
+        let commit = commit.trim().to_owned();
+        commits.insert(hash.to_owned(), commit.clone());
+        Some(commit)
+    }
+}
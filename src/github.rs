@@ -0,0 +1,46 @@
+use crate::Location;
+use std::fmt::Write;
+use std::path::{Component, Path};
+
+/// Repository and commit used to build permalinks to resolved locations
+#[derive(Debug, Clone)]
+pub struct GitHub {
+    pub repo: String,
+    pub reference: String,
+}
+
+fn encode_path(path: &str) -> String {
+    let mut out = String::with_capacity(path.len());
+    for b in path.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                out.push(b as char)
+            }
+            b'\\' => out.push('/'),
+            b => write!(out, "%{b:02X}").unwrap(),
+        }
+    }
+    out
+}
+
+impl GitHub {
+    /// Link to the resolved line, only available for paths relative to the repository
+    pub fn permalink(&self, location: &Location) -> Option<String> {
+        let path = Path::new(&location.source);
+        if path.is_absolute()
+            || path
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return None;
+        }
+        let path = location.source.trim_start_matches("./");
+        Some(format!(
+            "https://github.com/{}/blob/{}/{}#L{}",
+            self.repo,
+            self.reference,
+            encode_path(path),
+            location.line
+        ))
+    }
+}
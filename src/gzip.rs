@@ -0,0 +1,486 @@
+//! Minimal gzip, compressing with LZ77 and fixed Huffman codes, decompressing any DEFLATE stream
+
+use eyre::{bail, eyre, Result};
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DIST_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DIST_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order of code length code lengths in the header of a dynamic block
+const CLEN_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+const WINDOW: usize = 32768;
+const MAX_MATCH: usize = 258;
+const MAX_CHAIN: usize = 64;
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut table = [0u32; 256];
+    for (i, entry) in table.iter_mut().enumerate() {
+        let mut c = i as u32;
+        for _ in 0..8 {
+            c = if c & 1 != 0 {
+                0xedb88320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+        }
+        *entry = c;
+    }
+    !data.iter().fold(!0u32, |c, &b| {
+        table[((c ^ b as u32) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+#[derive(Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    bits: u64,
+    count: u32,
+}
+
+impl BitWriter {
+    fn write(&mut self, value: u32, count: u32) {
+        self.bits |= (value as u64) << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.bits as u8);
+            self.bits >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Huffman codes are packed starting from the most significant bit
+    fn code(&mut self, code: u32, len: u32) {
+        self.write(code.reverse_bits() >> (32 - len), len);
+    }
+
+    fn literal(&mut self, symbol: u32) {
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn matched(&mut self, len: usize, dist: usize) {
+        let i = LENGTH_BASE.partition_point(|&base| base as usize <= len) - 1;
+        self.literal(257 + i as u32);
+        self.write(
+            (len - LENGTH_BASE[i] as usize) as u32,
+            LENGTH_EXTRA[i] as u32,
+        );
+        let i = DIST_BASE.partition_point(|&base| base as usize <= dist) - 1;
+        self.code(i as u32, 5);
+        self.write((dist - DIST_BASE[i] as usize) as u32, DIST_EXTRA[i] as u32);
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.count > 0 {
+            self.out.push(self.bits as u8);
+        }
+        self.out
+    }
+}
+
+fn hash(data: &[u8], i: usize) -> usize {
+    ((data[i] as usize) << 10 ^ (data[i + 1] as usize) << 5 ^ data[i + 2] as usize) & (WINDOW - 1)
+}
+
+/// Chain position `i` into the hash table of previous positions
+fn insert(data: &[u8], i: usize, head: &mut [usize], prev: &mut [usize]) {
+    if i + 3 <= data.len() {
+        let h = hash(data, i);
+        prev[i] = head[h];
+        head[h] = i;
+    }
+}
+
+fn deflate(data: &[u8]) -> Vec<u8> {
+    let mut w = BitWriter::default();
+    w.write(1, 1);
+    w.write(1, 2);
+    let mut head = vec![usize::MAX; WINDOW];
+    let mut prev = vec![usize::MAX; data.len()];
+    let mut i = 0;
+    while i < data.len() {
+        let (mut best_len, mut best_dist) = (0, 0);
+        if i + 3 <= data.len() {
+            let mut candidate = head[hash(data, i)];
+            let max = MAX_MATCH.min(data.len() - i);
+            for _ in 0..MAX_CHAIN {
+                if candidate == usize::MAX || i - candidate > WINDOW {
+                    break;
+                }
+                let len = (0..max)
+                    .take_while(|&k| data[candidate + k] == data[i + k])
+                    .count();
+                if len > best_len {
+                    (best_len, best_dist) = (len, i - candidate);
+                    if len == max {
+                        break;
+                    }
+                }
+                candidate = prev[candidate];
+            }
+        }
+        if best_len >= 3 {
+            w.matched(best_len, best_dist);
+            for k in i..i + best_len {
+                insert(data, k, &mut head, &mut prev);
+            }
+            i += best_len;
+        } else {
+            w.literal(data[i] as u32);
+            insert(data, i, &mut head, &mut prev);
+            i += 1;
+        }
+    }
+    w.literal(256);
+    w.finish()
+}
+
+pub fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    out.extend(deflate(data));
+    out.extend(crc32(data).to_le_bytes());
+    out.extend((data.len() as u32).to_le_bytes());
+    out
+}
+
+pub fn is_gzip(data: &[u8]) -> bool {
+    data.starts_with(&[0x1f, 0x8b])
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Result<u32> {
+        let byte = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| eyre!("unexpected end of gzip stream"))?;
+        let b = (byte >> self.bit) & 1;
+        self.bit += 1;
+        if self.bit == 8 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+        Ok(b as u32)
+    }
+
+    fn bits(&mut self, count: u32) -> Result<u32> {
+        let mut value = 0;
+        for i in 0..count {
+            value |= self.bit()? << i;
+        }
+        Ok(value)
+    }
+
+    fn align(&mut self) {
+        if self.bit != 0 {
+            self.bit = 0;
+            self.pos += 1;
+        }
+    }
+}
+
+/// Canonical Huffman code by number of codes of each length and symbols in code order
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut offsets = [0u16; 16];
+        for len in 1..16 {
+            offsets[len] = offsets[len - 1] + counts[len - 1];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Self { counts, symbols }
+    }
+
+    fn decode(&self, r: &mut BitReader) -> Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for len in 1..16 {
+            code |= r.bit()? as i32;
+            let count = self.counts[len] as i32;
+            if code - count < first {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        bail!("invalid Huffman code in gzip stream")
+    }
+}
+
+fn fixed() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+}
+
+fn dynamic(r: &mut BitReader) -> Result<(Huffman, Huffman)> {
+    let nlen = r.bits(5)? as usize + 257;
+    let ndist = r.bits(5)? as usize + 1;
+    let ncode = r.bits(4)? as usize + 4;
+    let mut clens = [0u8; 19];
+    for &i in &CLEN_ORDER[..ncode] {
+        clens[i] = r.bits(3)? as u8;
+    }
+    let clens = Huffman::new(&clens);
+    let mut lengths = Vec::with_capacity(nlen + ndist);
+    while lengths.len() < nlen + ndist {
+        let (len, repeat) = match clens.decode(r)? {
+            len @ 0..=15 => (len as u8, 1),
+            16 => (
+                *lengths
+                    .last()
+                    .ok_or_else(|| eyre!("repeat without previous length in gzip stream"))?,
+                3 + r.bits(2)?,
+            ),
+            17 => (0, 3 + r.bits(3)?),
+            _ => (0, 11 + r.bits(7)?),
+        };
+        lengths.extend(std::iter::repeat_n(len, repeat as usize));
+    }
+    if lengths.len() > nlen + ndist {
+        bail!("too many code lengths in gzip stream");
+    }
+    Ok((
+        Huffman::new(&lengths[..nlen]),
+        Huffman::new(&lengths[nlen..]),
+    ))
+}
+
+fn inflate(r: &mut BitReader) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let last = r.bit()?;
+        let (lit, dist) = match r.bits(2)? {
+            0 => {
+                r.align();
+                let header = r
+                    .data
+                    .get(r.pos..r.pos + 4)
+                    .ok_or_else(|| eyre!("unexpected end of gzip stream"))?;
+                let len = u16::from_le_bytes([header[0], header[1]]) as usize;
+                r.pos += 4;
+                let block = r
+                    .data
+                    .get(r.pos..r.pos + len)
+                    .ok_or_else(|| eyre!("unexpected end of gzip stream"))?;
+                out.extend_from_slice(block);
+                r.pos += len;
+                if last == 1 {
+                    return Ok(out);
+                }
+                continue;
+            }
+            1 => fixed(),
+            2 => dynamic(r)?,
+            _ => bail!("invalid block type in gzip stream"),
+        };
+        loop {
+            let symbol = lit.decode(r)? as usize;
+            match symbol {
+                0..=255 => out.push(symbol as u8),
+                256 => break,
+                _ => {
+                    let i = symbol - 257;
+                    if i >= LENGTH_BASE.len() {
+                        bail!("invalid length in gzip stream");
+                    }
+                    let len = LENGTH_BASE[i] as usize + r.bits(LENGTH_EXTRA[i] as u32)? as usize;
+                    let i = dist.decode(r)? as usize;
+                    if i >= DIST_BASE.len() {
+                        bail!("invalid distance in gzip stream");
+                    }
+                    let d = DIST_BASE[i] as usize + r.bits(DIST_EXTRA[i] as u32)? as usize;
+                    if d > out.len() {
+                        bail!("distance too far back in gzip stream");
+                    }
+                    let start = out.len() - d;
+                    for k in 0..len {
+                        out.push(out[start + k]);
+                    }
+                }
+            }
+        }
+        if last == 1 {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decompress a gzip stream, the output of its members concatenated as `gunzip` does
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut rest = data;
+    loop {
+        rest = &rest[member(rest, &mut out)?..];
+        if rest.is_empty() {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decompress the member at the start of `data` into `out`, returning its length
+fn member(data: &[u8], out: &mut Vec<u8>) -> Result<usize> {
+    if data.len() < 18 || !is_gzip(data) || data[2] != 8 {
+        bail!("not a gzip stream");
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & 4 != 0 {
+        let len = u16::from_le_bytes([data[pos], data[pos + 1]]) as usize;
+        pos += 2 + len;
+    }
+    for flag in [8, 16] {
+        if flags & flag != 0 {
+            pos += data
+                .get(pos..)
+                .unwrap_or_default()
+                .iter()
+                .position(|&b| b == 0)
+                .ok_or_else(|| eyre!("unterminated gzip header"))?
+                + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        pos += 2;
+    }
+    let mut reader = BitReader {
+        data: data
+            .get(pos..)
+            .ok_or_else(|| eyre!("truncated gzip header"))?,
+        pos: 0,
+        bit: 0,
+    };
+    let inflated = inflate(&mut reader)?;
+    reader.align();
+    let trailer = reader
+        .data
+        .get(reader.pos..reader.pos + 8)
+        .ok_or_else(|| eyre!("missing gzip trailer"))?;
+    if u32::from_le_bytes(trailer[..4].try_into().unwrap()) != crc32(&inflated) {
+        bail!("gzip checksum mismatch");
+    }
+    if u32::from_le_bytes(trailer[4..].try_into().unwrap()) != inflated.len() as u32 {
+        bail!("gzip length mismatch");
+    }
+    out.extend(inflated);
+    Ok(pos + reader.pos + 8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::HEXLOWER;
+
+    fn round_trip(data: &[u8]) {
+        let compressed = compress(data);
+        assert!(is_gzip(&compressed));
+        assert_eq!(decompress(&compressed).unwrap(), data);
+    }
+
+    #[test]
+    fn round_trips() {
+        round_trip(b"");
+        round_trip(b"a");
+        round_trip(b"hello hello hello hello\n");
+        // matches reaching back across the whole window, and runs longer than the longest match
+        let repeated = b"at main (wasm://wasm/001ac3c6:wasm-function[10]:0x2648d)\n".repeat(2000);
+        round_trip(&repeated);
+        round_trip(&[0; 3 * MAX_MATCH + 1]);
+        let mut state = 1u32;
+        let noise = (0..100_000)
+            .map(|_| {
+                state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+                (state >> 24) as u8
+            })
+            .collect::<Vec<_>>();
+        round_trip(&noise);
+    }
+
+    #[test]
+    fn multiple_members() {
+        let mut stream = compress(b"first\n");
+        stream.extend(compress(b""));
+        stream.extend(compress(b"second\n"));
+        assert_eq!(decompress(&stream).unwrap(), b"first\nsecond\n");
+    }
+
+    #[test]
+    fn decompresses_gzip() {
+        // `gzip` with the name of the file in the header, a block of fixed codes
+        let fixed = HEXLOWER
+            .decode(b"1f8b08080000000002ff612e74787400cb48cdc9c957c840905c003b7c8adf12000000")
+            .unwrap();
+        assert_eq!(decompress(&fixed).unwrap(), b"hello hello hello\n");
+        // a block of dynamic codes
+        let dynamic = HEXLOWER
+            .decode(
+                b"1f8b08000000000002030dcc311240301005d0de29fe8c86192ea15771009b58644836b33685db4bf99ad762164953302c52d43366ca99155d741629f74db35ee185893c38c263ac154a9e1df91b27275632dee13e6cb14ed092309edb002a26912c78fa01ace2aab464000000",
+            )
+            .unwrap();
+        assert_eq!(
+            decompress(&dynamic).unwrap(),
+            b"# MoonBit Source Mapper (mbtmap)\n\nThis tool filters traceback generated by `moon run -g`, automatica",
+        );
+        let mut both = fixed.clone();
+        both.extend(&dynamic);
+        assert!(decompress(&both)
+            .unwrap()
+            .starts_with(b"hello hello hello\n# MoonBit"));
+    }
+
+    #[test]
+    fn rejects_corrupt() {
+        let mut stream = compress(b"hello hello hello\n");
+        let crc = stream.len() - 8;
+        stream[crc] ^= 1;
+        assert!(decompress(&stream).is_err());
+        assert!(decompress(b"hello").is_err());
+        let mut stream = compress(b"hello");
+        stream.truncate(stream.len() - 4);
+        assert!(decompress(&stream).is_err());
+        let mut stream = compress(b"hello");
+        stream.extend(b"trailing garbage");
+        assert!(decompress(&stream).is_err());
+    }
+}
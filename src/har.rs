@@ -0,0 +1,83 @@
+//! HAR exports with the wasm frames of tracebacks anywhere in them annotated in place
+//!
+//! Every string of the archive is annotated, so that tracebacks are found in request and response
+//! bodies, WebSocket messages and the console entries some browsers export, with bodies encoded
+//! in base64 decoded first and encoded again, and the strings of JSON bodies annotated in turn.
+
+use crate::preset::Preset;
+use crate::resolver::Annotator;
+use data_encoding::BASE64;
+use eyre::Result;
+use serde_json::Value;
+use sourcemap::SourceMap;
+use std::path::PathBuf;
+
+/// Annotate the frames of each string in the archive, sources relative to `cwd` if given,
+/// returning how many are resolved
+pub fn annotate(
+    har: &mut Value,
+    map: &SourceMap,
+    preset: Preset,
+    cwd: Option<PathBuf>,
+) -> Result<usize> {
+    let mut annotator = Annotator::new(preset, false)?;
+    annotator.cwd = cwd;
+    let mut resolved = 0;
+    walk(har, &mut |text| {
+        let annotated = annotator.annotate(map, text, |_| resolved += 1);
+        (annotated != text).then_some(annotated)
+    });
+    Ok(resolved)
+}
+
+fn walk(value: &mut Value, f: &mut dyn FnMut(&str) -> Option<String>) {
+    match value {
+        Value::String(text) => {
+            // JSON bodies, such as logs sent to a backend, have their strings annotated instead
+            let nested = text
+                .starts_with(['{', '['])
+                .then(|| serde_json::from_str::<Value>(text).ok())
+                .flatten();
+            if let Some(mut nested) = nested {
+                let mut changed = false;
+                walk(&mut nested, &mut |text: &str| {
+                    let annotated = f(text);
+                    changed |= annotated.is_some();
+                    annotated
+                });
+                if changed {
+                    *text = nested.to_string();
+                }
+            } else if let Some(annotated) = f(text) {
+                *text = annotated;
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| walk(value, f)),
+        Value::Object(object) => {
+            if object.get("encoding").and_then(Value::as_str) == Some("base64") {
+                let decoded = object
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .and_then(|text| BASE64.decode(text.as_bytes()).ok())
+                    .and_then(|bytes| String::from_utf8(bytes).ok());
+                if let Some(annotated) = decoded.and_then(|text| f(&text)) {
+                    object.insert(
+                        "text".to_owned(),
+                        BASE64.encode(annotated.as_bytes()).into(),
+                    );
+                    if let Some(size) = object.get_mut("size") {
+                        *size = annotated.len().into();
+                    }
+                }
+                for (key, value) in object.iter_mut() {
+                    if key != "text" {
+                        walk(value, f);
+                    }
+                }
+            } else {
+                object.values_mut().for_each(|value| walk(value, f));
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,556 @@
+//! HTTP/1.1 client for fetching maps and bundles from artifact servers
+//!
+//! Plain `http://` is spoken over a socket of its own, while `https://` requests are made by
+//! `curl`, bringing TLS verified against the trust store of the system, each request still sent
+//! and its redirects followed here.
+
+use crate::exit::{Class, Classify};
+use crate::{object, source_map};
+use data_encoding::BASE64;
+use eyre::{bail, eyre, Report, Result};
+use sourcemap::SourceMap;
+use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+const MAX_REDIRECTS: usize = 5;
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+pub const DEFAULT_RETRIES: u32 = 2;
+/// Delay before the first retry, doubled before each following one
+const BACKOFF: Duration = Duration::from_secs(1);
+
+pub fn is_url(s: &str) -> bool {
+    s.starts_with("http://") || s.starts_with("https://")
+}
+
+#[derive(Debug, Default)]
+pub struct Client {
+    /// Extra request headers, sent only to the host first requested
+    pub headers: Vec<(String, String)>,
+    /// Bearer token sent as `Authorization`, only to the host first requested
+    pub token: Option<String>,
+    /// Whether to send the token and credentials over plaintext `http://` too, rather than only
+    /// over `https://`
+    pub plaintext_auth: bool,
+    /// Credentials by machine from netrc
+    pub netrc: Vec<Credential>,
+    /// HTTP proxy to send `http://` requests through
+    pub proxy: Option<Url>,
+    /// HTTP proxy to send `https://` requests through
+    pub https_proxy: Option<Url>,
+    /// Host suffixes bypassing the proxy
+    pub no_proxy: Vec<String>,
+    /// Time limit of each attempt, from connecting to reading the whole response
+    pub timeout: Option<Duration>,
+    /// Number of retries after connection failures, timeouts and server errors
+    pub retries: u32,
+    /// Called with each warning, such as of a failed attempt about to be retried, which are
+    /// otherwise dropped
+    pub warn: Option<fn(&str)>,
+}
+
+pub(crate) enum Failure {
+    Transient(Report),
+    Fatal(Report),
+}
+
+#[derive(Debug, Clone)]
+pub struct Credential {
+    /// Machine name, `None` for the `default` entry
+    pub machine: Option<String>,
+    pub login: String,
+    pub password: String,
+}
+
+/// Entries of a netrc file, `macdef` macros skipped
+pub fn parse_netrc(s: &str) -> Vec<Credential> {
+    let mut credentials = Vec::new();
+    let mut tokens = s.split_whitespace();
+    let mut current: Option<Credential> = None;
+    while let Some(token) = tokens.next() {
+        let machine = match token {
+            "machine" => Some(tokens.next().unwrap_or_default().to_owned()),
+            "default" => None,
+            "login" | "password" | "account" => {
+                let value = tokens.next().unwrap_or_default().to_owned();
+                if let Some(credential) = &mut current {
+                    match token {
+                        "login" => credential.login = value,
+                        "password" => credential.password = value,
+                        _ => {}
+                    }
+                }
+                continue;
+            }
+            // a macro runs until an empty line, which whitespace splitting loses, so stop here
+            "macdef" => break,
+            _ => continue,
+        };
+        credentials.extend(current.take());
+        current = Some(Credential {
+            machine,
+            login: String::new(),
+            password: String::new(),
+        });
+    }
+    credentials.extend(current);
+    credentials
+}
+
+/// Argument of a `curl` config file, quoted with backslashes and quotes escaped
+fn curl_quote(arg: &str) -> String {
+    format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+fn basic(login: &str, password: &str) -> String {
+    format!(
+        "Basic {}",
+        BASE64.encode(format!("{login}:{password}").as_bytes())
+    )
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+fn dechunk(mut body: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    loop {
+        let line = body
+            .windows(2)
+            .position(|w| w == b"\r\n")
+            .ok_or_else(|| eyre!("malformed chunked response"))?;
+        let size = String::from_utf8_lossy(&body[..line]);
+        let size = size.split(';').next().unwrap_or_default().trim();
+        let size = usize::from_str_radix(size, 16)?;
+        body = &body[line + 2..];
+        if size == 0 {
+            return Ok(out);
+        }
+        if body.len() < size {
+            bail!("truncated chunked response");
+        }
+        out.extend_from_slice(&body[..size]);
+        body = body.get(size + 2..).unwrap_or_default();
+    }
+}
+
+/// Whether the whole body of its `Content-Length` has been read, for servers keeping the
+/// connection open regardless of `Connection: close`
+fn complete(raw: &[u8]) -> bool {
+    let Some(end) = raw.windows(4).position(|w| w == b"\r\n\r\n") else {
+        return false;
+    };
+    let len = String::from_utf8_lossy(&raw[..end])
+        .split("\r\n")
+        .filter_map(|line| line.split_once(':'))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.trim().parse::<usize>().ok());
+    len.is_some_and(|len| raw.len() >= end + 4 + len)
+}
+
+fn parse(raw: &[u8]) -> Result<Response> {
+    let end = raw
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .ok_or_else(|| eyre!("malformed HTTP response"))?;
+    let head = String::from_utf8_lossy(&raw[..end]);
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split(' ').nth(1))
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(|| eyre!("malformed HTTP status line"))?;
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_owned(), value.trim().to_owned()))
+        .collect();
+    let mut response = Response {
+        status,
+        headers,
+        body: raw[end + 4..].to_vec(),
+    };
+    if response
+        .header("transfer-encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        response.body = dechunk(&response.body)?;
+    } else if let Some(len) = response.header("content-length") {
+        response.body.truncate(len.parse()?);
+    }
+    Ok(response)
+}
+
+impl Client {
+    /// Client with bearer token from `MBTMAP_TOKEN`, credentials from `$NETRC` or `~/.netrc`, and
+    /// proxy from `http_proxy` and `no_proxy`
+    pub fn from_env() -> Self {
+        let var = |names: &[&str]| names.iter().find_map(|name| env::var(name).ok());
+        let netrc = var(&["NETRC"]).map(PathBuf::from).or_else(|| {
+            var(&["HOME", "USERPROFILE"]).map(|home| PathBuf::from(home).join(".netrc"))
+        });
+        Self {
+            headers: Vec::new(),
+            token: var(&["MBTMAP_TOKEN"]),
+            plaintext_auth: false,
+            netrc: netrc
+                .and_then(|netrc| fs::read_to_string(netrc).ok())
+                .map(|netrc| parse_netrc(&netrc))
+                .unwrap_or_default(),
+            proxy: var(&["http_proxy", "HTTP_PROXY"])
+                .filter(|proxy| !proxy.is_empty())
+                .and_then(|proxy| Url::parse(&proxy).ok()),
+            https_proxy: var(&["https_proxy", "HTTPS_PROXY"])
+                .filter(|proxy| !proxy.is_empty())
+                .and_then(|proxy| Url::parse(&proxy).ok()),
+            no_proxy: var(&["no_proxy", "NO_PROXY"])
+                .map(|hosts| {
+                    hosts
+                        .split(',')
+                        .map(|host| host.trim().trim_start_matches('.').to_owned())
+                        .filter(|host| !host.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            timeout: Some(DEFAULT_TIMEOUT),
+            retries: DEFAULT_RETRIES,
+            warn: None,
+        }
+    }
+
+    fn warning(&self, message: &str) {
+        if let Some(warn) = self.warn {
+            warn(message);
+        }
+    }
+
+    fn remaining(&self, deadline: Option<Instant>) -> io::Result<Option<Duration>> {
+        match deadline {
+            None => Ok(None),
+            Some(deadline) => deadline
+                .checked_duration_since(Instant::now())
+                .filter(|remaining| !remaining.is_zero())
+                .map(Some)
+                .ok_or_else(|| io::ErrorKind::TimedOut.into()),
+        }
+    }
+
+    fn connect(&self, addr: (&str, u16), deadline: Option<Instant>) -> io::Result<TcpStream> {
+        let mut error = io::Error::from(io::ErrorKind::AddrNotAvailable);
+        for addr in addr.to_socket_addrs()? {
+            let stream = match self.remaining(deadline)? {
+                Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+                None => TcpStream::connect(addr),
+            };
+            match stream {
+                Ok(stream) => return Ok(stream),
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+
+    fn bypasses_proxy(&self, host: &str) -> bool {
+        self.no_proxy
+            .iter()
+            .any(|suffix| suffix == "*" || host == suffix || host.ends_with(&format!(".{suffix}")))
+    }
+
+    /// `Authorization` of the request, from credentials in URL, bearer token or netrc, whichever
+    /// comes first
+    fn authorization(&self, url: &Url, first: bool) -> Option<String> {
+        if !url.username().is_empty() {
+            return Some(basic(url.username(), url.password().unwrap_or_default()));
+        }
+        if let Some(token) = self.token.as_ref().filter(|_| first) {
+            return Some(format!("Bearer {token}"));
+        }
+        let host = url.host_str()?;
+        let credential = self
+            .netrc
+            .iter()
+            .find(|credential| credential.machine.as_deref() == Some(host))
+            .or_else(|| {
+                self.netrc
+                    .iter()
+                    .find(|credential| credential.machine.is_none())
+            })?;
+        Some(basic(&credential.login, &credential.password))
+    }
+
+    /// Headers of the request along with the extra ones, which are sent only to the host first
+    /// requested
+    fn request_headers(&self, url: &Url, first: bool) -> Vec<(String, String)> {
+        let headers = self.headers.iter().filter(|_| first).cloned();
+        let mut request = Vec::new();
+        if !headers
+            .clone()
+            .any(|(name, _)| name.eq_ignore_ascii_case("authorization"))
+        {
+            let host = url.host_str().unwrap_or_default();
+            match self.authorization(url, first) {
+                Some(authorization) if url.scheme() == "https" => {
+                    request.push(("Authorization".to_owned(), authorization));
+                }
+                Some(authorization) if self.plaintext_auth => {
+                    self.warning(&format!(
+                        "{host}: sending credentials over plaintext http://"
+                    ));
+                    request.push(("Authorization".to_owned(), authorization));
+                }
+                Some(_) => self.warning(&format!(
+                    "{host}: credentials not sent over plaintext http://, pass --allow-http-auth to send them anyway"
+                )),
+                None => {}
+            }
+        }
+        request.extend(headers);
+        request
+    }
+
+    /// `first` tells whether the request is to the host first requested, before any redirect to
+    /// another host
+    fn request(&self, url: &Url, first: bool) -> Result<Response, Failure> {
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let exchanged = match url.scheme() {
+            "https" => self.exchange_tls(url, first, deadline),
+            _ => self.exchange(url, first, deadline),
+        };
+        exchanged.map_err(|e| match e.kind() {
+            io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => Failure::Transient(eyre!(
+                "{url}: timed out after {:?}",
+                self.timeout.unwrap_or_default()
+            )),
+            // such as `curl` missing or the certificate of the server failing to verify
+            io::ErrorKind::Unsupported => Failure::Fatal(eyre!("{url}: {e}")),
+            _ => Failure::Transient(eyre!("{url}: {e}")),
+        })
+    }
+
+    /// Exchange of `https://` by `curl`, given the request by a config on stdin to keep
+    /// credentials out of its command line
+    fn exchange_tls(
+        &self,
+        url: &Url,
+        first: bool,
+        deadline: Option<Instant>,
+    ) -> io::Result<Response> {
+        let mut target = url.clone();
+        target.set_fragment(None);
+        let _ = target.set_username("");
+        let _ = target.set_password(None);
+        let host = url.host_str().unwrap_or_default();
+        let mut config = format!(
+            "url = {}\nsilent\nshow-error\ninclude\nraw\nhttp1.1\nsuppress-connect-headers\nproto = \"=https\"\nuser-agent = \"mbtmap/{}\"\nheader = \"Accept-Encoding: identity\"\n",
+            curl_quote(target.as_str()),
+            env!("CARGO_PKG_VERSION"),
+        );
+        for (name, value) in self.request_headers(url, first) {
+            config.push_str(&format!(
+                "header = {}\n",
+                curl_quote(&format!("{name}: {value}"))
+            ));
+        }
+        match self
+            .https_proxy
+            .as_ref()
+            .filter(|_| !self.bypasses_proxy(host))
+        {
+            Some(proxy) => config.push_str(&format!("proxy = {}\n", curl_quote(proxy.as_str()))),
+            // proxies of the environment are chosen here, not again by `curl`
+            None => config.push_str("noproxy = \"*\"\n"),
+        }
+        if let Some(remaining) = self.remaining(deadline)? {
+            config.push_str(&format!("max-time = {:.3}\n", remaining.as_secs_f64()));
+        }
+        let mut child = Command::new("curl")
+            .args(["--config", "-"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "https:// is fetched by curl, which is not found on PATH",
+                ),
+                _ => e,
+            })?;
+        let written = child.stdin.take().unwrap().write_all(config.as_bytes());
+        let output = child.wait_with_output()?;
+        written?;
+        let message = || String::from_utf8_lossy(&output.stderr).trim().to_owned();
+        match output.status.code() {
+            Some(0) => parse(&output.stdout)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string())),
+            Some(28) => Err(io::ErrorKind::TimedOut.into()),
+            // failures of TLS, which retrying does not fix
+            Some(35 | 51 | 53 | 54 | 58 | 59 | 60 | 64 | 66 | 77 | 80 | 83 | 90 | 91) => {
+                Err(io::Error::new(io::ErrorKind::Unsupported, message()))
+            }
+            _ => Err(io::Error::other(message())),
+        }
+    }
+
+    fn exchange(&self, url: &Url, first: bool, deadline: Option<Instant>) -> io::Result<Response> {
+        let host = url.host_str().unwrap_or_default();
+        let port = url.port_or_known_default().unwrap_or(80);
+        let proxy = self.proxy.as_ref().filter(|_| !self.bypasses_proxy(host));
+        let (mut stream, target) = match proxy {
+            Some(proxy) => {
+                let proxy_host = proxy.host_str().unwrap_or_default();
+                let proxy_port = proxy.port_or_known_default().unwrap_or(80);
+                let mut target = url.clone();
+                target.set_fragment(None);
+                let _ = target.set_username("");
+                let _ = target.set_password(None);
+                (
+                    self.connect((proxy_host, proxy_port), deadline)?,
+                    target.to_string(),
+                )
+            }
+            None => (
+                self.connect((host, port), deadline)?,
+                url[url::Position::BeforePath..url::Position::AfterQuery].to_owned(),
+            ),
+        };
+        let mut request = format!(
+            "GET {target} HTTP/1.1\r\nHost: {}\r\nUser-Agent: mbtmap/{}\r\nAccept-Encoding: identity\r\nConnection: close\r\n",
+            &url[url::Position::BeforeHost..url::Position::AfterPort],
+            env!("CARGO_PKG_VERSION"),
+        );
+        if let Some(proxy) = proxy.filter(|proxy| !proxy.username().is_empty()) {
+            let authorization = basic(proxy.username(), proxy.password().unwrap_or_default());
+            request.push_str(&format!("Proxy-Authorization: {authorization}\r\n"));
+        }
+        for (name, value) in self.request_headers(url, first) {
+            request.push_str(&format!("{name}: {value}\r\n"));
+        }
+        request.push_str("\r\n");
+        stream.set_write_timeout(self.remaining(deadline)?)?;
+        stream.write_all(request.as_bytes())?;
+        let mut raw = Vec::new();
+        let mut buf = [0; 8192];
+        loop {
+            stream.set_read_timeout(self.remaining(deadline)?)?;
+            match stream.read(&mut buf)? {
+                0 => break,
+                n => raw.extend_from_slice(&buf[..n]),
+            }
+            if complete(&raw) {
+                break;
+            }
+        }
+        parse(&raw).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    }
+
+    /// Body of the resource along with the URL it is finally served from
+    fn fetch(&self, url: &str) -> Result<(Url, Vec<u8>), Failure> {
+        let fatal = |e: Report| Failure::Fatal(e);
+        let mut url = Url::parse(url).map_err(|e| fatal(e.into()))?;
+        let origin = url.origin();
+        for _ in 0..=MAX_REDIRECTS {
+            if !matches!(url.scheme(), "http" | "https") || url.host_str().is_none() {
+                return Err(fatal(eyre!(
+                    "{url}: only http:// and https:// are supported"
+                )));
+            }
+            let response = self.request(&url, url.origin() == origin)?;
+            match response.status {
+                200..=299 => return Ok((url, response.body)),
+                301 | 302 | 303 | 307 | 308 => {
+                    let location = response
+                        .header("location")
+                        .ok_or_else(|| fatal(eyre!("{url}: redirect without location")))?;
+                    url = url.join(location).map_err(|e| fatal(e.into()))?;
+                }
+                status @ (408 | 429 | 500..=599) => {
+                    return Err(Failure::Transient(eyre!("{url}: HTTP {status}")))
+                }
+                status => return Err(fatal(eyre!("{url}: HTTP {status}"))),
+            }
+        }
+        Err(fatal(eyre!("{url}: too many redirects")))
+    }
+
+    /// Body of the resource, following redirects and retrying with exponential backoff
+    pub fn get(&self, url: &str) -> Result<Vec<u8>> {
+        Ok(self.get_redirected(url)?.1)
+    }
+
+    /// Body of the resource along with the URL it is served from after redirects, relative to
+    /// which references in it are resolved
+    pub fn get_redirected(&self, url: &str) -> Result<(String, Vec<u8>)> {
+        self.retrying(url, || self.fetch(url))
+            .map(|(url, body)| (url.into(), body))
+    }
+
+    /// Result of the first attempt not failing transiently, retrying with exponential backoff
+    fn retrying<T>(&self, url: &str, mut attempt: impl FnMut() -> Result<T, Failure>) -> Result<T> {
+        let mut backoff = BACKOFF;
+        let mut attempts = 0;
+        loop {
+            let error = match attempt() {
+                Ok(value) => return Ok(value),
+                Err(Failure::Fatal(e)) => return Err(e).class(Class::Network),
+                Err(Failure::Transient(e)) => e,
+            };
+            attempts += 1;
+            if attempts > self.retries {
+                let attempts = match attempts {
+                    1 => "1 attempt".to_owned(),
+                    n => format!("{n} attempts"),
+                };
+                let error = error.wrap_err(format!("failed to fetch {url} in {attempts}"));
+                return Err(error).class(Class::Network);
+            }
+            self.warning(&format!("{error}, retrying in {backoff:?}"));
+            thread::sleep(backoff);
+            backoff *= 2;
+        }
+    }
+
+    /// Contents of the file, or the resource if `path` is an HTTP, `s3://` or `gs://` URL
+    pub fn read(&self, path: &str) -> Result<Vec<u8>> {
+        if is_url(path) {
+            self.get(path)
+        } else if object::is_url(path) {
+            self.retrying(path, || object::read(path, self.timeout))
+        } else {
+            Ok(fs::read(path)?)
+        }
+    }
+
+    /// Contents of the file or resource along with where they are finally read from after
+    /// redirects
+    pub fn read_redirected(&self, path: &str) -> Result<(String, Vec<u8>)> {
+        if is_url(path) {
+            self.get_redirected(path)
+        } else {
+            Ok((path.to_owned(), self.read(path)?))
+        }
+    }
+
+    /// Source map from a file or URL, or the one a WASM module or JS bundle refers to by
+    /// `sourceMappingURL`
+    pub fn source_map(&self, path: &str) -> Result<SourceMap> {
+        source_map(path, |path| self.read_redirected(path)).class(Class::MapLoad)
+    }
+}
@@ -0,0 +1,96 @@
+//! Frames of no interest listed in `.mbtmapignore`, such as allocator internals and panic
+//! machinery, collapsed or hidden in output
+//!
+//! Each line is a glob of source paths, matching the whole path or its end after a `/`, or a glob
+//! of function names after `fn:`, either taken as a regex after `re:`, such as `fn:re:^\$panic`.
+
+use eyre::{eyre, Result};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the file looked up in the current directory and its ancestors
+pub const FILE: &str = ".mbtmapignore";
+
+#[derive(Debug)]
+pub struct Ignore {
+    sources: Vec<Regex>,
+    functions: Vec<Regex>,
+}
+
+/// Regex of a glob, `*` not matching `/` in paths
+fn glob(glob: &str, path: bool) -> String {
+    let mut re = String::new();
+    let mut rest = glob;
+    while let Some(c) = rest.chars().next() {
+        let (part, len) = if rest.starts_with("**") {
+            (".*".to_owned(), 2)
+        } else if c == '*' {
+            (if path { "[^/]*" } else { ".*" }.to_owned(), 1)
+        } else if c == '?' {
+            (if path { "[^/]" } else { "." }.to_owned(), 1)
+        } else {
+            (regex::escape(&c.to_string()), c.len_utf8())
+        };
+        re.push_str(&part);
+        rest = &rest[len..];
+    }
+    if path {
+        format!("(?:^|/){re}$")
+    } else {
+        format!("^{re}$")
+    }
+}
+
+/// Function of a frame in the form of `at NAME (LOCATION)`
+pub fn function(line: &str) -> Option<&str> {
+    let (name, _) = line.trim_start().strip_prefix("at ")?.split_once(" (")?;
+    Some(name)
+}
+
+impl Ignore {
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| eyre!("failed to read {}: {e}", path.display()))?;
+        let mut ignore = Self {
+            sources: Vec::new(),
+            functions: Vec::new(),
+        };
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (pattern, by_source) = match line.strip_prefix("fn:") {
+                Some(pattern) => (pattern, false),
+                None => (line, true),
+            };
+            let re = match pattern.strip_prefix("re:") {
+                Some(re) => re.to_owned(),
+                None => glob(pattern, by_source),
+            };
+            let re = Regex::new(&re).map_err(|e| eyre!("{}:{}: {e}", path.display(), i + 1))?;
+            if by_source {
+                ignore.sources.push(re);
+            } else {
+                ignore.functions.push(re);
+            }
+        }
+        Ok(ignore)
+    }
+
+    /// `.mbtmapignore` of the current directory or the nearest ancestor holding one
+    pub fn find() -> Option<PathBuf> {
+        let cwd = std::env::current_dir().ok()?;
+        cwd.ancestors()
+            .map(|dir| dir.join(FILE))
+            .find(|path| path.is_file())
+    }
+
+    /// Whether the frame resolved to the source, in the function if named, is ignored
+    pub fn is_ignored(&self, source: &str, function: Option<&str>) -> bool {
+        self.sources.iter().any(|re| re.is_match(source))
+            || function
+                .is_some_and(|function| self.functions.iter().any(|re| re.is_match(function)))
+    }
+}
@@ -0,0 +1,95 @@
+//! Input read from the systemd journal through `journalctl -o json`, each entry printed as its
+//! `MESSAGE` prefixed by the time it was logged
+
+use crate::window::civil_from_days;
+use eyre::{bail, eyre, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ChildStdout, Command, Stdio};
+
+#[derive(Debug)]
+pub struct Journal {
+    child: Child,
+    stdout: BufReader<ChildStdout>,
+}
+
+/// ISO 8601 timestamp in UTC of the microseconds since the epoch
+fn timestamp(micros: i64) -> String {
+    let (secs, micros) = (micros.div_euclid(1_000_000), micros.rem_euclid(1_000_000));
+    let (days, secs) = (secs.div_euclid(86400), secs.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{micros:06}Z",
+        secs / 3600,
+        secs / 60 % 60,
+        secs % 60
+    )
+}
+
+/// Field of the entry, given as a string, or an array of bytes if not valid UTF-8
+fn field(entry: &Value, name: &str) -> Option<String> {
+    match &entry[name] {
+        Value::String(s) => Some(s.clone()),
+        Value::Array(bytes) => {
+            let bytes = bytes
+                .iter()
+                .map(|byte| byte.as_u64().and_then(|byte| u8::try_from(byte).ok()))
+                .collect::<Option<Vec<_>>>()?;
+            Some(String::from_utf8_lossy(&bytes).into_owned())
+        }
+        _ => None,
+    }
+}
+
+impl Journal {
+    /// Read entries of the unit, or all the entries if `None`, waiting for new ones if `follow`
+    pub fn open(unit: Option<&str>, follow: bool) -> Result<Self> {
+        let mut command = Command::new("journalctl");
+        command.args(["--output=json", "--no-pager"]);
+        if let Some(unit) = unit {
+            command.args(["--unit", unit]);
+        }
+        if follow {
+            command.arg("--follow");
+        }
+        let mut child = command
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| eyre!("failed to run journalctl: {e}"))?;
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self { child, stdout })
+    }
+
+    /// Append the next entry to `buf`, returning its length or 0 at the end of the journal
+    pub fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                let status = self.child.wait()?;
+                if !status.success() {
+                    bail!("journalctl exited with {status}");
+                }
+                return Ok(0);
+            }
+            let entry: Value = serde_json::from_str(&line)?;
+            let Some(message) = field(&entry, "MESSAGE") else {
+                continue;
+            };
+            let time = field(&entry, "__REALTIME_TIMESTAMP")
+                .and_then(|time| time.parse().ok())
+                .map(timestamp)
+                .unwrap_or_default();
+            let entry = format!("{time} {}\n", message.trim_end_matches('\n'));
+            buf.push_str(&entry);
+            return Ok(entry.len());
+        }
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
@@ -0,0 +1,43 @@
+use crate::trace;
+use crate::Frame;
+use eyre::Result;
+use serde_json::{json, Value};
+
+/// Resolved frame with its position in the input
+pub fn frame(frame: &Frame) -> Value {
+    json!({
+        "trace": frame.trace,
+        "input_line": frame.input_line,
+        "input_offset": frame.input_offset,
+        "text": frame.text,
+        "address": format!("{:#x}", frame.addr),
+        "module": frame.module,
+        "name": frame.name,
+        "source": frame.location.source,
+        "line": frame.location.line,
+        "column": frame.column,
+        "raw_column": frame.location.column - 1,
+        "link": frame.link,
+        "confidence": frame.confidence.as_str(),
+        "owners": frame.owners,
+        "panic": frame.panic.as_ref().map(|panic| json!({
+            "message": panic.message,
+            "location": panic.location,
+        })),
+    })
+}
+
+/// Render resolved frames as a JSON document
+pub fn render(frames: &[Frame]) -> Result<String> {
+    let frames = frames
+        .iter()
+        .zip(trace::fingerprints(frames))
+        .map(|(f, fingerprint)| {
+            let mut frame = frame(f);
+            frame["fingerprint"] = json!(fingerprint);
+            frame
+        })
+        .collect::<Vec<_>>();
+    let document = json!({ "frames": frames });
+    Ok(serde_json::to_string_pretty(&document)? + "\n")
+}
@@ -0,0 +1,167 @@
+//! Logs of a Kubernetes pod streamed through `kubectl logs`, with the cluster and credentials of
+//! its kubeconfig, each line prefixed by the time it was logged
+
+use crate::window::{format_rfc3339, rfc3339};
+use eyre::{bail, eyre, Result};
+use serde_json::Value;
+use std::io::{BufRead, BufReader};
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Interval of checking whether a restarted container is running again
+const POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Default)]
+pub struct Pod {
+    pub name: String,
+    pub container: Option<String>,
+    pub namespace: Option<String>,
+    /// Context of the kubeconfig, default to the current one
+    pub context: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Logs {
+    pod: Pod,
+    follow: bool,
+    child: Option<(Child, BufReader<ChildStdout>)>,
+    /// Time of the last line read, the stream resumed after it on reconnect
+    last: Option<(i64, i64)>,
+    /// Lines logged at or before the time are read again on reconnect, and skipped
+    skip: Option<(i64, i64)>,
+}
+
+impl Pod {
+    fn kubectl(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("kubectl");
+        command.args(args);
+        if let Some(namespace) = &self.namespace {
+            command.args(["--namespace", namespace]);
+        }
+        if let Some(context) = &self.context {
+            command.args(["--context", context]);
+        }
+        command
+    }
+
+    /// Whether the container is running, or `None` if the pod no longer exists
+    fn is_running(&self) -> Result<Option<bool>> {
+        let output = self
+            .kubectl(&["get", "pod", &self.name, "--output=json"])
+            .stderr(Stdio::piped())
+            .output()
+            .map_err(|e| eyre!("failed to run kubectl: {e}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            if stderr.contains("NotFound") {
+                return Ok(None);
+            }
+            bail!("kubectl get pod failed: {}", stderr.trim());
+        }
+        let pod: Value = serde_json::from_slice(&output.stdout)?;
+        let statuses = pod["status"]["containerStatuses"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+        let status = match &self.container {
+            Some(container) => statuses
+                .iter()
+                .find(|status| status["name"].as_str() == Some(container)),
+            None => statuses.first(),
+        };
+        Ok(Some(status.is_some_and(|status| {
+            status["state"]["running"].is_object()
+        })))
+    }
+}
+
+impl Logs {
+    /// Logs of the pod, waiting for new lines and reconnecting after restarts if `follow`
+    pub fn open(pod: Pod, follow: bool) -> Result<Self> {
+        let mut logs = Self {
+            pod,
+            follow,
+            child: None,
+            last: None,
+            skip: None,
+        };
+        logs.connect()?;
+        Ok(logs)
+    }
+
+    fn connect(&mut self) -> Result<()> {
+        let mut command = self.pod.kubectl(&["logs", &self.pod.name, "--timestamps"]);
+        if let Some(container) = &self.pod.container {
+            command.args(["--container", container]);
+        }
+        if self.follow {
+            command.arg("--follow");
+        }
+        if let Some(last) = self.last {
+            command.arg(format!("--since-time={}", format_rfc3339(last)));
+            self.skip = self.last;
+        }
+        let mut child = command
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| eyre!("failed to run kubectl: {e}"))?;
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        self.child = Some((child, stdout));
+        Ok(())
+    }
+
+    /// Wait for the container to run again after the stream ends, telling whether it does
+    fn reconnect(&mut self) -> Result<bool> {
+        loop {
+            match self.pod.is_running()? {
+                None => return Ok(false),
+                Some(true) => {
+                    self.connect()?;
+                    return Ok(true);
+                }
+                Some(false) => thread::sleep(POLL),
+            }
+        }
+    }
+
+    /// Append the next line to `buf`, returning its length or 0 at the end of the logs
+    pub fn read_line(&mut self, buf: &mut String) -> Result<usize> {
+        let mut line = String::new();
+        loop {
+            let Some((child, stdout)) = &mut self.child else {
+                return Ok(0);
+            };
+            line.clear();
+            if stdout.read_line(&mut line)? == 0 {
+                let status = child.wait()?;
+                self.child = None;
+                if !status.success() {
+                    bail!("kubectl logs exited with {status}");
+                }
+                if self.follow && self.reconnect()? {
+                    continue;
+                }
+                return Ok(0);
+            }
+            if let Some(instant) = rfc3339(&line) {
+                if self.skip.is_some_and(|skip| instant <= skip) {
+                    continue;
+                }
+                self.skip = None;
+                self.last = Some(instant);
+            }
+            buf.push_str(&line);
+            return Ok(line.len());
+        }
+    }
+}
+
+impl Drop for Logs {
+    fn drop(&mut self) {
+        if let Some((child, _)) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
@@ -0,0 +1,473 @@
+#[cfg(feature = "cli")]
+mod align;
+#[cfg(feature = "cli")]
+pub mod bench;
+pub mod bundle;
+#[cfg(feature = "cli")]
+pub mod cache;
+#[cfg(feature = "capi")]
+pub mod capi;
+#[cfg(feature = "cli")]
+pub mod cdp;
+#[cfg(feature = "cli")]
+pub mod clipboard;
+pub mod codeowners;
+#[cfg(feature = "cli")]
+mod context;
+#[cfg(feature = "cli")]
+pub mod crash;
+#[cfg(all(feature = "cli", unix))]
+pub mod daemon;
+pub mod discover;
+#[cfg(all(feature = "cli", unix))]
+pub mod docker;
+pub mod dwarf;
+#[cfg(all(feature = "cli", not(target_os = "wasi")))]
+pub mod editor;
+pub mod exit;
+#[cfg(feature = "cli")]
+pub mod external;
+#[cfg(any(feature = "capi", feature = "web"))]
+mod ffi;
+#[cfg(all(feature = "cli", unix))]
+pub mod fifo;
+#[cfg(feature = "cli")]
+pub mod filter;
+#[cfg(feature = "cli")]
+pub mod generate;
+pub mod git;
+pub mod github;
+mod gzip;
+#[cfg(feature = "cli")]
+pub mod har;
+pub mod http;
+pub mod ignore;
+#[cfg(feature = "cli")]
+pub mod journal;
+#[cfg(feature = "cli")]
+mod json;
+#[cfg(all(feature = "cli", not(target_os = "wasi")))]
+pub mod k8s;
+#[cfg(feature = "napi")]
+mod napi;
+#[cfg(feature = "cli")]
+mod numbering;
+pub mod object;
+#[cfg(feature = "cli")]
+pub mod output;
+pub mod preset;
+#[cfg(feature = "cli")]
+mod reassemble;
+#[cfg(feature = "cli")]
+mod redact;
+#[cfg(feature = "cli")]
+pub mod reload;
+#[cfg(feature = "cli")]
+mod report;
+pub mod resolver;
+pub mod rewrite;
+#[cfg(feature = "cli")]
+mod sample;
+#[cfg(feature = "cli")]
+mod sarif;
+#[cfg(feature = "cli")]
+pub mod selftest;
+#[cfg(all(feature = "cli", not(target_os = "wasi")))]
+pub mod serve;
+#[cfg(all(feature = "cli", not(target_os = "wasi")))]
+pub mod service;
+pub mod sha256;
+pub mod sidecar;
+#[cfg(feature = "cli")]
+pub mod snapshot;
+#[cfg(feature = "cli")]
+mod source;
+pub mod store;
+pub mod symbols;
+#[cfg(feature = "cli")]
+mod table;
+#[cfg(feature = "cli")]
+mod trace;
+pub mod wasm;
+#[cfg(all(feature = "cli", not(target_os = "wasi")))]
+pub mod watch;
+#[cfg(feature = "web")]
+mod web;
+#[cfg(feature = "cli")]
+pub mod webhook;
+#[cfg(feature = "cli")]
+mod websocket;
+#[cfg(feature = "cli")]
+mod window;
+#[cfg(feature = "cli")]
+mod workerd;
+
+use data_encoding::BASE64;
+#[cfg(feature = "cli")]
+use exit::{Class, Classify};
+use eyre::{bail, eyre, Result};
+#[cfg(feature = "cli")]
+use resolver::Confidence;
+#[cfg(feature = "cli")]
+use source::Snippet;
+use sourcemap::{SourceMap, Token};
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "cli")]
+use trace::Panic;
+use wasm::Module;
+
+#[derive(Debug, Clone)]
+pub struct Location {
+    pub source: String,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.source, self.line, self.column)
+    }
+}
+
+/// Convention of columns in output
+#[derive(Debug, Clone, Copy)]
+pub struct Columns {
+    /// Column of the first character of a line, 0 or 1
+    pub base: u32,
+    pub hidden: bool,
+}
+
+impl Default for Columns {
+    fn default() -> Self {
+        Self {
+            base: 1,
+            hidden: false,
+        }
+    }
+}
+
+impl Columns {
+    /// Column of the location in the convention, or `None` if hidden
+    pub fn column(&self, location: &Location) -> Option<u32> {
+        (!self.hidden).then(|| location.column - 1 + self.base)
+    }
+
+    /// `source:line:column` of the location in the convention, or `source:line` if hidden
+    pub fn format(&self, location: &Location) -> String {
+        match self.column(location) {
+            Some(column) => format!("{}:{}:{column}", location.source, location.line),
+            None => format!("{}:{}", location.source, location.line),
+        }
+    }
+}
+
+/// A frame in the traceback whose address has been resolved
+#[cfg(feature = "cli")]
+#[derive(Debug)]
+pub struct Frame {
+    /// The whole line containing the frame, without line ending
+    pub text: String,
+    pub addr: u32,
+    /// URL of the module the frame comes from
+    pub module: Option<String>,
+    /// Function name from the symbol map or the name section of the module
+    pub name: Option<String>,
+    pub location: Location,
+    /// Column of the location in the convention of `--column-base`, or `None` with `--no-column`
+    pub column: Option<u32>,
+    /// Location as printed, in the same convention
+    pub position: String,
+    /// Permalink to the resolved line
+    pub link: Option<String>,
+    pub confidence: Confidence,
+    /// Owners of the source by `CODEOWNERS`
+    pub owners: Vec<String>,
+    /// Rust panic leading the traceback
+    pub panic: Option<Panic>,
+    /// Index of the traceback containing the frame, told apart by `--trace-delimiter`
+    pub trace: usize,
+    /// Line number in the input from 1, and byte offset of the frame in the input
+    pub input_line: usize,
+    pub input_offset: usize,
+    snippet: Option<Snippet>,
+}
+
+pub(crate) fn decode_data_url(url: &str) -> Result<Vec<u8>> {
+    let (header, data) = url
+        .split_once(',')
+        .ok_or_else(|| eyre!("malformed data URL"))?;
+    if header.ends_with(";base64") {
+        Ok(BASE64.decode(data.as_bytes())?)
+    } else {
+        Ok(data.as_bytes().to_vec())
+    }
+}
+
+/// Read source map from a file, or the one a WASM module or JS bundle refers to by
+/// `sourceMappingURL`
+pub fn read_source_map(path: &str) -> Result<SourceMap> {
+    source_map(path, |path| Ok((path.to_owned(), fs::read(path)?)))
+}
+
+/// Source map read by `read` from a file or URL, or the one a WASM module or JS bundle refers to
+/// by `sourceMappingURL` relative to it, `read` returning the contents along with the location
+/// they are finally read from, such as after redirects
+fn source_map(path: &str, read: impl Fn(&str) -> Result<(String, Vec<u8>)>) -> Result<SourceMap> {
+    let (location, data) = read(path)?;
+    if gzip::is_gzip(&data) {
+        bail!("{path} is gzip-compressed, such as a symbol bundle, which is given by --bundle");
+    }
+    let url = if path.ends_with(".wasm") {
+        Module::parse(&data)?
+            .source_mapping_url()
+            .ok_or_else(|| eyre!("{path} has no sourceMappingURL section"))?
+            .to_owned()
+    } else {
+        // a map starts with its JSON object, optionally after an XSSI prefix
+        let start = data.iter().find(|b| !b.is_ascii_whitespace());
+        let reference = match start {
+            Some(b'{' | b')') | None => None,
+            Some(_) => sourcemap::locate_sourcemap_reference_slice(&data)?,
+        };
+        match reference {
+            Some(reference) => reference.get_url().to_owned(),
+            None => return Ok(SourceMap::from_slice(&data)?),
+        }
+    };
+    if url.starts_with("data:") {
+        return Ok(SourceMap::from_slice(&decode_data_url(&url)?)?);
+    }
+    let map = if http::is_url(&location) || object::is_url(&location) {
+        url::Url::parse(&location)?.join(&url)?.to_string()
+    } else {
+        let url = url.strip_prefix("file://").unwrap_or(&url);
+        let map = Path::new(&location)
+            .parent()
+            .unwrap_or(Path::new(""))
+            .join(url);
+        map.to_string_lossy().into_owned()
+    };
+    Ok(SourceMap::from_slice(&read(&map)?.1)?)
+}
+
+/// Default source map and those routed by worker script name
+#[cfg(feature = "cli")]
+pub struct Maps {
+    default: SourceMap,
+    scripts: HashMap<String, SourceMap>,
+}
+
+#[cfg(feature = "cli")]
+impl Maps {
+    /// Read source maps from files or URLs fetched by the client, applying their sidecars
+    pub fn open(
+        client: &http::Client,
+        default: &str,
+        scripts: &[(String, String)],
+    ) -> Result<Self> {
+        let open = |path: &str| -> Result<SourceMap> {
+            let map = client.source_map(path)?;
+            match sidecar::Sidecar::find(path).class(Class::MapLoad)? {
+                Some(sidecar) => sidecar.apply(map).class(Class::MapLoad),
+                None => Ok(map),
+            }
+        };
+        Ok(Self {
+            default: open(default)?,
+            scripts: scripts
+                .iter()
+                .map(|(script, path)| Ok((script.clone(), open(path)?)))
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    /// The same maps with `default` in place of the default one
+    pub(crate) fn with_default(&self, default: SourceMap) -> Self {
+        Self {
+            default,
+            scripts: self.scripts.clone(),
+        }
+    }
+
+    fn get(&self, script: Option<&str>) -> &SourceMap {
+        script
+            .and_then(|script| self.scripts.get(script))
+            .unwrap_or(&self.default)
+    }
+}
+
+/// Base of addresses written without `0x` prefix
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Radix {
+    /// Hex if captured from a frame of a preset or holding hex letters, otherwise decimal
+    Auto,
+    Hex,
+    #[default]
+    Dec,
+}
+
+impl Radix {
+    /// Parse the address, bare digits taken as hex in auto mode if `captured` from a frame
+    pub fn parse(self, addr: &str, captured: bool) -> Option<u32> {
+        let hex = match self {
+            Radix::Hex => true,
+            Radix::Dec => false,
+            Radix::Auto => captured || !addr.bytes().all(|b| b.is_ascii_digit()),
+        };
+        match addr.strip_prefix("0x") {
+            Some(_) => parse_addr(addr),
+            None if hex => Some(usize::from_str_radix(addr, 16).ok()? as u32),
+            None => parse_addr(addr),
+        }
+    }
+
+    /// Evaluate the sum and differences of addresses and variables, such as `0x2648d-0x100` or
+    /// `base+off`
+    pub fn eval(self, expr: &str, vars: &HashMap<String, u32>) -> Result<u32> {
+        let mut sum = 0i64;
+        let mut sign = 1;
+        for (i, term) in expr.split_inclusive(['+', '-']).enumerate() {
+            let (term, next) = match term.strip_suffix(['+', '-']) {
+                Some(rest) => (rest, if term.ends_with('-') { -1 } else { 1 }),
+                None => (term, 1),
+            };
+            let term = term.trim();
+            let value = match vars.get(term) {
+                Some(&value) => value,
+                None if term.is_empty() && i == 0 => bail!("`{expr}` starts with an operator"),
+                None => self.parse(term, false).ok_or_else(|| {
+                    eyre!("expected address or variable, found `{term}` in `{expr}`")
+                })?,
+            };
+            sum += sign * i64::from(value);
+            sign = next;
+        }
+        if expr.ends_with(['+', '-']) {
+            bail!("`{expr}` ends with an operator");
+        }
+        u32::try_from(sum)
+            .map_err(|_| eyre!("`{expr}` evaluates to {sum}, out of the address range"))
+    }
+}
+
+pub fn parse_addr(addr: &str) -> Option<u32> {
+    let addr = if addr.starts_with("0x") {
+        usize::from_str_radix(addr.strip_prefix("0x").unwrap(), 16).ok()?
+    } else {
+        addr.parse().ok()?
+    };
+    Some(addr as u32)
+}
+
+/// Addresses of the code at `line` of the source ending with `path`, or at the next line with code,
+/// returned along with that line
+pub fn addresses(map: &SourceMap, path: &str, line: u32) -> Result<(u32, Vec<u32>)> {
+    let path = path.trim_start_matches("./");
+    let sources = map
+        .sources()
+        .filter(|source| {
+            let normalized = normalize_source(source);
+            normalized == path || normalized.ends_with(&format!("/{path}"))
+        })
+        .collect::<Vec<_>>();
+    match sources[..] {
+        [] => bail!("no source {path} in the source map"),
+        [_] => {}
+        _ => bail!("{path} is ambiguous between {}", sources.join(", ")),
+    }
+    let tokens = map
+        .tokens()
+        .filter(|token| token.get_source() == Some(sources[0]) && token.get_src_line() + 1 >= line)
+        .collect::<Vec<_>>();
+    let Some(found) = tokens.iter().map(|token| token.get_src_line() + 1).min() else {
+        bail!("no code at or after {path}:{line}");
+    };
+    let mut addrs = tokens
+        .iter()
+        .filter(|token| token.get_src_line() + 1 == found)
+        .map(|token| token.get_dst_col())
+        .collect::<Vec<_>>();
+    addrs.sort_unstable();
+    addrs.dedup();
+    Ok((found, addrs))
+}
+
+/// Source identifier percent-decoded, with `file://` dropped, separators unified to `/` and `.` and
+/// `..` segments resolved, so that it compares with paths on disk
+pub fn normalize_source(source: &str) -> Cow<'_, str> {
+    let clean = !source.contains(['%', '\\'])
+        && !source.starts_with("file://")
+        && !source
+            .split('/')
+            .any(|segment| segment == "." || segment == "..");
+    if clean {
+        return Cow::Borrowed(source);
+    }
+    let bytes = source.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = (bytes[i] == b'%')
+            .then(|| source.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match hex {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    let decoded = String::from_utf8(decoded).unwrap_or_else(|_| source.to_owned());
+    let decoded = decoded.replace('\\', "/");
+    let decoded = decoded.strip_prefix("file://").unwrap_or(&decoded);
+    // scheme and host of URLs such as `webpack://app/` kept as they are
+    let (prefix, path) = match decoded.find("://") {
+        Some(i) => {
+            let end = decoded[i + 3..]
+                .find('/')
+                .map_or(decoded.len(), |j| i + 3 + j);
+            decoded.split_at(end)
+        }
+        None => ("", decoded),
+    };
+    let mut segments: Vec<&str> = Vec::new();
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => {}
+            ".." if segments.last().is_some_and(|last| *last != "..") => _ = segments.pop(),
+            // nothing to go up from at the root
+            ".." if path.starts_with('/') => {}
+            segment => segments.push(segment),
+        }
+    }
+    let root = if path.starts_with('/') { "/" } else { "" };
+    Cow::Owned(format!("{prefix}{root}{}", segments.join("/")))
+}
+
+pub fn locate(token: &Token, cwd: &Option<PathBuf>) -> Location {
+    let source = match token.get_source().map(normalize_source) {
+        Some(s) => match cwd {
+            Some(cwd) => match Path::new(&*s).strip_prefix(cwd) {
+                Ok(path) => path.to_str().unwrap_or(&s).to_owned(),
+                Err(_) => s.into_owned(),
+            },
+            None => s.into_owned(),
+        },
+        None => "<unknown>".to_string(),
+    };
+    Location {
+        source,
+        line: token.get_src_line() + 1,
+        column: token.get_src_col() + 1,
+    }
+}
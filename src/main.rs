@@ -1,140 +1,1626 @@
-use clap::Parser;
-use eyre::Result;
-use regex::{Captures, Regex};
-use sourcemap::SourceMap;
-use std::env::current_dir;
-use std::fs::{File, OpenOptions};
-use std::io::{self, BufRead, BufReader, Read, Stdin};
-use std::path::PathBuf;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use eyre::{bail, eyre, Result};
+use mbtmap::bench;
+use mbtmap::bundle::Bundle;
+#[cfg(not(target_os = "wasi"))]
+use mbtmap::cache;
+use mbtmap::cdp::{Cdp, Debugger};
+use mbtmap::crash::{self, Crash};
+#[cfg(unix)]
+use mbtmap::daemon;
+use mbtmap::discover::{find_map, wasm_pack};
+#[cfg(unix)]
+use mbtmap::docker;
+use mbtmap::exit::{self, Class, Classify};
+#[cfg(unix)]
+use mbtmap::fifo::Fifo;
+use mbtmap::filter::{parse_key_val, Filter, FilterArgs};
+use mbtmap::generate::{self, Shell};
+use mbtmap::http::{self, parse_netrc, Client};
+use mbtmap::journal::Journal;
+#[cfg(not(target_os = "wasi"))]
+use mbtmap::k8s::{self, Pod};
+use mbtmap::preset::Preset;
+use mbtmap::reload::{Reloader, Stamp};
+use mbtmap::resolver::Resolver;
+use mbtmap::rewrite::{self, Rewrite};
+use mbtmap::selftest;
+#[cfg(not(target_os = "wasi"))]
+use mbtmap::service::{Manager, Service};
+#[cfg(not(target_os = "wasi"))]
+use mbtmap::watch::Watcher;
+use mbtmap::webhook::Webhook;
+use mbtmap::{clipboard, dwarf, har, object, output, sha256, snapshot, store, Maps, Radix};
+#[cfg(not(target_os = "wasi"))]
+use mbtmap::{editor, serve};
+use std::collections::HashMap;
+use std::env::{self, current_dir};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, Stdin, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::process;
+#[cfg(not(target_os = "wasi"))]
+use std::process::Stdio;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Lines queued between the threads reading, resolving and writing as the input comes
+const PIPELINE: usize = 4096;
+
+/// Bytes of whole lines read from the input at a time when not buffering by line
+const CHUNK: usize = 1 << 20;
+
+/// Interval of checking whether the map of `mbtmap dev` was rebuilt
+const RELOAD: Duration = Duration::from_millis(500);
 
 #[derive(Parser, Debug)]
+#[command(
+    version,
+    about,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    after_help = exit::HELP
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print completion script for the shell
+    Completions { shell: Shell },
+    /// Print manual page in roff
+    Manpage,
+    /// Manage symbol bundles
+    Bundle {
+        #[command(subcommand)]
+        command: BundleCommand,
+    },
+    /// Resolve bare addresses given as arguments, one per line
+    Resolve {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Addresses in hex with `0x` prefix or in decimal, or as told by `--addr-radix`, or sums and differences of them and variables such as `0x2648d-0x100` or `base+off`
+        #[arg(required_unless_present = "addr_file")]
+        addrs: Vec<String>,
+        /// File of addresses to resolve after those given, the first field of each line, skipping blank lines and `#` comments
+        #[arg(long, value_name = "FILE")]
+        addr_file: Option<String>,
+        /// Read `--addr-file` as packed binary addresses, such as PC buffers dumped by a sampling profiler, skipping zeros padding them
+        #[arg(long, value_enum, value_name = "FORMAT", requires = "addr_file")]
+        binary_addrs: Option<AddrEncoding>,
+        /// Variable usable in addresses, defined by an address or an expression of previous variables, may be repeated
+        #[arg(long = "var", value_name = "NAME=ADDR", value_parser = parse_key_val)]
+        vars: Vec<(String, String)>,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+    /// Open the editor at the source location of an address, or of the first frame in the text
+    #[cfg(not(target_os = "wasi"))]
+    Open {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Address in hex with `0x` prefix or in decimal, or sum and differences of them and variables, or text of a frame
+        frame: String,
+        /// Variable usable in the address, defined by an address or an expression of previous variables, may be repeated
+        #[arg(long = "var", value_name = "NAME=ADDR", value_parser = parse_key_val)]
+        vars: Vec<(String, String)>,
+        /// Preset of the frame
+        #[arg(short = 'P', long, value_enum, default_value_t = Preset::Moonbit)]
+        preset: Preset,
+        /// Editor and its arguments, default to `$VISUAL`, `$EDITOR` or VS Code
+        #[arg(long)]
+        editor: Option<String>,
+    },
+    /// Resolve wasm frames in the JSON payload of an error-tracking webhook and print it again
+    Webhook {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Path to the payload, default to stdin
+        input: Option<String>,
+        /// Path to write the payload to, default to stdout
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+        /// Source map for frames of the named script, may be repeated
+        #[arg(long, value_name = "SCRIPT=PATH", value_parser = parse_key_val)]
+        map_for: Vec<(String, String)>,
+    },
+    /// Symbolicate a crash report of the form `{"module", "addrs", "message", "meta"}` an app emitted
+    Crash {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Path to the crash report, default to stdin
+        input: Option<String>,
+        /// Print the symbolicated report as JSON instead of text
+        #[arg(long)]
+        json: bool,
+        /// Source map for reports of the named module, may be repeated
+        #[arg(long, value_name = "MODULE=PATH", value_parser = parse_key_val)]
+        map_for: Vec<(String, String)>,
+        /// Use absolute path to source files resolved instead of relative to current working directory
+        #[arg(short = 'p', long)]
+        absolute_path: bool,
+    },
+    /// Annotate tracebacks anywhere in a HAR export, such as response bodies and console entries
+    Har {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Path to the HAR file, default to stdin
+        input: Option<String>,
+        /// Path to write the annotated HAR file to, default to stdout
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+        /// Preset of the tracebacks
+        #[arg(short = 'P', long, value_enum, default_value_t = Preset::Moonbit)]
+        preset: Preset,
+        /// Use absolute path to source files resolved instead of relative to current working directory
+        #[arg(short = 'p', long)]
+        absolute_path: bool,
+    },
+    /// Measure the time of loading a map, the memory it holds and the lookups per second in it
+    Bench {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Addresses to look up, spread over the range covered by the map
+        #[arg(long, default_value_t = 1_000_000)]
+        addresses: usize,
+        /// Print the report in JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Check that frames of tokens sampled across a map resolve back to their locations through the
+    /// filter with the preset and options given
+    Selftest {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Tokens to sample, spread over the map
+        #[arg(long, default_value_t = 200)]
+        samples: usize,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+    /// Save the WASM modules and JS bundles of a deployment along with their source maps, for
+    /// resolving traces offline once the assets are replaced
+    Snapshot {
+        /// URLs of pages or assets of the deployment, or files listing asset URLs one per line
+        #[arg(required = true)]
+        sources: Vec<String>,
+        /// Directory to save to, assets mirrored under `<host>/<path>` of their URLs
+        #[arg(short = 'o', long)]
+        output: PathBuf,
+        /// Save the assets flat under `<release>/` of the directory, as a symbol store holds them
+        #[arg(long)]
+        release: Option<String>,
+        #[command(flatten)]
+        client: ClientArgs,
+    },
+    /// Generate a source map from the DWARF line table of a WASM module built with debug info
+    Gen {
+        /// Path or URL to WASM module with DWARF
+        module: String,
+        /// Path to write the source map to, default to stdout
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+        /// Embed contents of the sources, relative paths taken from current working directory
+        #[arg(long)]
+        sources_content: bool,
+    },
+    /// Rewrite a source map, mapping source paths, stripping sources content, setting the debug ID or offsetting addresses
+    RewriteMap {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Path to write the source map to, default to stdout
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+        /// Replace the prefix FROM of source paths with TO, the first rule matching taking effect, may be repeated
+        #[arg(long, value_name = "FROM=TO", value_parser = parse_key_val)]
+        path_map: Vec<(String, String)>,
+        /// Drop contents of sources embedded in the map
+        #[arg(long)]
+        strip_sources_content: bool,
+        /// Set the debug ID, derived from SHA-256 of the map rewritten if not given
+        #[arg(long, value_name = "ID", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+        debug_id: Option<String>,
+        /// Add N to every address, negative to subtract, in hex with `0x` prefix or in decimal
+        #[arg(long, value_name = "N", allow_hyphen_values = true, value_parser = parse_offset, default_value = "0")]
+        offset: i64,
+        /// Warn about sources of the map rewritten missing on disk, failing if none exists
+        #[arg(long)]
+        verify_sources: bool,
+    },
+    /// Merge source maps into an index map of sections at the offsets, for modules concatenated
+    Merge {
+        /// Paths or URLs to source maps, or WASM modules referring to their source maps
+        #[arg(required = true)]
+        maps: Vec<String>,
+        /// Offset of each map in order, separated by commas
+        #[arg(long, value_name = "OFFSET,...", required = true, value_delimiter = ',', value_parser = parse_addr)]
+        offsets: Vec<u32>,
+        /// Path to write the index map to, default to stdout
+        #[arg(short = 'o', long)]
+        output: Option<String>,
+    },
+    /// Print the WASM addresses of the code at a source line, and set breakpoints there over the DevTools protocol with --cdp
+    Breakpoint {
+        /// Path or URL to source map, or WASM module referring to its source map
+        map: String,
+        /// Source line as PATH:LINE, the path matching the end of a source in the map
+        #[arg(value_parser = parse_source_line)]
+        location: (String, u32),
+        /// Set the breakpoints in the WASM modules of a Chrome or Node.js inspector target, given like `mbtmap cdp`, reporting where it pauses until it is gone
+        #[arg(long, value_name = "TARGET")]
+        cdp: Option<String>,
+    },
+    /// Connect to a Chrome or Node.js inspector target and attach source location to WASM traceback of its exceptions and console messages
+    Cdp {
+        /// WebSocket URL of the target, or HTTP URL of the inspector to pick its first target
+        target: String,
+        /// Path or URL to source map, or WASM module referring to its source map
+        #[arg(long)]
+        map: String,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+    /// Serve a WebSocket endpoint at /ws answering each message of log lines with them annotated
+    #[cfg(not(target_os = "wasi"))]
+    Serve {
+        /// Path or URL to source map, or WASM module referring to its source map
+        #[arg(long, required_unless_present = "bundle")]
+        map: Option<String>,
+        /// Path or URL to symbol bundle created by `mbtmap bundle create` in place of source map
+        #[arg(long, value_name = "PATH_OR_URL", conflicts_with = "map")]
+        bundle: Option<String>,
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8731")]
+        listen: String,
+        /// Environment variable holding bearer token required to upload source maps to /maps, refused unless set
+        #[arg(long, value_name = "VAR", default_value = "MBTMAP_UPLOAD_TOKEN")]
+        upload_token_env: String,
+        /// Directory or HTTP URL of the symbol store holding the releases of /ws?release=VERSION, loaded on first use
+        #[arg(long, value_name = "DIR_OR_URL")]
+        symbol_store: Option<String>,
+        /// Estimated memory of releases loaded and maps uploaded, the least recently used evicted beyond it, such as 512M
+        #[arg(long, value_name = "SIZE", default_value = "1G", value_parser = cache::parse_size)]
+        max_map_memory: usize,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+    /// Keep `mbtmap serve` running in the background, started at login by the service manager
+    #[cfg(not(target_os = "wasi"))]
+    Service {
+        #[command(subcommand)]
+        command: ServiceCommand,
+    },
+    /// Run the command again whenever files of the project change, attaching source location to WASM traceback in its output
+    #[cfg(not(target_os = "wasi"))]
+    Dev {
+        /// Path or URL to source map, or WASM module referring to its source map, or `auto` to find the one of the project built last
+        #[arg(long, default_value = "auto")]
+        map: String,
+        /// Command to run by the shell
+        #[arg(long, value_name = "COMMAND")]
+        run: String,
+        /// Directory to watch, may be repeated, default to current working directory
+        #[arg(long, value_name = "PATH")]
+        watch: Vec<PathBuf>,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+    /// Hold parsed source maps in a resident process, resolving invocations given `--use-daemon`
+    #[cfg(unix)]
+    Daemon {
+        /// Path of the Unix socket to listen on, default to `mbtmap.sock` in $XDG_RUNTIME_DIR
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+        /// Estimated memory of maps held, the least recently used evicted beyond it, such as 512M
+        #[arg(long, value_name = "SIZE", default_value = "1G", value_parser = cache::parse_size)]
+        max_map_memory: usize,
+    },
+    /// Create a named pipe and attach source location to WASM traceback written to it, opening it again for each writer
+    #[cfg(unix)]
+    Fifo {
+        /// Path of the named pipe, created unless already there
+        path: PathBuf,
+        /// Path or URL to source map, or WASM module referring to its source map
+        #[arg(long)]
+        map: String,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+    /// Attach to the log stream of a Docker container and attach source location to WASM traceback in it
+    #[cfg(unix)]
+    Docker {
+        /// Name or ID of the container
+        container: String,
+        /// Path or URL to source map, or WASM module referring to its source map
+        #[arg(long)]
+        map: String,
+        /// Print the logs so far and exit instead of following the stream across restarts
+        #[arg(long)]
+        no_follow: bool,
+        /// Unix socket of the Docker Engine, default to the one of $DOCKER_HOST or /var/run/docker.sock
+        #[arg(long, value_name = "PATH")]
+        socket: Option<PathBuf>,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+    /// Stream the logs of a Kubernetes pod through kubectl and attach source location to WASM traceback in them
+    #[cfg(not(target_os = "wasi"))]
+    K8s {
+        /// Name of the pod
+        pod: String,
+        /// Container of the pod, default to the only or default one
+        #[arg(short = 'c', long)]
+        container: Option<String>,
+        /// Namespace of the pod, default to the one of the kubeconfig context
+        #[arg(short = 'n', long)]
+        namespace: Option<String>,
+        /// Context of the kubeconfig, default to the current one
+        #[arg(long)]
+        context: Option<String>,
+        /// Keep streaming new lines, reconnecting when the container restarts
+        #[arg(long)]
+        follow: bool,
+        /// Path or URL to source map, or WASM module referring to its source map
+        #[arg(long)]
+        map: String,
+        #[command(flatten)]
+        filter: Box<FilterArgs>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum BundleCommand {
+    /// Bundle source maps, or WASM modules referring to their source maps, into a single file
+    Create {
+        /// Path to write the bundle to
+        #[arg(short = 'o', long)]
+        output: String,
+        /// Metadata recorded in the bundle, such as release version, may be repeated
+        #[arg(long, value_name = "KEY=VALUE", value_parser = parse_key_val)]
+        meta: Vec<(String, String)>,
+        /// Source maps or WASM modules, the first being the default
+        #[arg(required = true)]
+        maps: Vec<String>,
+    },
+}
+
+#[cfg(not(target_os = "wasi"))]
+#[derive(Subcommand, Debug)]
+enum ServiceCommand {
+    /// Install a systemd user unit, launchd agent or Windows logon task running `mbtmap serve`
+    /// with the arguments in the current directory, and start it
+    Install {
+        /// Print the unit instead of installing it
+        #[arg(long)]
+        print: bool,
+        /// Arguments of `mbtmap serve`, such as `--map app.wasm.map`
+        #[arg(required = true, trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+    /// Stop the service and remove it
+    Uninstall,
+}
+
+#[derive(clap::Args, Debug)]
 struct Args {
-    /// Path to source map
-    sourcemap: String,
-    /// Path to traceback containing mysterious WASM address to resolve, default to read from stdin
+    /// Path or URL to source map, or WASM module referring to its source map
+    #[arg(required_unless_present_any = ["auto", "bundle", "release"])]
+    sourcemap: Option<String>,
+    /// Path or URL to traceback containing mysterious WASM address to resolve, default to read from stdin
     input: Option<String>,
-    /// Print filtered result to stdout instead of stderr
-    #[arg(short = 'o', long)]
-    stdout: bool,
-    /// Use absolute path to source files resolved instead of relative to current working directory
-    #[arg(short = 'p', long)]
-    absolute_path: bool,
+    /// Read from multiple files or FIFOs at once, prefixing output lines with the path, may be repeated
+    #[arg(long = "input", value_name = "PATH", conflicts_with = "journal")]
+    inputs: Vec<String>,
+    /// Print lines of multiple inputs as they come instead of in the order of the inputs
+    #[arg(long, requires = "inputs")]
+    unordered: bool,
+    /// Read from the systemd journal, only the entries of the unit if given, instead of input
+    #[arg(long, value_name = "UNIT", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    journal: Option<String>,
+    /// Keep reading new entries of the journal as they are logged, implying --line-buffer
+    #[arg(long, requires = "journal")]
+    follow: bool,
+    /// Forward to the daemon listening on the socket, default to the one of `mbtmap daemon`, resolving in process if none runs
+    #[cfg(unix)]
+    #[arg(long, value_name = "SOCKET", num_args = 0..=1, require_equals = true, default_missing_value = "")]
+    use_daemon: Option<String>,
+    /// Read the traceback from the clipboard instead of input
+    #[arg(long, conflicts_with_all = ["input", "inputs", "journal"])]
+    from_clipboard: bool,
+    /// Put the annotated output on the clipboard as well as printing it
+    #[arg(long, conflicts_with_all = ["inputs", "line_buffer", "follow"])]
+    to_clipboard: bool,
     /// Filter with line buffer instead of waiting stdin to close and then filter all the input, see README for caveat related
     #[arg(short = 'l', long)]
     line_buffer: bool,
+    /// Find source map of the wasm-pack project containing current working directory, the only path given is taken as input
+    #[arg(short = 'a', long, conflicts_with = "bundle")]
+    auto: bool,
+    /// Path or URL to symbol bundle created by `mbtmap bundle create` in place of source map, the only path given is taken as input
+    #[arg(short = 'b', long, value_name = "PATH", conflicts_with = "release")]
+    bundle: Option<String>,
+    /// Release version or debug ID to look up in the symbol store in place of source map, the only path given is taken as input
+    #[arg(
+        long,
+        value_name = "VERSION",
+        requires = "symbol_store",
+        conflicts_with = "auto"
+    )]
+    release: Option<String>,
+    /// Directory or HTTP URL of the symbol store holding `<release>.symbols` or `<release>/` per release
+    #[arg(long, value_name = "DIR_OR_URL", requires = "release")]
+    symbol_store: Option<String>,
+    /// Refuse to resolve unless SHA-256 of the source map or WASM module, or the default one in bundle, matches
+    #[arg(long, value_name = "HEX")]
+    map_sha256: Option<String>,
+    #[command(flatten)]
+    filter: FilterArgs,
+    #[command(flatten)]
+    client: ClientArgs,
+}
+
+/// Options of fetching maps and bundles by `http://` or `https://` URL
+#[derive(clap::Args, Debug)]
+struct ClientArgs {
+    /// Extra header to send in the form of `Name: value`, may be repeated
+    #[arg(long = "header", value_name = "HEADER", value_parser = parse_header)]
+    headers: Vec<(String, String)>,
+    /// Environment variable holding bearer token to send
+    #[arg(long, value_name = "VAR", default_value = "MBTMAP_TOKEN")]
+    token_env: String,
+    /// Send the token and credentials over plaintext http:// too, where anyone on the network can read them, rather than only over https://
+    #[arg(long)]
+    allow_http_auth: bool,
+    /// netrc file of credentials, default to $NETRC or ~/.netrc
+    #[arg(long, value_name = "PATH")]
+    netrc: Option<String>,
+    /// HTTP proxy to fetch through, default to $http_proxy, or $https_proxy for https:// URLs
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+    /// Seconds each attempt of fetching may take, 0 for no limit
+    #[arg(long, value_name = "SECS", default_value_t = http::DEFAULT_TIMEOUT.as_secs())]
+    timeout: u64,
+    /// Times to retry fetching after connection failures, timeouts and server errors, with exponential backoff
+    #[arg(long, value_name = "N", default_value_t = http::DEFAULT_RETRIES)]
+    retries: u32,
+}
+
+fn parse_addr(s: &str) -> Result<u32, String> {
+    mbtmap::parse_addr(s).ok_or_else(|| format!("expected address, found `{s}`"))
+}
+
+/// Variables of address expressions, each evaluated with those defined before
+fn eval_vars(defs: &[(String, String)], radix: Radix) -> Result<HashMap<String, u32>> {
+    let mut vars = HashMap::new();
+    for (name, expr) in defs {
+        let value = radix.eval(expr, &vars)?;
+        vars.insert(name.clone(), value);
+    }
+    Ok(vars)
+}
+
+/// Encoding of packed binary addresses
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+enum AddrEncoding {
+    /// 32-bit little-endian
+    U32le,
+    /// 64-bit little-endian
+    U64le,
+}
+
+fn read_binary_addrs(path: &str, encoding: AddrEncoding) -> Result<Vec<u32>> {
+    let data = fs::read(path).map_err(|e| eyre!("failed to read {path}: {e}"))?;
+    let size = match encoding {
+        AddrEncoding::U32le => 4,
+        AddrEncoding::U64le => 8,
+    };
+    if data.len() % size != 0 {
+        bail!(
+            "{path} is {} bytes, not a multiple of {size} bytes of addresses",
+            data.len()
+        );
+    }
+    let mut addrs = Vec::with_capacity(data.len() / size);
+    for (i, bytes) in data.chunks_exact(size).enumerate() {
+        let addr = match encoding {
+            AddrEncoding::U32le => u64::from(u32::from_le_bytes(bytes.try_into().unwrap())),
+            AddrEncoding::U64le => u64::from_le_bytes(bytes.try_into().unwrap()),
+        };
+        if addr == 0 {
+            continue;
+        }
+        match u32::try_from(addr) {
+            Ok(addr) => addrs.push(addr),
+            Err(_) => bail!(
+                "address {addr:#x} out of range at byte {} of {path}",
+                i * size
+            ),
+        }
+    }
+    Ok(addrs)
+}
+
+fn read_addr_file(path: &str, radix: Radix, vars: &HashMap<String, u32>) -> Result<Vec<u32>> {
+    let mut addrs = Vec::new();
+    for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let Some(field) = line.split_whitespace().next() else {
+            continue;
+        };
+        if field.starts_with('#') {
+            continue;
+        }
+        match radix.eval(field, vars) {
+            Ok(addr) => addrs.push(addr),
+            Err(e) => bail!("{e} at line {} of {path}", i + 1),
+        }
+    }
+    Ok(addrs)
+}
+
+fn parse_offset(s: &str) -> Result<i64, String> {
+    let (negative, n) = match s.strip_prefix('-') {
+        Some(n) => (true, n),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let n = match n.strip_prefix("0x") {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => n.parse(),
+    }
+    .map_err(|_| format!("invalid offset `{s}`"))?;
+    Ok(if negative { -n } else { n })
+}
+
+fn parse_source_line(s: &str) -> Result<(String, u32), String> {
+    let (path, line) = s
+        .rsplit_once(':')
+        .ok_or_else(|| format!("expected PATH:LINE, got `{s}`"))?;
+    let line = line
+        .parse()
+        .ok()
+        .filter(|&line| line > 0)
+        .ok_or_else(|| format!("invalid line `{line}`"))?;
+    Ok((path.to_owned(), line))
+}
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    s.split_once(':')
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .ok_or_else(|| format!("expected `Name: value`, found `{s}`"))
+}
+
+/// Client from the environment, printing its warnings to stderr
+fn client() -> Client {
+    let mut client = Client::from_env();
+    client.warn = Some(|message| eprintln!("warning: {message}"));
+    client
+}
+
+impl ClientArgs {
+    fn client(&self) -> Result<Client> {
+        let mut client = client();
+        client.headers = self.headers.clone();
+        client.token = env::var(&self.token_env).ok();
+        client.plaintext_auth = self.allow_http_auth;
+        if let Some(netrc) = &self.netrc {
+            client.netrc = parse_netrc(&fs::read_to_string(netrc)?);
+        }
+        if let Some(proxy) = &self.proxy {
+            client.proxy = Some(proxy.parse()?);
+            client.https_proxy = client.proxy.clone();
+        }
+        client.timeout = (self.timeout != 0).then(|| Duration::from_secs(self.timeout));
+        client.retries = self.retries;
+        Ok(client)
+    }
 }
 
 #[derive(Debug)]
 enum Input {
     File(BufReader<File>),
     Stdin(Stdin),
+    Journal(Journal),
+    /// Input fetched from a URL or read from the clipboard
+    Fetched(Cursor<Vec<u8>>),
 }
 
 impl Input {
-    fn open(input: Option<String>) -> Result<Self> {
+    fn open(client: &Client, input: Option<String>) -> Result<Self> {
         let this = match input {
-            Some(input) => Self::File(BufReader::new(OpenOptions::new().read(true).open(input)?)),
+            Some(input) if http::is_url(&input) || object::is_url(&input) => {
+                Self::Fetched(Cursor::new(client.read(&input).class(Class::Input)?))
+            }
+            Some(input) => {
+                let file = OpenOptions::new().read(true).open(&input);
+                let file = file.map_err(|e| eyre!("failed to open {input}: {e}"));
+                Self::File(BufReader::new(file.class(Class::Input)?))
+            }
             None => Self::Stdin(io::stdin()),
         };
         Ok(this)
     }
 
-    fn read_to_string(&mut self) -> Result<String> {
-        let mut buf = String::new();
-        match self {
-            Input::File(file) => file.read_to_string(&mut buf)?,
-            Input::Stdin(stdin) => stdin.read_to_string(&mut buf)?,
+    /// Read stdin to its end before the map is loaded, as the map may be rebuilt until then, keeping
+    /// what exceeds `CHUNK` in a temporary file rather than in memory
+    fn spool(mut self) -> Result<Self> {
+        let Input::Stdin(stdin) = &mut self else {
+            return Ok(self);
         };
-        Ok(buf)
+        let mut head = Vec::new();
+        stdin.by_ref().take(CHUNK as u64).read_to_end(&mut head)?;
+        if head.len() < CHUNK {
+            return Ok(Input::Fetched(Cursor::new(head)));
+        }
+        let path = env::temp_dir().join(format!("mbtmap-{}.spool", process::id()));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        // deleted once closed
+        fs::remove_file(&path)?;
+        file.write_all(&head)?;
+        io::copy(stdin, &mut file)?;
+        file.rewind()?;
+        Ok(Input::File(BufReader::new(file)))
+    }
+
+    /// Append whole lines of at least `CHUNK` bytes to `buf`, or the rest of the input, returning
+    /// the length appended or 0 at the end of the input
+    fn read_chunk(&mut self, buf: &mut String) -> Result<usize> {
+        let start = buf.len();
+        while buf.len() - start < CHUNK && self.read_line(buf)? != 0 {}
+        Ok(buf.len() - start)
     }
 
     fn read_line(&mut self, buf: &mut String) -> Result<usize> {
         Ok(match self {
             Input::File(file) => file.read_line(buf)?,
             Input::Stdin(stdin) => stdin.read_line(buf)?,
+            Input::Journal(journal) => journal.read_line(buf)?,
+            Input::Fetched(data) => data.read_line(buf)?,
         })
     }
 }
 
-fn read_source_map(path: &str) -> Result<SourceMap> {
-    Ok(SourceMap::from_reader(
-        OpenOptions::new().read(true).open(path)?,
-    )?)
+fn main() -> process::ExitCode {
+    match run() {
+        Ok(()) => process::ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {e:?}");
+            process::ExitCode::from(exit::code(&e))
+        }
+    }
+}
+
+/// Write `s` to stdout, as `print!` does but without panicking once the rest is no longer wanted,
+/// such as when piped to `head`
+fn print_all(s: &str) -> io::Result<()> {
+    match io::stdout().lock().write_all(s.as_bytes()) {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => written,
+    }
+}
+
+fn run() -> Result<()> {
+    let cli = Cli::parse();
+    let mut command = Cli::command();
+    command.build();
+    match cli.command {
+        Some(Command::Completions { shell }) => print_all(&generate::completions(&command, shell))?,
+        Some(Command::Manpage) => print_all(&generate::manpage(&command))?,
+        Some(Command::Bundle {
+            command: BundleCommand::Create { output, meta, maps },
+        }) => Bundle::create(&maps, &meta)?.write(&output)?,
+        Some(Command::Resolve {
+            map,
+            addrs,
+            addr_file,
+            binary_addrs,
+            vars,
+            filter,
+        }) => {
+            let vars = eval_vars(&vars, filter.addr_radix)?;
+            let mut addrs = addrs
+                .iter()
+                .map(|addr| filter.addr_radix.eval(addr, &vars))
+                .collect::<Result<Vec<_>>>()?;
+            match (addr_file, binary_addrs) {
+                (Some(addr_file), Some(encoding)) => {
+                    addrs.extend(read_binary_addrs(&addr_file, encoding)?)
+                }
+                (Some(addr_file), None) => {
+                    addrs.extend(read_addr_file(&addr_file, filter.addr_radix, &vars)?)
+                }
+                (None, _) => {}
+            }
+            let mut resolver = Filter::new(&filter, None)?;
+            let maps = Maps::open(&client(), &map, &filter.map_for)?;
+            resolver.resolve_addrs(&maps, &addrs);
+            resolver.finish(&maps)?
+        }
+        #[cfg(not(target_os = "wasi"))]
+        Some(Command::Open {
+            map,
+            frame,
+            vars,
+            preset,
+            editor,
+        }) => {
+            let resolver = Resolver::new(client().source_map(&map)?, preset)?;
+            let vars = eval_vars(&vars, Radix::Dec)?;
+            let location = match Radix::Dec.eval(&frame, &vars) {
+                Ok(addr) => resolver.resolve(addr),
+                Err(_) => resolver.first_frame(&frame),
+            };
+            let location = location.ok_or_else(|| eyre!("failed to resolve {frame}"))?;
+            let editor = editor.unwrap_or_else(editor::default_editor);
+            let status = editor::command(&editor, &location)?
+                .status()
+                .map_err(|e| eyre!("failed to run {editor}: {e}"))?;
+            if !status.success() {
+                bail!("{editor} exited with {status}");
+            }
+        }
+        Some(Command::Bench {
+            map,
+            addresses,
+            json,
+        }) => {
+            let report = bench::run(&client(), &map, addresses)?;
+            if json {
+                println!("{}", report.to_json());
+            } else {
+                print!("{report}");
+            }
+        }
+        Some(Command::Selftest {
+            map,
+            samples,
+            filter,
+        }) => {
+            let maps = Maps::open(&client(), &map, &filter.map_for)?;
+            let report = selftest::run(&maps, &filter, samples)?;
+            print!("{report}");
+            if !report.failures.is_empty() {
+                bail!(
+                    "{} of {} frames did not resolve back",
+                    report.failures.len(),
+                    report.frames
+                );
+            }
+        }
+        Some(Command::Snapshot {
+            sources,
+            output,
+            release,
+            client,
+        }) => {
+            let snapshot =
+                snapshot::snapshot(&client.client()?, &sources, &output, release.as_deref())?;
+            eprintln!(
+                "{} assets and {} maps saved to {}",
+                snapshot.assets,
+                snapshot.maps,
+                output.display()
+            );
+        }
+        Some(Command::Webhook {
+            map,
+            input,
+            output,
+            map_for,
+        }) => {
+            let maps = Maps::open(&client(), &map, &map_for)?;
+            let mut payload: serde_json::Value = match input {
+                Some(input) => {
+                    serde_json::from_reader(BufReader::new(File::open(input).class(Class::Input)?))?
+                }
+                None => serde_json::from_reader(io::stdin().lock())?,
+            };
+            let resolved = Webhook::default().symbolicate(&mut payload, &maps);
+            eprintln!("{resolved} frames resolved");
+            let mut w: Box<dyn Write> = match output {
+                Some(output) => Box::new(File::create(output)?),
+                None => Box::new(io::stdout().lock()),
+            };
+            serde_json::to_writer(&mut w, &payload)?;
+            writeln!(w)?;
+        }
+        Some(Command::Crash {
+            map,
+            input,
+            json,
+            map_for,
+            absolute_path,
+        }) => {
+            let maps = Maps::open(&client(), &map, &map_for)?;
+            let cwd = if absolute_path {
+                None
+            } else {
+                Some(current_dir()?)
+            };
+            let data = match input {
+                Some(input) => fs::read(input).class(Class::Input)?,
+                None => {
+                    let mut data = Vec::new();
+                    io::stdin().lock().read_to_end(&mut data)?;
+                    data
+                }
+            };
+            let report = Crash::parse(&data)?.symbolicate(&maps, &cwd);
+            if json {
+                println!("{report}");
+            } else {
+                print!("{}", crash::render_text(&report));
+            }
+        }
+        Some(Command::Har {
+            map,
+            input,
+            output,
+            preset,
+            absolute_path,
+        }) => {
+            let map = client().source_map(&map)?;
+            let cwd = if absolute_path {
+                None
+            } else {
+                Some(current_dir()?)
+            };
+            let mut archive: serde_json::Value = match input {
+                Some(input) => {
+                    serde_json::from_reader(BufReader::new(File::open(input).class(Class::Input)?))?
+                }
+                None => serde_json::from_reader(io::stdin().lock())?,
+            };
+            let resolved = har::annotate(&mut archive, &map, preset, cwd)?;
+            eprintln!("{resolved} frames resolved");
+            let mut w: Box<dyn Write> = match output {
+                Some(output) => Box::new(File::create(output)?),
+                None => Box::new(io::stdout().lock()),
+            };
+            serde_json::to_writer_pretty(&mut w, &archive)?;
+            writeln!(w)?;
+        }
+        Some(Command::Gen {
+            module,
+            output,
+            sources_content,
+        }) => {
+            let mut map = dwarf::source_map(&client().read(&module)?)?;
+            if sources_content {
+                let mut missing = 0;
+                for i in 0..map.get_source_count() {
+                    let contents = map.get_source(i).and_then(|s| fs::read_to_string(s).ok());
+                    missing += usize::from(contents.is_none());
+                    map.set_source_contents(i, contents.as_deref());
+                }
+                if missing > 0 {
+                    eprintln!(
+                        "warning: {missing} of {} sources not found, left without contents",
+                        map.get_source_count()
+                    );
+                }
+            }
+            match output {
+                Some(output) => map.to_writer(File::create(output)?)?,
+                None => map.to_writer(io::stdout().lock())?,
+            }
+        }
+        Some(Command::RewriteMap {
+            map,
+            output,
+            path_map,
+            strip_sources_content,
+            debug_id,
+            offset,
+            verify_sources,
+        }) => {
+            let debug_id = match debug_id.as_deref() {
+                None => None,
+                Some("") => Some(None),
+                Some(id) => Some(Some(
+                    id.parse().map_err(|_| eyre!("invalid debug ID {id}"))?,
+                )),
+            };
+            let rewrite = Rewrite {
+                path_map,
+                strip_sources_content,
+                debug_id,
+                offset,
+            };
+            let map = rewrite.apply(&client().source_map(&map)?)?;
+            if verify_sources {
+                let missing = map
+                    .sources()
+                    .filter(|source| !Path::new(source).exists())
+                    .collect::<Vec<_>>();
+                for source in &missing {
+                    eprintln!("warning: missing source {source}");
+                }
+                if !missing.is_empty() && missing.len() == map.get_source_count() as usize {
+                    bail!("none of the {} sources exists", missing.len());
+                }
+            }
+            match output {
+                Some(output) => map.to_writer(File::create(output)?)?,
+                None => map.to_writer(io::stdout().lock())?,
+            }
+        }
+        Some(Command::Merge {
+            maps,
+            offsets,
+            output,
+        }) => {
+            if maps.len() != offsets.len() {
+                bail!("{} maps but {} offsets", maps.len(), offsets.len());
+            }
+            let client = client();
+            let maps = offsets
+                .into_iter()
+                .zip(&maps)
+                .map(|(offset, map)| Ok((offset, client.source_map(map)?)))
+                .collect::<Result<Vec<_>>>()?;
+            let index = rewrite::merge(maps)?;
+            match output {
+                Some(output) => index.to_writer(File::create(output)?)?,
+                None => index.to_writer(io::stdout().lock())?,
+            }
+        }
+        Some(Command::Breakpoint {
+            map,
+            location: (path, line),
+            cdp,
+        }) => breakpoint(&map, &path, line, cdp.as_deref())?,
+        Some(Command::Cdp {
+            target,
+            map,
+            filter,
+        }) => {
+            let mut cdp = Cdp::connect(&client(), &target)?;
+            stream(&map, &filter, move |buf| cdp.read_line(buf))?
+        }
+        #[cfg(not(target_os = "wasi"))]
+        Some(Command::Serve {
+            map,
+            bundle,
+            listen,
+            upload_token_env,
+            symbol_store,
+            max_map_memory,
+            filter,
+        }) => {
+            let client = client();
+            let maps = match (bundle, map) {
+                (Some(bundle), _) => {
+                    let data = client.read(&bundle).class(Class::MapLoad)?;
+                    Bundle::parse(&data, &bundle)
+                        .class(Class::MapLoad)?
+                        .into_maps(&client, &filter.map_for)?
+                }
+                (None, map) => Maps::open(&client, &map.unwrap(), &filter.map_for)?,
+            };
+            let options = serve::Options {
+                token: env::var(upload_token_env)
+                    .ok()
+                    .filter(|token| !token.is_empty()),
+                symbol_store,
+                client,
+                max_map_memory,
+            };
+            serve::serve(&listen, &filter, &maps, options)?
+        }
+        #[cfg(unix)]
+        Some(Command::Fifo { path, map, filter }) => {
+            let mut fifo = Fifo::create(path)?;
+            stream(&map, &filter, move |buf| fifo.read_line(buf))?
+        }
+        #[cfg(unix)]
+        Some(Command::Docker {
+            container,
+            map,
+            no_follow,
+            socket,
+            filter,
+        }) => {
+            let socket = socket.unwrap_or_else(docker::default_socket);
+            let mut logs = docker::Logs::open(socket, &container, !no_follow)?;
+            stream(&map, &filter, move |buf| logs.read_line(buf))?
+        }
+        #[cfg(not(target_os = "wasi"))]
+        Some(Command::K8s {
+            pod,
+            container,
+            namespace,
+            context,
+            follow,
+            map,
+            filter,
+        }) => {
+            let pod = Pod {
+                name: pod,
+                container,
+                namespace,
+                context,
+            };
+            let mut logs = k8s::Logs::open(pod, follow)?;
+            stream(&map, &filter, move |buf| logs.read_line(buf))?
+        }
+        #[cfg(not(target_os = "wasi"))]
+        Some(Command::Service {
+            command: ServiceCommand::Install { print, args },
+        }) => {
+            let service = Service::new(args)?;
+            let manager = Manager::current();
+            if print {
+                print!("{}", service.unit(manager)?);
+            } else {
+                let path = service.install(manager)?;
+                eprintln!("{} installed and started", path.display());
+            }
+        }
+        #[cfg(not(target_os = "wasi"))]
+        Some(Command::Service {
+            command: ServiceCommand::Uninstall,
+        }) => Manager::current().uninstall()?,
+        #[cfg(not(target_os = "wasi"))]
+        Some(Command::Dev {
+            map,
+            run,
+            mut watch,
+            filter,
+        }) => {
+            if watch.is_empty() {
+                watch.push(PathBuf::from("."));
+            }
+            let mut watcher = Watcher::new(watch);
+            loop {
+                eprintln!("[mbtmap] running {run}");
+                let status = dev(&map, &run, &filter)?;
+                eprintln!("[mbtmap] {status}, waiting for changes");
+                watcher.wait();
+            }
+        }
+        #[cfg(unix)]
+        Some(Command::Daemon {
+            socket,
+            max_map_memory,
+        }) => daemon(
+            socket.unwrap_or_else(daemon::default_socket),
+            max_map_memory,
+        )?,
+        None => filter(cli.args)?,
+    }
+    Ok(())
 }
 
-fn resolve(map: &SourceMap, addr: &str, cwd: &Option<PathBuf>) -> Option<String> {
-    let addr = if addr.starts_with("0x") {
-        usize::from_str_radix(addr.strip_prefix("0x").unwrap(), 16).ok()?
+fn filter(mut args: Args) -> Result<()> {
+    // the only path given is taken as input if source map comes from elsewhere
+    let elsewhere = args.auto || args.bundle.is_some() || args.release.is_some();
+    if elsewhere && args.input.is_some() {
+        bail!("source map can not be given with --auto, --bundle or --release");
+    }
+    if (args.journal.is_some() || !args.inputs.is_empty() || args.from_clipboard)
+        && (args.input.is_some() || elsewhere && args.sourcemap.is_some())
+    {
+        bail!("input can not be given with --journal, --input or --from-clipboard");
+    }
+    // traceback read from stdin before forwarding, resolved in process if no daemon runs
+    let mut read = None;
+    #[cfg(unix)]
+    if let Some(socket) = &args.use_daemon {
+        let forwarded = !elsewhere
+            && args.journal.is_none()
+            && args.inputs.is_empty()
+            && !(args.line_buffer || args.follow || args.from_clipboard || args.to_clipboard)
+            && args.map_sha256.is_none();
+        if forwarded {
+            let socket = match socket.as_str() {
+                "" => daemon::default_socket(),
+                socket => PathBuf::from(socket),
+            };
+            let request = daemon::Request {
+                args: env::args().collect(),
+                cwd: current_dir()?,
+                input: match args.input {
+                    Some(_) => None,
+                    None => Some(io::read_to_string(io::stdin())?),
+                },
+            };
+            if daemon::forward(&socket, &request)?.is_some() {
+                return Ok(());
+            }
+            read = request.input;
+        }
+    }
+    let (sourcemap, input) = if elsewhere {
+        (None, args.sourcemap)
     } else {
-        addr.parse().ok()?
+        (args.sourcemap, args.input)
     };
-    let token = map.lookup_token(0, addr as u32)?;
-    let path = match token.get_source() {
-        Some(s) => match cwd {
-            Some(cwd) => {
-                let path = PathBuf::from(s);
-                match path.strip_prefix(cwd) {
-                    Ok(path) => path.to_str().unwrap_or(s).to_owned(),
-                    Err(_) => s.to_owned(),
-                }
+    let sourcemap = if args.auto {
+        let sourcemap = wasm_pack(&current_dir()?)
+            .ok_or_else(|| eyre!("no source map found in pkg/ of wasm-pack project"))?;
+        Some(sourcemap.to_string_lossy().into_owned())
+    } else {
+        sourcemap
+    };
+    let client = args.client.client()?;
+    let load_bundle = || -> Result<Option<Bundle>> {
+        Ok(match (&args.bundle, &args.release, &args.symbol_store) {
+            (Some(bundle), _, _) => {
+                let data = client.read(bundle).class(Class::MapLoad)?;
+                Some(Bundle::parse(&data, bundle).class(Class::MapLoad)?)
+            }
+            (None, Some(release), Some(symbol_store)) => {
+                let mut bundle =
+                    store::lookup(&client, symbol_store, release).class(Class::MapLoad)?;
+                // for `--git-root` to take the build commit from
+                bundle
+                    .metadata
+                    .entry("release".to_owned())
+                    .or_insert_with(|| release.clone());
+                Some(bundle)
             }
-            None => s.to_owned(),
-        },
-        None => "<unknown>".to_string(),
+            _ => None,
+        })
     };
-    Some(format!(
-        "{path}:{}:{}",
-        token.get_src_line() + 1,
-        token.get_src_col() + 1
-    ))
-}
-
-fn main() -> Result<()> {
-    let args = Args::parse();
-    let mut input = Input::open(args.input)?;
-    let cwd = if args.absolute_path {
-        None
+    let bundle = load_bundle()?;
+    if let Some(expected) = &args.map_sha256 {
+        let (digest, path) = match &bundle {
+            Some(bundle) => (bundle.entries[0].sha256.clone(), &bundle.entries[0].name),
+            None => {
+                let sourcemap = sourcemap.as_ref().unwrap();
+                (sha256::hex(&client.read(sourcemap)?), sourcemap)
+            }
+        };
+        if !digest.eq_ignore_ascii_case(expected) {
+            bail!("SHA-256 of {path} is {digest}, not {expected} as expected, refusing to resolve with a different build");
+        }
+    }
+    let open_maps = |bundle: Option<Bundle>| match bundle {
+        Some(bundle) => bundle.into_maps(&client, &args.filter.map_for),
+        None => Maps::open(&client, sourcemap.as_deref().unwrap(), &args.filter.map_for),
+    };
+    if !args.inputs.is_empty() {
+        if cfg!(target_os = "wasi") {
+            bail!("--inputs reads inputs by threads, which WASI does not run");
+        }
+        let filters = args
+            .inputs
+            .iter()
+            .map(|input| Ok(Filter::new(&args.filter, bundle.as_ref())?.label(input)))
+            .collect::<Result<Vec<_>>>()?;
+        return multiplex(
+            &client,
+            &args.inputs,
+            filters,
+            open_maps(bundle)?,
+            args.unordered,
+        );
+    }
+    // output is sent to stdout of the writer to tell it from warnings, and printed where asked
+    let print_stdout = args.filter.stdout;
+    args.filter.stdout |= args.to_clipboard;
+    let mut filter = Filter::new(&args.filter, bundle.as_ref())?;
+    let mut input = match &args.journal {
+        Some(unit) => Input::Journal(Journal::open(
+            Some(unit.as_str()).filter(|unit| !unit.is_empty()),
+            args.follow,
+        )?),
+        None if args.from_clipboard => Input::Fetched(Cursor::new(clipboard::read()?.into_bytes())),
+        None => Input::open(&client, input)?,
+    };
+    if args.line_buffer || args.follow {
+        // a store is looked up once, as a release is not rebuilt in place
+        let mut watched = match (&args.bundle, &args.symbol_store) {
+            (Some(bundle), _) => vec![bundle.as_str()],
+            (None, Some(_)) => Vec::new(),
+            (None, None) => sourcemap.as_deref().into_iter().collect(),
+        };
+        watched.extend(args.filter.map_for.iter().map(|(_, path)| path.as_str()));
+        return pipeline(
+            filter,
+            open_maps(bundle)?,
+            Reloader::new(&watched),
+            || open_maps(load_bundle()?),
+            move |buf| input.read_line(buf),
+        );
+    }
+    input = match read {
+        Some(read) => Input::Fetched(Cursor::new(read.into_bytes())),
+        None => input.spool()?,
+    };
+    let maps = open_maps(bundle)?;
+    if !args.to_clipboard {
+        process_chunks(&mut filter, &maps, &mut input)?;
+        return filter.finish(&maps);
+    }
+    if cfg!(target_os = "wasi") {
+        bail!("--to-clipboard runs the clipboard tool, which WASI does not allow");
+    }
+    let (writer, queued) = mpsc::sync_channel::<(bool, String)>(PIPELINE);
+    let copying = thread::spawn(move || -> Result<()> {
+        let mut copied = String::new();
+        for (stdout, s) in queued {
+            if stdout {
+                copied.push_str(&s);
+            }
+            output::write(stdout && print_stdout, &s)?;
+        }
+        output::flush()?;
+        clipboard::write(&copied)
+    });
+    let mut filter = filter.writer(writer);
+    let finished =
+        process_chunks(&mut filter, &maps, &mut input).and_then(|()| filter.finish(&maps));
+    copying.join().unwrap()?;
+    finished
+}
+
+/// Resolve the input by chunks of whole lines, so that the input is never held in memory at once
+fn process_chunks(filter: &mut Filter, maps: &Maps, input: &mut Input) -> Result<()> {
+    let mut chunk = String::new();
+    while input.read_chunk(&mut chunk)? != 0 {
+        filter.process(maps, &chunk);
+        chunk.clear();
+    }
+    Ok(())
+}
+
+/// Source map given, or the one of the project in current working directory built last if `auto`
+#[cfg(not(target_os = "wasi"))]
+fn dev_map(map: &str) -> Result<String> {
+    if map != "auto" {
+        return Ok(map.to_owned());
+    }
+    let cwd = current_dir()?;
+    let map = wasm_pack(&cwd)
+        .or_else(|| find_map(&cwd))
+        .ok_or_else(|| eyre!("no source map found in pkg/ or target/ of the project"))?;
+    Ok(map.to_string_lossy().into_owned())
+}
+
+/// Run the command once, resolving lines of its stdout and stderr as they come with the map
+/// loaded again whenever rebuilt
+#[cfg(not(target_os = "wasi"))]
+fn dev(map: &str, run: &str, args: &FilterArgs) -> Result<process::ExitStatus> {
+    let (shell, flag) = if cfg!(windows) {
+        ("cmd", "/C")
     } else {
-        Some(current_dir()?)
+        ("sh", "-c")
     };
-    // "wasm://wasm/000c5502:wasm-function[1060]:0x2648d"
-    let re = Regex::new(r"wasm\://.*\:.*\:((?:0x)?[[:xdigit:]]+)")?;
-    if !args.line_buffer {
-        let input = input.read_to_string()?;
-        let map = read_source_map(&args.sourcemap)?;
-        let result = re.replace_all(&input, |caps: &Captures| {
-            format!(
-                "{} {}",
-                &caps[0],
-                resolve(&map, &caps[1], &cwd).unwrap_or_default()
-            )
+    let mut child = process::Command::new(shell)
+        .args([flag, run])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| eyre!("failed to run {run}: {e}"))?;
+    let (sender, lines) = mpsc::sync_channel(PIPELINE);
+    let readers = [
+        Box::new(child.stdout.take().unwrap()) as Box<dyn Read + Send>,
+        Box::new(child.stderr.take().unwrap()),
+    ];
+    for output in readers {
+        let sender = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines() {
+                let Ok(line) = line else { return };
+                if sender.send(line + "\n").is_err() {
+                    return;
+                }
+            }
         });
-        if args.stdout {
-            print!("{result}")
-        } else {
-            eprint!("{result}")
+    }
+    drop(sender);
+    let client = client();
+    let mut filter = Filter::new(args, None)?;
+    // map with the path and modification time it was loaded from
+    let mut loaded: Option<(Maps, String, Option<SystemTime>)> = None;
+    let mut checked: Option<Instant> = None;
+    while let Some(line) = recv_flushing(&lines) {
+        if checked.is_none_or(|checked| checked.elapsed() >= RELOAD) {
+            checked = Some(Instant::now());
+            let path = dev_map(map).ok();
+            let modified = path
+                .as_ref()
+                .and_then(|path| fs::metadata(path).and_then(|m| m.modified()).ok());
+            let stale = loaded
+                .as_ref()
+                .is_none_or(|(_, loaded, at)| Some(loaded) != path.as_ref() || *at != modified);
+            if let (true, Some(path)) = (stale, path) {
+                match Maps::open(&client, &path, &args.map_for) {
+                    Ok(maps) => loaded = Some((maps, path, modified)),
+                    Err(e) => {
+                        _ = output::write(
+                            false,
+                            &format!("[mbtmap] warning: failed to load {path}: {e}\n"),
+                        )
+                    }
+                }
+            }
         }
-    } else {
-        let map = read_source_map(&args.sourcemap)?;
+        match &loaded {
+            Some((maps, _, _)) => filter.process(maps, &line),
+            None => _ = output::write(false, &line),
+        }
+    }
+    match &loaded {
+        Some((maps, _, _)) => filter.finish(maps)?,
+        None => {
+            if let Err(e) = dev_map(map) {
+                eprintln!("[mbtmap] warning: {e}");
+            }
+        }
+    }
+    Ok(child.wait()?)
+}
+
+/// Serve invocations forwarded by `--use-daemon`, with paths taken relative to the directory of the
+/// client and maps kept parsed until their files change
+#[cfg(unix)]
+fn daemon(socket: PathBuf, max_map_memory: usize) -> Result<()> {
+    let cache = cache::Cache::new(max_map_memory);
+    daemon::serve(&socket, |request, writer| {
+        let mut args = Cli::try_parse_from(&request.args)?.args;
+        let cwd = request.cwd;
+        let absolute = |path: &mut String| {
+            if !http::is_url(path) && !object::is_url(path) {
+                *path = cwd.join(&*path).to_string_lossy().into_owned();
+            }
+        };
+        let sourcemap = args
+            .sourcemap
+            .as_mut()
+            .ok_or_else(|| eyre!("source map required"))?;
+        absolute(sourcemap);
+        args.filter
+            .map_for
+            .iter_mut()
+            .for_each(|(_, path)| absolute(path));
+        args.filter.wasm.iter_mut().for_each(absolute);
+        args.filter.symbols.iter_mut().for_each(absolute);
+        let client = args.client.client()?;
+        let mut input = match request.input {
+            Some(input) => Input::Fetched(Cursor::new(input.into_bytes())),
+            None => {
+                let mut input = args.input.ok_or_else(|| eyre!("input required"))?;
+                absolute(&mut input);
+                Input::open(&client, Some(input))?
+            }
+        };
+        // replaced files are told apart from those written in place by the inode
+        let stamp = Stamp::of(sourcemap);
+        let key = format!("{sourcemap} {stamp:?} {:?}", args.filter.map_for);
+        let maps = cache.get(&key, || {
+            Maps::open(&client, sourcemap, &args.filter.map_for)
+        })?;
+        let mut filter = Filter::new(&args.filter, None)?
+            .cwd(cwd.clone())
+            .writer(writer);
+        process_chunks(&mut filter, &maps, &mut input)?;
+        filter.finish(&maps)
+    })
+}
+
+/// Open the maps again if their files changed, keeping the old ones if they fail to load
+fn reload(reloader: &mut Reloader, reopen: impl Fn() -> Result<Maps>, maps: &mut Maps) {
+    let changed = reloader.changed();
+    if changed.is_empty() {
+        return;
+    }
+    let changed = changed.join(", ");
+    let warning = match reopen() {
+        Ok(reopened) => {
+            *maps = reopened;
+            format!("warning: {changed} changed, reloaded\n")
+        }
+        Err(e) => format!("warning: {changed} changed, failed to reload: {e}\n"),
+    };
+    _ = output::write(false, &warning);
+}
+
+/// Resolve lines as they come, read and written by threads of their own through bounded queues, so
+/// that slow resolution with huge maps does not stall reading and block the application logging
+#[cfg(not(target_os = "wasi"))]
+fn pipeline(
+    filter: Filter,
+    mut maps: Maps,
+    mut reloader: Reloader,
+    reopen: impl Fn() -> Result<Maps>,
+    mut read_line: impl FnMut(&mut String) -> Result<usize> + Send + 'static,
+) -> Result<()> {
+    let (sender, lines) = mpsc::sync_channel(PIPELINE);
+    // detached so that a reader blocked on input does not keep the process alive after an error
+    thread::spawn(move || loop {
         let mut buf = String::new();
-        loop {
-            buf.clear();
-            if input.read_line(&mut buf)? == 0 {
+        let line = match read_line(&mut buf) {
+            Ok(0) => return,
+            line => line.map(|_| buf),
+        };
+        let error = line.is_err();
+        if sender.send(line).is_err() || error {
+            return;
+        }
+    });
+    let (writer, queued) = mpsc::sync_channel::<(bool, String)>(PIPELINE);
+    let writing = thread::spawn(move || -> io::Result<()> {
+        while let Some((stdout, s)) = recv_flushing(&queued) {
+            match output::write(stdout, &s) {
+                // the rest of output is no longer wanted, such as when piped to `head`
+                Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+                written => written?,
+            }
+        }
+        match output::flush() {
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+            flushed => flushed,
+        }
+    });
+    let mut filter = filter.writer(writer);
+    let mut result = Ok(());
+    for line in lines {
+        if writing.is_finished() {
+            break;
+        }
+        reload(&mut reloader, &reopen, &mut maps);
+        match line {
+            Ok(line) => filter.process(&maps, &line),
+            Err(e) => {
+                result = Err(e);
                 break;
             }
-            let result = re.replace_all(&buf, |caps: &Captures| {
-                format!(
-                    "{} {}",
-                    &caps[0],
-                    resolve(&map, &caps[1], &cwd).unwrap_or_default()
-                )
+        }
+    }
+    let finished = filter.finish(&maps);
+    writing.join().unwrap()?;
+    result.and(finished)
+}
+
+/// Resolve lines as they come, read, resolved and written in turn as WASI runs no threads
+#[cfg(target_os = "wasi")]
+fn pipeline(
+    mut filter: Filter,
+    mut maps: Maps,
+    mut reloader: Reloader,
+    reopen: impl Fn() -> Result<Maps>,
+    mut read_line: impl FnMut(&mut String) -> Result<usize> + Send + 'static,
+) -> Result<()> {
+    let mut line = String::new();
+    while read_line(&mut line)? != 0 {
+        reload(&mut reloader, &reopen, &mut maps);
+        filter.process(&maps, &line);
+        line.clear();
+        match output::flush() {
+            // the rest of output is no longer wanted, such as when piped to `head`
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => return Ok(()),
+            flushed => flushed?,
+        }
+    }
+    filter.finish(&maps)
+}
+
+/// Next item of the channel, flushing the output first if waiting for it
+fn recv_flushing<T>(receiver: &Receiver<T>) -> Option<T> {
+    match receiver.try_recv() {
+        Ok(item) => Some(item),
+        Err(TryRecvError::Disconnected) => None,
+        Err(TryRecvError::Empty) => {
+            // errors are returned on the flush when finished
+            _ = output::flush();
+            receiver.recv().ok()
+        }
+    }
+}
+
+/// Resolve lines of the inputs read concurrently, each by its own filter, printed in the order of
+/// the inputs with the lines of later inputs held back until earlier ones end, or as they come if
+/// `unordered`
+fn multiplex(
+    client: &Client,
+    inputs: &[String],
+    filters: Vec<Filter>,
+    maps: Maps,
+    unordered: bool,
+) -> Result<()> {
+    thread::scope(|scope| {
+        // dropped on errors before the scope waits for readers blocked on sending
+        let (sender, receiver) = mpsc::sync_channel(PIPELINE);
+        for (i, input) in inputs.iter().enumerate() {
+            let sender = sender.clone();
+            scope.spawn(move || {
+                let read = || -> Result<()> {
+                    let mut input = Input::open(client, Some(input.clone()))?;
+                    loop {
+                        let mut buf = String::new();
+                        let line = (input.read_line(&mut buf)? != 0).then_some(buf);
+                        let end = line.is_none();
+                        if sender.send((i, Ok(line))).is_err() || end {
+                            return Ok(());
+                        }
+                    }
+                };
+                if let Err(e) = read() {
+                    _ = sender.send((i, Err(eyre!("failed to read {input}: {e}"))));
+                }
             });
-            if args.stdout {
-                print!("{result}")
-            } else {
-                eprint!("{result}")
+        }
+        drop(sender);
+        // filters are finished once their input ends, so that what they hold back is printed in order
+        let mut filters = filters.into_iter().map(Some).collect::<Vec<_>>();
+        let mut held = vec![Vec::new(); inputs.len()];
+        let mut ended = vec![false; inputs.len()];
+        let mut current = 0;
+        while let Some((i, line)) = recv_flushing(&receiver) {
+            match line? {
+                Some(line) if unordered || i == current => {
+                    filters[i].as_mut().unwrap().process(&maps, &line)
+                }
+                Some(line) => held[i].push(line),
+                None => ended[i] = true,
+            }
+            while !unordered && current < inputs.len() && ended[current] {
+                filters[current].take().unwrap().finish(&maps)?;
+                current += 1;
+                if let Some(filter) = filters.get_mut(current).and_then(Option::as_mut) {
+                    for line in mem::take(&mut held[current]) {
+                        filter.process(&maps, &line);
+                    }
+                }
             }
         }
+        filters
+            .into_iter()
+            .flatten()
+            .try_for_each(|filter| filter.finish(&maps))
+    })
+}
+
+fn breakpoint(map: &str, path: &str, line: u32, cdp: Option<&str>) -> Result<()> {
+    let client = client();
+    let map = client.source_map(map)?;
+    let (found, addrs) = mbtmap::addresses(&map, path, line)?;
+    if found != line {
+        eprintln!("warning: no code at {path}:{line}, using line {found}");
+    }
+    for addr in &addrs {
+        println!("{addr:#x}");
+    }
+    let Some(target) = cdp else {
+        return Ok(());
+    };
+    let mut debugger = Debugger::attach(&client, target)?;
+    for &addr in &addrs {
+        for (url, breakpoint) in debugger.set_breakpoint(addr)? {
+            eprintln!("breakpoint {breakpoint} set at {url}:{addr:#x}");
+        }
+    }
+    while let Some((url, offset)) = debugger.pause()? {
+        let location = map
+            .lookup_token(0, offset)
+            .map(|token| format!(" {}", mbtmap::locate(&token, &None)))
+            .unwrap_or_default();
+        eprintln!("paused at {url}:{offset:#x}{location}");
     }
     Ok(())
 }
+
+/// Resolve lines read from a log stream as they come
+fn stream(
+    map: &str,
+    args: &FilterArgs,
+    read_line: impl FnMut(&mut String) -> Result<usize> + Send + 'static,
+) -> Result<()> {
+    let filter = Filter::new(args, None)?;
+    let client = client();
+    let open = || Maps::open(&client, map, &args.map_for);
+    let mut watched = vec![map];
+    watched.extend(args.map_for.iter().map(|(_, path)| path.as_str()));
+    pipeline(filter, open()?, Reloader::new(&watched), open, read_line)
+}
@@ -0,0 +1,246 @@
+//! Node.js addon over raw N-API, loaded from the cdylib renamed to `mbtmap.node`:
+//!
+//! ```js
+//! const mbtmap = require("./mbtmap.node");
+//! const resolver = mbtmap.open("app.wasm.map", "wasm-bindgen");
+//! console.log(mbtmap.rewrite(resolver, error.stack));
+//! console.log(mbtmap.resolve(resolver, 0x2648d));
+//! ```
+
+use crate::preset::Preset;
+use crate::resolver::Resolver;
+use std::ffi::{c_char, c_void, CString};
+use std::ptr;
+
+#[repr(C)]
+pub struct Env {
+    _private: [u8; 0],
+}
+#[repr(C)]
+pub struct Value {
+    _private: [u8; 0],
+}
+#[repr(C)]
+pub struct CallbackInfo {
+    _private: [u8; 0],
+}
+
+type NapiEnv = *mut Env;
+type NapiValue = *mut Value;
+type NapiCallbackInfo = *mut CallbackInfo;
+type NapiStatus = i32;
+type NapiCallback = unsafe extern "C" fn(NapiEnv, NapiCallbackInfo) -> NapiValue;
+type NapiFinalize = unsafe extern "C" fn(NapiEnv, *mut c_void, *mut c_void);
+
+const NAPI_OK: NapiStatus = 0;
+
+extern "C" {
+    fn napi_create_function(
+        env: NapiEnv,
+        name: *const c_char,
+        length: usize,
+        cb: NapiCallback,
+        data: *mut c_void,
+        result: *mut NapiValue,
+    ) -> NapiStatus;
+    fn napi_set_named_property(
+        env: NapiEnv,
+        object: NapiValue,
+        name: *const c_char,
+        value: NapiValue,
+    ) -> NapiStatus;
+    fn napi_get_cb_info(
+        env: NapiEnv,
+        info: NapiCallbackInfo,
+        argc: *mut usize,
+        argv: *mut NapiValue,
+        this: *mut NapiValue,
+        data: *mut *mut c_void,
+    ) -> NapiStatus;
+    fn napi_get_value_string_utf8(
+        env: NapiEnv,
+        value: NapiValue,
+        buf: *mut c_char,
+        size: usize,
+        result: *mut usize,
+    ) -> NapiStatus;
+    fn napi_create_string_utf8(
+        env: NapiEnv,
+        s: *const c_char,
+        length: usize,
+        result: *mut NapiValue,
+    ) -> NapiStatus;
+    fn napi_get_value_uint32(env: NapiEnv, value: NapiValue, result: *mut u32) -> NapiStatus;
+    fn napi_create_uint32(env: NapiEnv, value: u32, result: *mut NapiValue) -> NapiStatus;
+    fn napi_create_object(env: NapiEnv, result: *mut NapiValue) -> NapiStatus;
+    fn napi_get_null(env: NapiEnv, result: *mut NapiValue) -> NapiStatus;
+    fn napi_create_external(
+        env: NapiEnv,
+        data: *mut c_void,
+        finalize: NapiFinalize,
+        hint: *mut c_void,
+        result: *mut NapiValue,
+    ) -> NapiStatus;
+    fn napi_get_value_external(
+        env: NapiEnv,
+        value: NapiValue,
+        result: *mut *mut c_void,
+    ) -> NapiStatus;
+    fn napi_throw_error(env: NapiEnv, code: *const c_char, msg: *const c_char) -> NapiStatus;
+}
+
+unsafe fn throw(env: NapiEnv, message: &str) -> NapiValue {
+    let message = CString::new(message.replace('\0', "")).unwrap();
+    napi_throw_error(env, ptr::null(), message.as_ptr());
+    ptr::null_mut()
+}
+
+/// Arguments of the call, at least `required` of them given and the rest being `undefined`
+unsafe fn args<const N: usize>(
+    env: NapiEnv,
+    info: NapiCallbackInfo,
+    required: usize,
+) -> Option<[NapiValue; N]> {
+    let mut argv = [ptr::null_mut(); N];
+    let mut argc = N;
+    let status = napi_get_cb_info(
+        env,
+        info,
+        &mut argc,
+        argv.as_mut_ptr(),
+        ptr::null_mut(),
+        ptr::null_mut(),
+    );
+    (status == NAPI_OK && argc >= required).then_some(argv)
+}
+
+unsafe fn string(env: NapiEnv, value: NapiValue) -> Option<String> {
+    let mut len = 0;
+    if napi_get_value_string_utf8(env, value, ptr::null_mut(), 0, &mut len) != NAPI_OK {
+        return None;
+    }
+    let mut buf = vec![0u8; len + 1];
+    if napi_get_value_string_utf8(env, value, buf.as_mut_ptr().cast(), buf.len(), &mut len)
+        != NAPI_OK
+    {
+        return None;
+    }
+    buf.truncate(len);
+    String::from_utf8(buf).ok()
+}
+
+unsafe fn create_string(env: NapiEnv, s: &str) -> NapiValue {
+    let mut result = ptr::null_mut();
+    napi_create_string_utf8(env, s.as_ptr().cast(), s.len(), &mut result);
+    result
+}
+
+unsafe fn resolver<'a>(env: NapiEnv, value: NapiValue) -> Option<&'a Resolver> {
+    let mut data = ptr::null_mut();
+    if napi_get_value_external(env, value, &mut data) != NAPI_OK || data.is_null() {
+        return None;
+    }
+    Some(&*(data as *const Resolver))
+}
+
+unsafe extern "C" fn finalize(_env: NapiEnv, data: *mut c_void, _hint: *mut c_void) {
+    drop(Box::from_raw(data as *mut Resolver));
+}
+
+/// `open(path, preset = "moonbit")`
+unsafe extern "C" fn open(env: NapiEnv, info: NapiCallbackInfo) -> NapiValue {
+    let Some([path, preset]) = args::<2>(env, info, 1) else {
+        return throw(env, "expected path to source map");
+    };
+    let Some(path) = string(env, path) else {
+        return throw(env, "expected path to source map");
+    };
+    let preset = match string(env, preset) {
+        Some(preset) => match preset.parse::<Preset>() {
+            Ok(preset) => preset,
+            Err(e) => return throw(env, &e),
+        },
+        None => Preset::default(),
+    };
+    let resolver = match Resolver::open(&path, preset) {
+        Ok(resolver) => resolver,
+        Err(e) => return throw(env, &e.to_string()),
+    };
+    let mut result = ptr::null_mut();
+    napi_create_external(
+        env,
+        Box::into_raw(Box::new(resolver)).cast(),
+        finalize,
+        ptr::null_mut(),
+        &mut result,
+    );
+    result
+}
+
+/// `rewrite(resolver, text)`
+unsafe extern "C" fn rewrite(env: NapiEnv, info: NapiCallbackInfo) -> NapiValue {
+    let Some([resolver, text]) = args::<2>(env, info, 2) else {
+        return throw(env, "expected resolver and text");
+    };
+    let (Some(resolver), Some(text)) = (self::resolver(env, resolver), string(env, text)) else {
+        return throw(env, "expected resolver and text");
+    };
+    create_string(env, &resolver.rewrite(&text))
+}
+
+/// `resolve(resolver, addr)`, returning `{ source, line, column }` or `null`
+unsafe extern "C" fn resolve(env: NapiEnv, info: NapiCallbackInfo) -> NapiValue {
+    let Some([resolver, addr]) = args::<2>(env, info, 2) else {
+        return throw(env, "expected resolver and address");
+    };
+    let mut value = 0;
+    let Some(resolver) = self::resolver(env, resolver) else {
+        return throw(env, "expected resolver and address");
+    };
+    if napi_get_value_uint32(env, addr, &mut value) != NAPI_OK {
+        return throw(env, "expected resolver and address");
+    }
+    let mut result = ptr::null_mut();
+    let Some(location) = resolver.resolve(value) else {
+        napi_get_null(env, &mut result);
+        return result;
+    };
+    napi_create_object(env, &mut result);
+    let (mut line, mut column) = (ptr::null_mut(), ptr::null_mut());
+    napi_create_uint32(env, location.line, &mut line);
+    napi_create_uint32(env, location.column, &mut column);
+    napi_set_named_property(
+        env,
+        result,
+        c"source".as_ptr(),
+        create_string(env, &location.source),
+    );
+    napi_set_named_property(env, result, c"line".as_ptr(), line);
+    napi_set_named_property(env, result, c"column".as_ptr(), column);
+    result
+}
+
+/// Entry point looked up by Node.js when loading the addon
+///
+/// # Safety
+///
+/// Only to be called by Node.js with a valid environment and exports object.
+#[no_mangle]
+pub unsafe extern "C" fn napi_register_module_v1(env: NapiEnv, exports: NapiValue) -> NapiValue {
+    let functions: [(&str, NapiCallback); 3] =
+        [("open", open), ("rewrite", rewrite), ("resolve", resolve)];
+    for (name, callback) in functions {
+        let mut function = ptr::null_mut();
+        napi_create_function(
+            env,
+            name.as_ptr().cast(),
+            name.len(),
+            callback,
+            ptr::null_mut(),
+            &mut function,
+        );
+        let name = CString::new(name).unwrap();
+        napi_set_named_property(env, exports, name.as_ptr(), function);
+    }
+    exports
+}
@@ -0,0 +1,46 @@
+use regex::Regex;
+
+/// Renumbering of frames in each run of consecutive frame lines, with indentation normalized
+pub struct Numbering {
+    /// Frame line with leading indentation and numbering to strip, the rest captured as `frame`
+    line: Regex,
+    pending: Vec<String>,
+}
+
+impl Numbering {
+    pub fn new() -> Self {
+        Self {
+            line: Regex::new(r"^[ \t]*(?:#\d+[ \t]+|\d+:[ \t]+)?(?P<frame>.*?)\r?\n?$").unwrap(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn flush(&mut self, out: &mut String) {
+        let width = self.pending.len().saturating_sub(1).to_string().len();
+        for (i, frame) in self.pending.drain(..).enumerate() {
+            out.push_str(&format!("    #{i:<width$} {frame}\n"));
+        }
+    }
+
+    /// Renumber the frames in `text`, those at its end held back until the run of frames ends
+    pub fn push(&mut self, text: &str, is_frame: impl Fn(&str) -> bool) -> String {
+        let mut out = String::new();
+        for line in text.split_inclusive('\n') {
+            let stripped = line.trim_start();
+            if is_frame(line) || stripped.starts_with("at ") {
+                let caps = self.line.captures(line).unwrap();
+                self.pending.push(caps["frame"].to_owned());
+            } else {
+                self.flush(&mut out);
+                out.push_str(line);
+            }
+        }
+        out
+    }
+
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        self.flush(&mut out);
+        out
+    }
+}
@@ -0,0 +1,117 @@
+//! Objects in S3 and Google Cloud Storage fetched by `aws s3 cp` and `gsutil cat`, with the
+//! credential chains of those tools
+
+use crate::http::Failure;
+use eyre::eyre;
+use std::io::{self, Read};
+use std::process::{Child, Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Interval of checking whether the tool has exited
+const POLL: Duration = Duration::from_millis(20);
+
+pub fn is_url(s: &str) -> bool {
+    s.starts_with("s3://") || s.starts_with("gs://")
+}
+
+/// Output of the pipe, read on a thread of its own for the tool not to block on a full pipe
+fn drain(pipe: Option<impl Read + Send + 'static>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut out = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut out).ok();
+        }
+        out
+    })
+}
+
+/// Markers in the errors of the tools of failures worth retrying, as timeouts, throttling, server
+/// errors and broken connections are, unlike missing objects and denied access
+const TRANSIENT: [&str; 12] = [
+    "timed out",
+    "timeout",
+    "slowdown",
+    "throttl",
+    "toomanyrequests",
+    "rate exceeded",
+    "internalerror",
+    "serviceunavailable",
+    "service unavailable",
+    "could not connect",
+    "connection reset",
+    "connection was closed",
+];
+
+/// Whether the tool failed with `stderr` for a reason worth retrying, the status of the response
+/// being 408, 429 or 5xx when given, everything else such as `NoSuchKey`, `AccessDenied` or 404
+/// being fatal, digits of the URL itself aside
+fn transient(url: &str, stderr: &str) -> bool {
+    let stderr = stderr.replace(url, "").to_ascii_lowercase();
+    let status = stderr
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|word| word.len() == 3)
+        .filter_map(|word| word.parse::<u16>().ok())
+        .find(|status| (400..600).contains(status));
+    match status {
+        Some(status) => matches!(status, 408 | 429 | 500..=599),
+        None => TRANSIENT.iter().any(|marker| stderr.contains(marker)),
+    }
+}
+
+/// Wait for the tool to exit, killing it once `timeout` has passed, such as when it is stuck on a
+/// credential prompt or a stalled network
+fn wait(child: &mut Child, timeout: Option<Duration>) -> io::Result<Option<bool>> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status.success()));
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            child.kill().ok();
+            child.wait()?;
+            return Ok(None);
+        }
+        thread::sleep(POLL);
+    }
+}
+
+/// Content of the object at the `s3://` or `gs://` URL, the tool killed after `timeout`
+pub(crate) fn read(url: &str, timeout: Option<Duration>) -> Result<Vec<u8>, Failure> {
+    let mut command = if url.starts_with("s3://") {
+        let mut command = Command::new("aws");
+        command.args(["s3", "cp", "--quiet", url, "-"]);
+        command
+    } else {
+        let mut command = Command::new("gsutil");
+        command.args(["-q", "cat", url]);
+        command
+    };
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| Failure::Fatal(eyre!("failed to run {program} to fetch {url}: {e}")))?;
+    let stdout = drain(child.stdout.take());
+    let stderr = drain(child.stderr.take());
+    let exited = wait(&mut child, timeout).map_err(|e| Failure::Transient(eyre!("{url}: {e}")))?;
+    match exited {
+        Some(true) => Ok(stdout.join().unwrap()),
+        Some(false) => {
+            let stderr = String::from_utf8_lossy(&stderr.join().unwrap()).into_owned();
+            let e = eyre!("{url}: {program} failed: {}", stderr.trim());
+            if transient(url, &stderr) {
+                Err(Failure::Transient(e))
+            } else {
+                Err(Failure::Fatal(e))
+            }
+        }
+        // pipes left to the threads, as processes spawned by the tool may still hold them open
+        None => Err(Failure::Transient(eyre!(
+            "{url}: {program} timed out after {:?}",
+            timeout.unwrap_or_default()
+        ))),
+    }
+}
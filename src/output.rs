@@ -0,0 +1,53 @@
+//! Output to stdout and stderr, buffered in between explicit flushes
+
+use std::io::{self, BufWriter, Stderr, Stdout, Write};
+use std::sync::{Mutex, PoisonError};
+
+/// Bytes buffered for each stream before written out regardless of flushes
+const BUFFER: usize = 64 << 10;
+
+struct Output {
+    stdout: BufWriter<Stdout>,
+    stderr: BufWriter<Stderr>,
+    /// Whether stdout or stderr was written last
+    last: Option<bool>,
+}
+
+static OUTPUT: Mutex<Option<Output>> = Mutex::new(None);
+
+fn with<T>(f: impl FnOnce(&mut Output) -> T) -> T {
+    let mut output = OUTPUT.lock().unwrap_or_else(PoisonError::into_inner);
+    f(output.get_or_insert_with(|| Output {
+        stdout: BufWriter::with_capacity(BUFFER, io::stdout()),
+        stderr: BufWriter::with_capacity(BUFFER, io::stderr()),
+        last: None,
+    }))
+}
+
+/// Write `s` to stdout or stderr, flushing the other one first if written last, so that both stay
+/// in order when they go to the same terminal
+pub fn write(stdout: bool, s: &str) -> io::Result<()> {
+    with(|output| {
+        if output.last == Some(!stdout) {
+            if stdout {
+                output.stderr.flush()?;
+            } else {
+                output.stdout.flush()?;
+            }
+        }
+        output.last = Some(stdout);
+        if stdout {
+            output.stdout.write_all(s.as_bytes())
+        } else {
+            output.stderr.write_all(s.as_bytes())
+        }
+    })
+}
+
+/// Write out what is buffered of both
+pub fn flush() -> io::Result<()> {
+    with(|output| {
+        output.stdout.flush()?;
+        output.stderr.flush()
+    })
+}
@@ -0,0 +1,166 @@
+use regex::{Captures, Match};
+use std::str::FromStr;
+
+/// Trace shapes of different toolchains and runtimes
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Preset {
+    /// Traceback printed by `moon run -g`
+    #[default]
+    Moonbit,
+    /// Rust panic surfaced via `console_error_panic_hook`, with `__wbg_` JS glue frames
+    WasmBindgen,
+    /// Deno stack trace, with `file:///` module URLs and `ext:` internal frames passed through
+    Deno,
+    /// Cloudflare Workers traces, with JSON log envelopes of `wrangler tail --format json` unwrapped
+    Workerd,
+    /// Emscripten `stackTrace()` output, names can be resolved with `--symbols`
+    Emscripten,
+    /// Wasmer backtrace, such as `at main (app.wasm[1]:0x52)` or `main@0x52`
+    Wasmer,
+    /// wazero stack trace, with Go-style frames followed by module offsets
+    Wazero,
+    /// AssemblyScript `--sourceMap` output, with frames of the standard library under `~lib/` tagged
+    #[cfg_attr(feature = "cli", value(name = "assemblyscript"))]
+    AssemblyScript,
+    /// Go `GOOS=js GOARCH=wasm` traces under `wasm_exec.js`, with its JS glue frames
+    Go,
+    /// TinyGo panics, traced by V8 or as wasmtime backtraces such as `0: 0x2b8 - main.main`
+    #[cfg_attr(feature = "cli", value(name = "tinygo"))]
+    TinyGo,
+    /// V8 `--prof` tick log, with code creation of WASM functions resolved through `--wasm`
+    V8Log,
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let preset = match s.to_ascii_lowercase().as_str() {
+            "moonbit" => Preset::Moonbit,
+            "wasm-bindgen" => Preset::WasmBindgen,
+            "deno" => Preset::Deno,
+            "workerd" => Preset::Workerd,
+            "emscripten" => Preset::Emscripten,
+            "wasmer" => Preset::Wasmer,
+            "wazero" => Preset::Wazero,
+            "assemblyscript" => Preset::AssemblyScript,
+            "go" => Preset::Go,
+            "tinygo" => Preset::TinyGo,
+            "v8-log" => Preset::V8Log,
+            _ => return Err(format!("invalid preset: {s}")),
+        };
+        Ok(preset)
+    }
+}
+
+/// Capture group `name` of the alternative matched, as names in different alternatives of a
+/// pattern are suffixed with `_1`, `_2` and so on
+pub fn group<'h>(caps: &Captures<'h>, name: &str) -> Option<Match<'h>> {
+    caps.name(name)
+        .or_else(|| (1..=3).find_map(|i| caps.name(&format!("{name}_{i}"))))
+}
+
+impl Preset {
+    /// Pattern matching a frame, with capture group `addr` being the address to resolve, and
+    /// optionally `func` being the function index or `symbol` being the function name to look up
+    /// in symbol map, see [`group`]
+    pub fn frame(self) -> &'static str {
+        match self {
+            // "wasm://wasm/000c5502:wasm-function[1060]:0x2648d"
+            Preset::Moonbit => r"wasm\://.*\:.*\:(?P<addr>(?:0x)?[[:xdigit:]]+)",
+            // "http://localhost:8080/pkg/app_bg.wasm:wasm-function[123]:0x4567"
+            Preset::WasmBindgen => {
+                r"(?:wasm|https?|file)://[^\s()]*:wasm-function\[(?P<func>\d+)\]:(?P<addr>(?:0x)?[[:xdigit:]]+)"
+            }
+            // "file:///home/user/app/main.wasm:wasm-function[3]:0x1a2"
+            Preset::Deno => {
+                r"(?:wasm|file)://[^\s()]*:(?:wasm-function\[(?P<func>\d+)\]|\d+):(?P<addr>0x[[:xdigit:]]+)"
+            }
+            // "wasm://wasm/0012abcd:wasm-function[12]:0x1234"
+            Preset::Workerd => {
+                r"wasm://[^\s()]*:wasm-function\[(?P<func>\d+)\]:(?P<addr>(?:0x)?[[:xdigit:]]+)"
+            }
+            // "at foo (http://localhost/app.wasm:wasm-function[12]:0x1234)", "at app.wasm.foo"
+            Preset::Emscripten => concat!(
+                r"(?:[^\s()]*\.wasm|wasm://[^\s()]*|<anonymous>)",
+                r"(?::wasm-function\[(?P<func>\d+)\])?:(?P<addr>0x[[:xdigit:]]+)",
+                r"|[^\s()]+\.wasm\.(?P<symbol>[\w$]+)",
+            ),
+            // "at main (app.wasm[1]:0x52)", "main@0x52"
+            Preset::Wasmer => concat!(
+                r"[^\s()]+\[(?P<func>\d+)\]:(?P<addr>0x[[:xdigit:]]+)",
+                r"|(?P<symbol>[^\s()@]+)@(?P<addr_1>0x[[:xdigit:]]+)",
+            ),
+            // "\t.main.main()\n\t\t0x2a3: main.go:10:2", "main.main()+0x2a3"
+            Preset::Wazero => concat!(
+                r"(?m:^[ \t]+(?P<addr>0x[[:xdigit:]]+):[^\r\n]*)",
+                r"|\)\+(?P<addr_1>0x[[:xdigit:]]+)",
+            ),
+            // "at assembly/index/run (wasm://wasm/0012abcd:wasm-function[8]:0x2c4)"
+            Preset::AssemblyScript => {
+                r"(?:wasm|https?|file)://[^\s()]*:wasm-function\[(?P<func>\d+)\]:(?P<addr>(?:0x)?[[:xdigit:]]+)"
+            }
+            // "at main.main (wasm://wasm/01c52e7e:wasm-function[1142]:0x1a2b3)"
+            Preset::Go => {
+                r"(?:wasm|https?|file)://[^\s()]*:wasm-function\[(?P<func>\d+)\]:(?P<addr>(?:0x)?[[:xdigit:]]+)"
+            }
+            // "at main.main (wasm://wasm/0012abcd:wasm-function[12]:0x2b8)", "  1:  0x2b8 - main.main"
+            Preset::TinyGo => concat!(
+                r"(?:wasm|https?|file)://[^\s()]*:wasm-function\[(?P<func>\d+)\]:(?P<addr>(?:0x)?[[:xdigit:]]+)",
+                r"|(?m:^[ \t]*\d+:[ \t]+(?P<addr_1>0x[[:xdigit:]]+) - [^\r\n]*)",
+            ),
+            // "code-creation,Function,11,12345,0x3e0c8a0c1000,323,wasm-function[12]"
+            Preset::V8Log => r"(?m:^code-creation,[^\n]*?wasm-function\[(?P<func>\d+)\])",
+        }
+    }
+
+    /// Pattern matching a frame of older V8, whose position in capture group `rel` is the decimal
+    /// offset within the function `func_1` rather than an address in the module
+    pub fn legacy(self) -> Option<&'static str> {
+        match self {
+            // "at foo (wasm-function[1060]:123)"
+            Preset::Moonbit
+            | Preset::WasmBindgen
+            | Preset::Deno
+            | Preset::Workerd
+            | Preset::Emscripten
+            | Preset::AssemblyScript
+            | Preset::Go
+            | Preset::TinyGo => {
+                Some(r"(?:(?m:^)|[\s(])wasm-function\[(?P<func_1>\d+)\]:(?P<rel>\d+)\b")
+            }
+            _ => None,
+        }
+    }
+
+    /// Annotation escaped to fit in the surrounding text
+    pub fn escape(self, annotation: String) -> String {
+        match self {
+            // Fields in V8 log are separated by comma
+            Preset::V8Log => annotation.replace(',', "\\x2C"),
+            _ => annotation,
+        }
+    }
+
+    /// Pattern matching a line of JS glue frame which can be hidden
+    pub fn glue(self) -> Option<&'static str> {
+        match self {
+            // "at imports.wbg.__wbg_new_abc (http://localhost:8080/pkg/app.js:321:21)"
+            Preset::WasmBindgen => Some(r"(?:__wbg_|__wbindgen_)[^\n]*\.m?js:\d+:\d+"),
+            // "at global.Go._resume (http://localhost:8080/wasm_exec.js:555:9)"
+            Preset::Go => Some(r"wasm_exec\.js:\d+:\d+"),
+            // "at ~lib/rt/itcms/__new (wasm://wasm/0012abcd:wasm-function[2]:0x5f1)"
+            Preset::AssemblyScript => Some(r"at ~lib/[^\n]*wasm-function\["),
+            _ => None,
+        }
+    }
+
+    /// Tag of frames resolved to `source`, such as those of the standard library
+    pub fn tag(self, source: &str) -> Option<&'static str> {
+        match self {
+            Preset::AssemblyScript if source.starts_with("~lib/") => Some("[stdlib]"),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! Frames wrapped across lines by logging systems joined back before matching
+//!
+//! A line mentioning `wasm` or leaving a parenthesis open without a whole frame is held back, and
+//! joined by the next line with its indentation stripped if that one starts without a timestamp
+//! and the two make a frame across the join.
+
+use crate::resolver::Annotator;
+use crate::window::Patterns;
+
+/// Whether the line may end in the middle of a frame, mentioning `wasm` or opening a parenthesis
+/// left unclosed
+fn is_partial(line: &str) -> bool {
+    line.contains("wasm") || line.rfind('(') > line.rfind(')')
+}
+
+pub struct Reassembler {
+    timestamps: Patterns,
+    /// Line held back, with its line ending
+    held: Option<String>,
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Self {
+            timestamps: Patterns::new(),
+            held: None,
+        }
+    }
+
+    /// Lines of `input` ready to resolve, joined where wrapped
+    pub fn push(&mut self, annotator: &Annotator, input: &str) -> String {
+        let mut out = String::new();
+        for line in input.split_inclusive('\n') {
+            let mut line = line.to_owned();
+            if let Some(held) = self.held.take() {
+                let start = held.trim_end_matches(['\n', '\r']);
+                let joined = format!("{start}{}", line.trim_start());
+                let across = annotator
+                    .frames(&joined)
+                    .any(|(begin, end)| begin < start.len() && end > start.len());
+                if across && !self.timestamps.is_match(&line) {
+                    line = joined;
+                } else {
+                    out.push_str(&held);
+                }
+            }
+            if line.ends_with('\n') && is_partial(&line) && annotator.frames(&line).next().is_none()
+            {
+                self.held = Some(line);
+            } else {
+                out.push_str(&line);
+            }
+        }
+        out
+    }
+
+    /// Line held back at the end of the input
+    pub fn finish(&mut self) -> Option<String> {
+        self.held.take()
+    }
+}
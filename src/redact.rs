@@ -0,0 +1,76 @@
+//! Redaction of sensitive content such as emails and tokens from the output, applied line by line
+//! as frames are resolved, so that annotated logs are safe to attach to public issues
+//!
+//! Each distinct text matched is replaced by `[redacted-N]`, numbered in order of first
+//! occurrence, so that the same secret reads the same across lines without anything of it left to
+//! guess from.
+
+use eyre::{eyre, Result};
+use regex::{Captures, Regex};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+pub struct Redactor {
+    rules: Vec<Regex>,
+    /// Number of each distinct text redacted so far
+    seen: HashMap<String, usize>,
+}
+
+impl Redactor {
+    pub fn new(rules: &[String]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| Regex::new(rule).map_err(|e| eyre!("invalid --redact pattern: {e}")))
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            rules,
+            seen: HashMap::new(),
+        })
+    }
+
+    fn placeholder(&mut self, secret: &str) -> String {
+        let next = self.seen.len() + 1;
+        let n = *self.seen.entry(secret.to_owned()).or_insert(next);
+        format!("[redacted-{n}]")
+    }
+
+    /// Text with each match of the rules replaced, only the first group if the rule has one
+    pub fn redact<'a>(&mut self, text: &'a str) -> Cow<'a, str> {
+        if self.rules.is_empty() {
+            return Cow::Borrowed(text);
+        }
+        let mut out = String::new();
+        for line in text.split_inclusive('\n') {
+            out.push_str(&self.redact_line(line));
+        }
+        Cow::Owned(out)
+    }
+
+    fn redact_line<'a>(&mut self, line: &'a str) -> Cow<'a, str> {
+        let mut line = Cow::Borrowed(line);
+        for i in 0..self.rules.len() {
+            let rule = self.rules[i].clone();
+            let grouped = rule.captures_len() > 1;
+            let replaced = rule.replace_all(&line, |caps: &Captures| {
+                let whole = caps.get(0).unwrap();
+                let group = match caps.get(1) {
+                    Some(group) => group,
+                    None if grouped => return whole.as_str().to_owned(),
+                    None => return self.placeholder(whole.as_str()),
+                };
+                let (start, end) = (group.start() - whole.start(), group.end() - whole.start());
+                let text = whole.as_str();
+                format!(
+                    "{}{}{}",
+                    &text[..start],
+                    self.placeholder(group.as_str()),
+                    &text[end..]
+                )
+            });
+            if let Cow::Owned(replaced) = replaced {
+                line = Cow::Owned(replaced);
+            }
+        }
+        line
+    }
+}
@@ -0,0 +1,73 @@
+//! Maps kept in step with their files, so that pipes running for days across rebuilds and rotated
+//! artifacts do not keep resolving by a map deleted or replaced since loaded
+
+use std::fs;
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+use std::time::{Duration, Instant, SystemTime};
+
+/// Interval of checking whether the files of the maps changed
+pub const INTERVAL: Duration = Duration::from_secs(5);
+
+/// Identity and version of a file, telling a file written in place from one deleted and created
+/// again, even with the same size and modification time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stamp {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Device and inode
+    #[cfg(unix)]
+    inode: (u64, u64),
+}
+
+impl Stamp {
+    /// Stamp of the file, or `None` if it does not exist
+    pub fn of(path: &str) -> Option<Self> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Self {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            #[cfg(unix)]
+            inode: (metadata.dev(), metadata.ino()),
+        })
+    }
+}
+
+/// Files of the maps loaded, checked for changes at most every `INTERVAL`
+#[derive(Debug)]
+pub struct Reloader {
+    files: Vec<(String, Option<Stamp>)>,
+    checked: Instant,
+}
+
+impl Reloader {
+    /// Watch the files as they are now, URLs being skipped
+    pub fn new(paths: &[&str]) -> Self {
+        Self {
+            files: paths
+                .iter()
+                .filter(|path| !crate::http::is_url(path) && !crate::object::is_url(path))
+                .map(|path| (path.to_string(), Stamp::of(path)))
+                .collect(),
+            checked: Instant::now(),
+        }
+    }
+
+    /// Files changed since watched or last reported, except those missing, as they may be
+    /// created again shortly
+    pub fn changed(&mut self) -> Vec<String> {
+        if self.files.is_empty() || self.checked.elapsed() < INTERVAL {
+            return Vec::new();
+        }
+        self.checked = Instant::now();
+        let mut changed = Vec::new();
+        for (path, stamp) in &mut self.files {
+            let now = Stamp::of(path);
+            if now.is_some() && now != *stamp {
+                *stamp = now;
+                changed.push(path.clone());
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,188 @@
+use crate::source::Snippet;
+use crate::Frame;
+use std::fmt::Write;
+
+fn snippet_lines(snippet: &Snippet) -> Vec<String> {
+    let width = (snippet.first_line as usize + snippet.lines.len())
+        .to_string()
+        .len();
+    snippet
+        .lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| {
+            let n = snippet.first_line + i as u32;
+            let marker = if n == snippet.line { '>' } else { ' ' };
+            format!("{marker}{n:>width$} | {line}")
+        })
+        .collect()
+}
+
+fn fence(content: &str) -> String {
+    let mut fence = "```".to_owned();
+    while content.contains(&fence) {
+        fence.push('`');
+    }
+    fence
+}
+
+/// Render annotated traceback and source snippet of each frame as a Markdown report
+pub fn markdown(annotated: &str, frames: &[Frame]) -> String {
+    let mut out = String::new();
+    let traceback_fence = fence(annotated);
+    writeln!(
+        out,
+        "### Traceback\n\n{traceback_fence}\n{}\n{traceback_fence}\n",
+        annotated.trim_end()
+    )
+    .unwrap();
+    if !frames.is_empty() {
+        writeln!(out, "### Frames\n").unwrap();
+    }
+    for frame in frames {
+        writeln!(
+            out,
+            "<details>\n<summary><code>{}</code> {}</summary>\n",
+            escape(frame.text.trim()),
+            location(frame, "<b><code>{}</code></b>"),
+        )
+        .unwrap();
+        match &frame.snippet {
+            Some(snippet) => {
+                let lines = snippet_lines(snippet).join("\n");
+                let snippet_fence = fence(&lines);
+                writeln!(out, "{snippet_fence}\n{lines}\n{snippet_fence}\n").unwrap();
+            }
+            None => writeln!(out, "_Source not available._\n").unwrap(),
+        }
+        writeln!(out, "</details>\n").unwrap();
+    }
+    out
+}
+
+/// Render a bug report to paste into an issue, with the environment, the top frame, the annotated
+/// traceback, and source snippets of the top three frames of the first traceback
+pub fn issue(environment: &[(&str, String)], annotated: &str, frames: &[Frame]) -> String {
+    let mut out = "### Environment\n\n".to_owned();
+    for (key, value) in environment {
+        writeln!(out, "- {key}: `{value}`").unwrap();
+    }
+    let top = frames
+        .iter()
+        .take_while(|frame| frame.trace == frames[0].trace)
+        .take(3)
+        .collect::<Vec<_>>();
+    if let Some(frame) = top.first() {
+        let position = match &frame.link {
+            Some(link) => format!("[`{}`]({link})", frame.position),
+            None => format!("`{}`", frame.position),
+        };
+        let summary = match &frame.name {
+            Some(name) => format!("Crashed in `{name}` at {position}"),
+            None => format!("Crashed at {position} in `{}`", frame.text.trim()),
+        };
+        writeln!(out, "\n### Summary\n\n{summary}").unwrap();
+        if let Some(panic) = &frame.panic {
+            let location = panic
+                .location
+                .as_ref()
+                .map_or_else(String::new, |location| format!(" at `{location}`"));
+            writeln!(out, "\nPanicked{location}: `{}`", panic.message).unwrap();
+        }
+    }
+    let traceback_fence = fence(annotated);
+    writeln!(
+        out,
+        "\n### Traceback\n\n{traceback_fence}\n{}\n{traceback_fence}",
+        annotated.trim_end()
+    )
+    .unwrap();
+    if !top.is_empty() {
+        writeln!(out, "\n### Top frames").unwrap();
+    }
+    for frame in top {
+        writeln!(out, "\n`{}` at `{}`\n", frame.text.trim(), frame.position).unwrap();
+        match &frame.snippet {
+            Some(snippet) => {
+                let lines = snippet_lines(snippet).join("\n");
+                let snippet_fence = fence(&lines);
+                writeln!(out, "{snippet_fence}\n{lines}\n{snippet_fence}").unwrap();
+            }
+            None => writeln!(out, "_Source not available._").unwrap(),
+        }
+    }
+    out
+}
+
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Resolved location of the frame wrapped in `tag`, linked to its permalink if any
+fn location(frame: &Frame, tag: &str) -> String {
+    let text = tag.replace("{}", &escape(&frame.position));
+    match &frame.link {
+        Some(link) => format!("<a href=\"{}\">{text}</a>", escape(link)),
+        None => text,
+    }
+}
+
+/// Render annotated traceback and source snippet of each frame as a standalone HTML page
+pub fn html(annotated: &str, frames: &[Frame]) -> String {
+    let mut out = String::new();
+    out.push_str(concat!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n",
+        "<title>Traceback</title>\n<style>\n",
+        "body { font-family: sans-serif; }\n",
+        "pre { background: #f6f8fa; padding: 8px; overflow-x: auto; }\n",
+        "summary { cursor: pointer; font-family: monospace; }\n",
+        ".current { background: #fff5b1; }\n",
+        "</style>\n</head>\n<body>\n",
+    ));
+    writeln!(
+        out,
+        "<h3>Traceback</h3>\n<pre>{}</pre>",
+        escape(annotated.trim_end())
+    )
+    .unwrap();
+    if !frames.is_empty() {
+        writeln!(out, "<h3>Frames</h3>").unwrap();
+    }
+    for frame in frames {
+        writeln!(
+            out,
+            "<details>\n<summary>{} {}</summary>",
+            escape(frame.text.trim()),
+            location(frame, "<b>{}</b>"),
+        )
+        .unwrap();
+        match &frame.snippet {
+            Some(snippet) => {
+                out.push_str("<pre>");
+                for (i, line) in snippet_lines(snippet).iter().enumerate() {
+                    if snippet.first_line + i as u32 == snippet.line {
+                        writeln!(out, "<span class=\"current\">{}</span>", escape(line)).unwrap();
+                    } else {
+                        writeln!(out, "{}", escape(line)).unwrap();
+                    }
+                }
+                out.push_str("</pre>\n");
+            }
+            None => out.push_str("<p><i>Source not available.</i></p>\n"),
+        }
+        out.push_str("</details>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
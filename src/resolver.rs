@@ -0,0 +1,654 @@
+use crate::codeowners::CodeOwners;
+use crate::git::{Age, Blame, Git};
+use crate::github::GitHub;
+use crate::ignore::{self, Ignore};
+use crate::preset::{self, Preset};
+use crate::symbols::Symbols;
+use crate::wasm::Module;
+use crate::{locate, read_source_map, Columns, Location, Radix};
+use eyre::Result;
+use regex::{Captures, Regex};
+use sourcemap::{SourceMap, Token};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Separator of frame and annotation with `--align`, replaced by the padding before printing
+pub(crate) const MARK: char = '\u{0}';
+
+/// Addresses looked up, telling how well the source map matches the traceback
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Coverage {
+    pub addresses: usize,
+    /// Addresses resolved to a location
+    pub resolved: usize,
+    /// Addresses before the first or after the last token of the source map
+    pub outside: usize,
+}
+
+impl Coverage {
+    /// Whether so many addresses fall outside that the map likely comes from another build
+    pub fn is_suspicious(&self) -> bool {
+        self.outside > 0 && self.outside * 4 >= self.addresses
+    }
+}
+
+/// How much to trust the location of a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Confidence {
+    /// A token starts at the address
+    Exact,
+    /// The address falls after the start of the nearest token, or is the start of the function
+    Nearest,
+    /// Only the name of the function is known from its index
+    NameOnly,
+    Unresolved,
+}
+
+impl Confidence {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Confidence::Exact => "exact",
+            Confidence::Nearest => "nearest",
+            Confidence::NameOnly => "name-only",
+            Confidence::Unresolved => "unresolved",
+        }
+    }
+
+    pub(crate) fn of(token: &Token, addr: u32) -> Self {
+        if (token.get_dst_line(), token.get_dst_col()) == (0, addr) {
+            Confidence::Exact
+        } else {
+            Confidence::Nearest
+        }
+    }
+}
+
+/// Location and function name of an address, either of which may be unknown
+#[derive(Debug, Clone, Default)]
+pub struct Lookup {
+    pub location: Option<Location>,
+    pub name: Option<String>,
+}
+
+/// Source of locations of addresses, such as a source map, the DWARF of a module or a symbol
+/// file, for plugging in symbol sources the annotator consults for addresses its map leaves
+/// unresolved
+pub trait FrameResolver {
+    /// Location and function name of the address, or `None` if neither is known
+    fn resolve_frame(&self, addr: u32) -> Option<Lookup>;
+}
+
+impl FrameResolver for SourceMap {
+    fn resolve_frame(&self, addr: u32) -> Option<Lookup> {
+        let token = self.lookup_token(0, addr)?;
+        Some(Lookup {
+            location: Some(locate(&token, &None)),
+            name: token.get_name().map(str::to_owned),
+        })
+    }
+}
+
+/// A frame resolved by the annotator
+pub struct Resolved<'a> {
+    /// The whole line containing the frame, without line ending
+    pub line: &'a str,
+    /// Byte offset of the frame in the input
+    pub offset: usize,
+    pub addr: u32,
+    /// URL of the module the frame comes from, such as `wasm://wasm/000c5502`
+    pub module: Option<&'a str>,
+    /// Function name from the symbol map or the name section of the module
+    pub name: Option<&'a str>,
+    /// Token of the source map, or `None` if resolved by the fallback resolver
+    pub token: Option<&'a Token<'a>>,
+    pub location: Location,
+    pub link: Option<String>,
+    pub confidence: Confidence,
+    /// Owners of the source by `CODEOWNERS`
+    pub owners: Vec<String>,
+}
+
+/// Line of the output of `Annotator::annotate` by the line of its input it comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Origin {
+    /// Next line of output, from the line of the input at the index, annotated or not
+    Line(usize),
+    /// Line of the input at the index left out, such as glue with `--hide-glue`
+    Removed(usize),
+    /// Next line of output added, such as the note of frames ignored
+    Added,
+}
+
+/// Matching and resolving of frames recognized by a preset
+pub struct Annotator {
+    re: Regex,
+    glue: Option<Regex>,
+    /// URL of the module before the function index or line of a frame
+    module_url: Regex,
+    /// ANSI escape sequence, such as the colors of a runtime printing traces to a terminal
+    ansi: Regex,
+    preset: Preset,
+    pub(crate) cwd: Option<PathBuf>,
+    pub(crate) github: Option<GitHub>,
+    /// Repository to tell lines changed since the build commit
+    pub(crate) git: Option<Git>,
+    /// Commits last changing the lines resolved, if blaming
+    pub(crate) blame: Option<Blame>,
+    pub(crate) codeowners: Option<CodeOwners>,
+    /// Frames collapsed, or hidden if `hide_ignored`
+    pub(crate) ignore: Option<Ignore>,
+    pub(crate) hide_ignored: bool,
+    pub(crate) symbols: Option<Symbols>,
+    pub(crate) module: Option<Module>,
+    /// Resolver of addresses the source map leaves unresolved, such as an external process
+    pub(crate) fallback: Option<Box<dyn FrameResolver + Send>>,
+    pub(crate) coverage: Cell<Coverage>,
+    /// Whether to annotate each frame with the URL of its module
+    pub(crate) with_module: bool,
+    /// Whether to suffix the annotation of each frame with its confidence
+    pub(crate) with_confidence: bool,
+    /// Whether to name frames by the names of the source map before the name section
+    pub(crate) original_names: bool,
+    pub(crate) columns: Columns,
+    /// Base of addresses captured without `0x` prefix
+    pub(crate) radix: Radix,
+    /// Whether to insert annotations after `MARK`, for `--align` to pad them
+    pub(crate) align: bool,
+    /// Frames resolved to each source missing on disk, if verifying sources
+    pub(crate) missing: Option<RefCell<BTreeMap<String, usize>>>,
+    /// Origins of the lines of output of each input annotated so far, if recording for
+    /// `--format diff`
+    pub(crate) origins: Option<RefCell<Vec<Origin>>>,
+}
+
+impl Annotator {
+    pub fn new(preset: Preset, hide_glue: bool) -> Result<Self> {
+        Ok(Self {
+            re: match preset.legacy() {
+                Some(legacy) => Regex::new(&format!("{}|{legacy}", preset.frame()))?,
+                None => Regex::new(preset.frame())?,
+            },
+            glue: match preset.glue() {
+                Some(glue) if hide_glue => Some(Regex::new(glue)?),
+                _ => None,
+            },
+            module_url: Regex::new(
+                r"(?P<module>(?:wasm|https?|file)://[^\s()]+?)(?::wasm-function\[\d+\]|:\d+)?:(?:0x)?[[:xdigit:]]+(?:[\s)]|$)",
+            )?,
+            ansi: Regex::new(r"\x1b(?:\[[0-?]*[ -/]*[@-~]|\][^\x07\x1b\n]*(?:\x07|\x1b\\))")?,
+            preset,
+            cwd: None,
+            github: None,
+            git: None,
+            blame: None,
+            codeowners: None,
+            ignore: None,
+            hide_ignored: false,
+            symbols: None,
+            module: None,
+            fallback: None,
+            coverage: Cell::default(),
+            with_module: false,
+            with_confidence: false,
+            original_names: false,
+            columns: Columns::default(),
+            radix: Radix::default(),
+            align: false,
+            missing: None,
+            origins: None,
+        })
+    }
+
+    /// Mark of the location if its line changed or moved since the build commit
+    pub(crate) fn age(&self, location: &Location) -> Option<String> {
+        let git = self.git.as_ref()?;
+        match git.age(&location.source, location.line, &self.cwd) {
+            Age::Unchanged => None,
+            Age::Moved(line) => Some(format!("[moved to line {line} since {}]", git.commit)),
+            Age::Changed => Some(format!("[changed since {}]", git.commit)),
+        }
+    }
+
+    /// Last commit changing the line of the location, if blaming
+    pub(crate) fn blame(&self, location: &Location) -> Option<String> {
+        let blame = self.blame.as_ref()?;
+        let commit = blame.blame(&location.source, location.line, &self.cwd)?;
+        Some(format!("[blame: {commit}]"))
+    }
+
+    /// Owners of the source of the location by `CODEOWNERS`
+    pub(crate) fn owners(&self, location: &Location) -> Vec<String> {
+        match &self.codeowners {
+            Some(codeowners) => codeowners.owners(&location.source, &self.cwd).to_vec(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Original name of the function enclosing `addr`, the name of the token starting the function
+    /// if the module is known, or otherwise the name of the token itself
+    fn original_name<'a>(
+        &self,
+        map: &'a SourceMap,
+        token: &Token<'a>,
+        addr: u32,
+        index: Option<u32>,
+    ) -> Option<&'a str> {
+        let Some(module) = &self.module else {
+            return token.get_name();
+        };
+        let start = module.function_offset(index.or_else(|| module.function_at(addr))?)?;
+        let token = map.lookup_token(0, start)?;
+        if (token.get_dst_line(), token.get_dst_col()) == (0, start) {
+            token.get_name()
+        } else {
+            None
+        }
+    }
+
+    /// Name of the function of a bare address resolved to `token`, with no symbol of the input to
+    /// look up, by the source map, the original function with `--original-names`, or else the name
+    /// section of the module
+    #[cfg(feature = "cli")]
+    pub(crate) fn address_name<'a>(
+        &'a self,
+        map: &'a SourceMap,
+        token: &Token<'a>,
+        addr: u32,
+    ) -> Option<&'a str> {
+        let name = if self.original_names {
+            self.original_name(map, token, addr, None)
+        } else {
+            token.get_name()
+        };
+        name.or_else(|| {
+            let module = self.module.as_ref()?;
+            module.function_name(module.function_at(addr)?)
+        })
+    }
+
+    /// Whether the line contains a frame recognized by the preset
+    pub fn is_frame(&self, line: &str) -> bool {
+        self.re.is_match(&self.strip_ansi(line).0)
+    }
+
+    /// `text` without ANSI escape sequences, and the start of each run of text kept in it and in
+    /// `text`, empty if there are none
+    fn strip_ansi<'a>(&self, text: &'a str) -> (Cow<'a, str>, Vec<(usize, usize)>) {
+        if !text.contains('\x1b') {
+            return (Cow::Borrowed(text), Vec::new());
+        }
+        let mut plain = String::new();
+        let mut runs = Vec::new();
+        let mut last = 0;
+        for m in self.ansi.find_iter(text) {
+            if m.start() > last {
+                runs.push((plain.len(), last));
+                plain.push_str(&text[last..m.start()]);
+            }
+            last = m.end();
+        }
+        if last < text.len() {
+            runs.push((plain.len(), last));
+            plain.push_str(&text[last..]);
+        }
+        (Cow::Owned(plain), runs)
+    }
+
+    /// Start and end of each frame recognized in `text`
+    #[cfg(feature = "cli")]
+    pub(crate) fn frames<'a>(&'a self, text: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.re.find_iter(text).map(|m| (m.start(), m.end()))
+    }
+
+    pub(crate) fn count(&self, map: &SourceMap, addr: u32) {
+        let mut coverage = self.coverage.get();
+        coverage.addresses += 1;
+        coverage.resolved += usize::from(map.lookup_token(0, addr).is_some());
+        let first = map.get_token(0).map(|token| token.get_dst_col());
+        let last = map
+            .get_token((map.get_token_count() as usize).saturating_sub(1))
+            .map(|token| token.get_dst_col());
+        if first.is_none_or(|first| addr < first) || last.is_none_or(|last| addr > last) {
+            coverage.outside += 1;
+        }
+        self.coverage.set(coverage);
+    }
+
+    fn separator(&self) -> char {
+        if self.align {
+            MARK
+        } else {
+            ' '
+        }
+    }
+
+    /// Count an address resolved by the fallback resolver, which tells nothing of how well the
+    /// map matches
+    pub(crate) fn count_elsewhere(&self) {
+        let mut coverage = self.coverage.get();
+        coverage.addresses += 1;
+        coverage.resolved += 1;
+        self.coverage.set(coverage);
+    }
+
+    /// Append source location to each frame in `input`, `on_frame` is called with each frame
+    /// resolved
+    pub fn annotate(
+        &self,
+        map: &SourceMap,
+        input: &str,
+        mut on_frame: impl FnMut(Resolved),
+    ) -> String {
+        let mut unglued = String::new();
+        // start of each line kept in `unglued`, and in the original input
+        let mut kept = Vec::new();
+        // index of each line left out
+        let mut glued = Vec::new();
+        let lines_in = input.split_inclusive('\n').count();
+        let input = match &self.glue {
+            Some(glue) => {
+                let mut start = 0;
+                for (i, line) in input.split_inclusive('\n').enumerate() {
+                    if glue.is_match(line) {
+                        glued.push(i);
+                    } else {
+                        kept.push((unglued.len(), start));
+                        unglued.push_str(line);
+                    }
+                    start += line.len();
+                }
+                &unglued
+            }
+            None => input,
+        };
+        // matched without colors, and annotated in place of the original text
+        let colored = input;
+        let (plain, runs) = self.strip_ansi(colored);
+        let input = &*plain;
+        let offset = |pos: usize| remap(&kept, remap(&runs, pos));
+        // lines of ignored frames, and the line and start of the last one
+        let mut ignored = Vec::new();
+        let mut lines = (0, 0);
+        // annotations to insert after the end of each frame in the colored text
+        let mut insertions = Vec::new();
+        let mut frame = |caps: &Captures| {
+            let whole = caps.get(0).unwrap();
+            // Name-only frame followed by its address is annotated at the address instead
+            let addr = preset::group(caps, "addr");
+            let relative =
+                preset::group(caps, "rel").and_then(|rel| rel.as_str().parse::<u32>().ok());
+            if addr.is_none() && relative.is_none() && input[whole.end()..].starts_with(" (") {
+                return caps[0].to_owned();
+            }
+            let mut annotation = Vec::new();
+            let module = self
+                .module_url
+                .captures(whole.as_str())
+                .and_then(|caps| Some(caps.name("module")?.as_str()));
+            if let Some(module) = module.filter(|_| self.with_module) {
+                annotation.push(format!("[module: {module}]"));
+            }
+            let func = preset::group(caps, "func");
+            let index = func.and_then(|func| func.as_str().parse().ok());
+            let by_function = addr.is_none();
+            let addr = match addr {
+                Some(addr) => self.radix.parse(addr.as_str(), true),
+                None => self
+                    .module
+                    .as_ref()
+                    .and_then(|module| module.function_offset(index?))
+                    .map(|start| start + relative.unwrap_or_default()),
+            };
+            let found = addr.and_then(|addr| Some((addr, map.lookup_token(0, addr)?)));
+            let fallback = match (&self.fallback, addr) {
+                (Some(fallback), Some(addr)) if found.is_none() => fallback.resolve_frame(addr),
+                _ => None,
+            };
+            let located = fallback.as_ref().and_then(|lookup| lookup.location.clone());
+            match addr {
+                Some(_) if located.is_some() => self.count_elsewhere(),
+                Some(addr) => self.count(map, addr),
+                None => {}
+            }
+            let name = self
+                .symbols
+                .as_ref()
+                .and_then(|symbols| {
+                    let key = func.or(preset::group(caps, "symbol"))?;
+                    symbols.get(key.as_str())
+                })
+                .or_else(|| {
+                    let (addr, token) = found.as_ref().filter(|_| self.original_names)?;
+                    self.original_name(map, token, *addr, index)
+                })
+                .or_else(|| self.module.as_ref()?.function_name(index?))
+                .or_else(|| fallback.as_ref()?.name.as_deref());
+            if let Some(name) = name {
+                annotation.push(format!("[func: {name}]"));
+            }
+            let (addr, token, location, confidence) = match (found, addr.zip(located)) {
+                (Some((addr, token)), _) => {
+                    let confidence = match Confidence::of(&token, addr) {
+                        Confidence::Exact if by_function => Confidence::Nearest,
+                        confidence => confidence,
+                    };
+                    let location = locate(&token, &self.cwd);
+                    (addr, Some(token), location, confidence)
+                }
+                (None, Some((addr, location))) => (addr, None, location, Confidence::Nearest),
+                (None, None) => {
+                    if relative.is_some() && self.module.is_none() {
+                        annotation.push("[offset in function, pass --wasm to resolve]".to_owned());
+                    }
+                    if self.with_confidence {
+                        let confidence = match name {
+                            Some(_) => Confidence::NameOnly,
+                            None => Confidence::Unresolved,
+                        };
+                        annotation.push(format!("[confidence: {}]", confidence.as_str()));
+                    }
+                    let annotation = self.preset.escape(annotation.join(" "));
+                    return format!("{}{}{annotation}", &caps[0], self.separator());
+                }
+            };
+            let start = input[..whole.start()].rfind('\n').map_or(0, |i| i + 1);
+            let end = input[whole.end()..]
+                .find('\n')
+                .map_or(input.len(), |i| whole.end() + i);
+            let line = input[start..end].trim_end_matches('\r');
+            if let Some(ignore) = &self.ignore {
+                let function = name.or_else(|| ignore::function(line));
+                if ignore.is_ignored(&location.source, function) {
+                    lines.0 += input[lines.1..start].matches('\n').count();
+                    lines.1 = start;
+                    ignored.push(lines.0);
+                    return caps[0].to_owned();
+                }
+            }
+            let link = self
+                .github
+                .as_ref()
+                .and_then(|github| github.permalink(&location));
+            annotation.push(self.columns.format(&location));
+            if let Some(missing) = &self.missing {
+                if !Path::new(&location.source).exists() {
+                    *missing
+                        .borrow_mut()
+                        .entry(location.source.clone())
+                        .or_default() += 1;
+                    annotation.push("[missing]".to_owned());
+                }
+            }
+            annotation.extend(self.age(&location));
+            annotation.extend(self.blame(&location));
+            let owners = self.owners(&location);
+            if !owners.is_empty() {
+                annotation.push(format!("[owners: {}]", owners.join(" ")));
+            }
+            annotation.extend(self.preset.tag(&location.source).map(str::to_owned));
+            annotation.extend(link.clone());
+            if self.with_confidence {
+                annotation.push(format!("[confidence: {}]", confidence.as_str()));
+            }
+            on_frame(Resolved {
+                line,
+                offset: offset(whole.start()),
+                addr,
+                module,
+                name,
+                token: token.as_ref(),
+                location,
+                link,
+                confidence,
+                owners,
+            });
+            let annotation = self.preset.escape(annotation.join(" "));
+            format!("{}{}{annotation}", &caps[0], self.separator())
+        };
+        let result = self.re.replace_all(input, |caps: &Captures| {
+            let replaced = frame(caps);
+            let end = caps.get(0).unwrap().end();
+            if !runs.is_empty() && replaced.len() > end - caps.get(0).unwrap().start() {
+                insertions.push((end, replaced[caps[0].len()..].to_owned()));
+            }
+            replaced
+        });
+        let result = if runs.is_empty() {
+            result
+        } else {
+            let mut out = String::new();
+            let mut last = 0;
+            for (end, annotation) in insertions {
+                // after the last character of the frame, before the escape sequences following
+                let at = remap(&runs, end - 1) + 1;
+                out.push_str(&colored[last..at]);
+                out.push_str(&annotation);
+                last = at;
+            }
+            out.push_str(&colored[last..]);
+            Cow::Owned(out)
+        };
+        if let Some(origins) = &self.origins {
+            self.record(&mut origins.borrow_mut(), lines_in, &glued, &ignored);
+        }
+        if ignored.is_empty() {
+            return result.into_owned();
+        }
+        let mut out = String::new();
+        let mut run = 0;
+        let mut indent = "";
+        let collapse = |out: &mut String, run: &mut usize, indent: &str| {
+            if *run > 0 && !self.hide_ignored {
+                let s = if *run > 1 { "s" } else { "" };
+                out.push_str(&format!("{indent}... {run} ignored frame{s}\n"));
+            }
+            *run = 0;
+        };
+        for (i, line) in result.split_inclusive('\n').enumerate() {
+            if ignored.binary_search(&i).is_ok() {
+                if run == 0 {
+                    indent = &line[..line.len() - line.trim_start().len()];
+                }
+                run += 1;
+            } else {
+                collapse(&mut out, &mut run, indent);
+                out.push_str(line);
+            }
+        }
+        collapse(&mut out, &mut run, indent);
+        out
+    }
+
+    /// Origins of the lines of output of an input of `lines` lines, those at the indices of
+    /// `glued` left out, and those of the rest at the indices of `ignored` collapsed
+    fn record(&self, origins: &mut Vec<Origin>, lines: usize, glued: &[usize], ignored: &[usize]) {
+        let mut kept = 0;
+        let mut run = 0;
+        for i in 0..lines {
+            if glued.binary_search(&i).is_ok() {
+                origins.push(Origin::Removed(i));
+                continue;
+            }
+            if ignored.binary_search(&kept).is_ok() {
+                origins.push(Origin::Removed(i));
+                run += 1;
+            } else {
+                if run > 0 && !self.hide_ignored {
+                    origins.push(Origin::Added);
+                }
+                run = 0;
+                origins.push(Origin::Line(i));
+            }
+            kept += 1;
+        }
+        if run > 0 && !self.hide_ignored {
+            origins.push(Origin::Added);
+        }
+    }
+}
+
+/// Position in the original text of `pos` in the text made of the runs starting at the first of
+/// each pair, taken from the second
+fn remap(runs: &[(usize, usize)], pos: usize) -> usize {
+    let i = runs.partition_point(|&(run, _)| run <= pos);
+    match i.checked_sub(1) {
+        Some(i) => runs[i].1 + pos - runs[i].0,
+        None => pos,
+    }
+}
+
+/// Source map and preset bundled together, for symbolicating traceback in process
+pub struct Resolver {
+    map: SourceMap,
+    annotator: Annotator,
+}
+
+impl Resolver {
+    pub fn new(map: SourceMap, preset: Preset) -> Result<Self> {
+        Ok(Self {
+            map,
+            annotator: Annotator::new(preset, false)?,
+        })
+    }
+
+    /// Read source map from a file, or the one a WASM module refers to
+    pub fn open(path: &str, preset: Preset) -> Result<Self> {
+        Self::new(read_source_map(path)?, preset)
+    }
+
+    /// Consult the resolver for addresses the source map leaves unresolved
+    pub fn fallback(mut self, fallback: impl FrameResolver + Send + 'static) -> Self {
+        self.annotator.fallback = Some(Box::new(fallback));
+        self
+    }
+
+    /// Source location of the address, relative to the module
+    pub fn resolve(&self, addr: u32) -> Option<Location> {
+        match self.map.lookup_token(0, addr) {
+            Some(token) => Some(locate(&token, &self.annotator.cwd)),
+            None => {
+                self.annotator
+                    .fallback
+                    .as_ref()?
+                    .resolve_frame(addr)?
+                    .location
+            }
+        }
+    }
+
+    /// Source location of the first frame resolved in the text
+    pub fn first_frame(&self, text: &str) -> Option<Location> {
+        let mut first = None;
+        self.annotator.annotate(&self.map, text, |frame| {
+            first.get_or_insert(frame.location);
+        });
+        first
+    }
+
+    /// Append source location to each frame in the traceback
+    pub fn rewrite(&self, text: &str) -> String {
+        self.annotator.annotate(&self.map, text, |_| {})
+    }
+}
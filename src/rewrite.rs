@@ -0,0 +1,97 @@
+//! Surgery on source maps, written back with `sourceRoot` folded into the paths of sources, normalized
+
+use crate::normalize_source;
+use crate::sha256::sha256;
+use data_encoding::HEXLOWER;
+use debugid::DebugId;
+use eyre::{bail, eyre, Result};
+use sourcemap::{DecodedMap, SourceMap, SourceMapBuilder, SourceMapIndex, SourceMapSection};
+
+#[derive(Debug, Default)]
+pub struct Rewrite {
+    /// Prefixes of source paths replaced, the first rule matching taking effect
+    pub path_map: Vec<(String, String)>,
+    pub strip_sources_content: bool,
+    /// Debug ID to set, or derived from SHA-256 of the map rewritten if `Some(None)`
+    pub debug_id: Option<Option<DebugId>>,
+    /// Added to the address of every token
+    pub offset: i64,
+}
+
+impl Rewrite {
+    fn path(&self, source: &str) -> String {
+        let source = normalize_source(source);
+        self.path_map
+            .iter()
+            .find_map(|(from, to)| Some(format!("{to}{}", source.strip_prefix(from.as_str())?)))
+            .unwrap_or_else(|| source.into_owned())
+    }
+
+    pub fn apply(&self, map: &SourceMap) -> Result<SourceMap> {
+        let mut builder = SourceMapBuilder::new(map.get_file());
+        let mut sources = Vec::new();
+        for i in 0..map.get_source_count() {
+            let id = builder.add_source(&self.path(map.get_source(i).unwrap_or_default()));
+            if !self.strip_sources_content {
+                builder.set_source_contents(id, map.get_source_contents(i));
+            }
+            sources.push(id);
+        }
+        let names = (0..map.get_name_count())
+            .map(|i| builder.add_name(map.get_name(i).unwrap_or_default()))
+            .collect::<Vec<_>>();
+        for token in map.tokens() {
+            let raw = token.get_raw_token();
+            let dst_col = u32::try_from(i64::from(raw.dst_col) + self.offset)
+                .map_err(|_| eyre!("offset moves address {:#x} out of range", raw.dst_col))?;
+            builder.add_raw(
+                raw.dst_line,
+                dst_col,
+                raw.src_line,
+                raw.src_col,
+                sources.get(raw.src_id as usize).copied(),
+                names.get(raw.name_id as usize).copied(),
+                raw.is_range,
+            );
+        }
+        let mut rewritten = builder.into_sourcemap();
+        let debug_id = match self.debug_id {
+            None => map.get_debug_id(),
+            Some(Some(debug_id)) => Some(debug_id),
+            Some(None) => {
+                let mut json = Vec::new();
+                rewritten.to_writer(&mut json)?;
+                let hex = HEXLOWER.encode(&sha256(&json)[..16]);
+                let uuid = format!(
+                    "{}-{}-{}-{}-{}",
+                    &hex[..8],
+                    &hex[8..12],
+                    &hex[12..16],
+                    &hex[16..20],
+                    &hex[20..]
+                );
+                Some(uuid.parse()?)
+            }
+        };
+        rewritten.set_debug_id(debug_id);
+        Ok(rewritten)
+    }
+}
+
+/// Index map of sections of the maps at their offsets, which must be ascending
+pub fn merge(maps: Vec<(u32, SourceMap)>) -> Result<SourceMapIndex> {
+    if let Some(pair) = maps.windows(2).find(|pair| pair[0].0 >= pair[1].0) {
+        bail!(
+            "offsets {:#x} and {:#x} not ascending",
+            pair[0].0,
+            pair[1].0
+        );
+    }
+    let sections = maps
+        .into_iter()
+        .map(|(offset, map)| {
+            SourceMapSection::new((0, offset), None, Some(DecodedMap::Regular(map)))
+        })
+        .collect();
+    Ok(SourceMapIndex::new(None, sections))
+}
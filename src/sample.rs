@@ -0,0 +1,75 @@
+//! Choice of tracebacks to resolve on chatty streams, the others passed through unresolved
+
+use std::time::Instant;
+
+/// Sampling of `--sample` and budget of `--rate-limit`, decided at the first frame of each
+/// traceback
+pub struct Sampler {
+    every: Option<usize>,
+    rate: Option<u32>,
+    /// Tracebacks seen with frames
+    seen: usize,
+    /// Tracebacks that may still be resolved within the current second, and its start
+    budget: u32,
+    since: Option<Instant>,
+    /// Whether the current traceback is resolved, once decided
+    current: Option<bool>,
+}
+
+/// Parse `1/N`, resolving one of every N tracebacks
+pub fn parse_sample(s: &str) -> Result<usize, String> {
+    s.strip_prefix("1/")
+        .and_then(|n| n.parse().ok())
+        .filter(|&n| n > 0)
+        .ok_or_else(|| format!("expected 1/N, found `{s}`"))
+}
+
+/// Parse `N/s`, resolving at most N tracebacks per second
+pub fn parse_rate(s: &str) -> Result<u32, String> {
+    s.strip_suffix("/s")
+        .and_then(|n| n.parse().ok())
+        .ok_or_else(|| format!("expected N/s, found `{s}`"))
+}
+
+impl Sampler {
+    pub fn new(every: Option<usize>, rate: Option<u32>) -> Self {
+        Self {
+            every,
+            rate,
+            seen: 0,
+            budget: 0,
+            since: None,
+            current: None,
+        }
+    }
+
+    /// Whether to resolve the current traceback
+    pub fn admit(&mut self) -> bool {
+        if let Some(admitted) = self.current {
+            return admitted;
+        }
+        self.seen += 1;
+        let mut admitted = self
+            .every
+            .is_none_or(|every| (self.seen - 1).is_multiple_of(every));
+        if let (true, Some(rate)) = (admitted, self.rate) {
+            let now = Instant::now();
+            if self
+                .since
+                .is_none_or(|since| now.duration_since(since).as_secs() >= 1)
+            {
+                self.since = Some(now);
+                self.budget = rate;
+            }
+            admitted = self.budget > 0;
+            self.budget = self.budget.saturating_sub(1);
+        }
+        self.current = Some(admitted);
+        admitted
+    }
+
+    /// End the current traceback
+    pub fn end(&mut self) {
+        self.current = None;
+    }
+}
@@ -0,0 +1,81 @@
+use crate::trace;
+use crate::Frame;
+use eyre::Result;
+use serde_json::{json, Value};
+use std::path::Path;
+
+const RULE_ID: &str = "wasm-frame";
+
+fn artifact_uri(source: &str) -> String {
+    if Path::new(source).is_absolute() {
+        format!("file://{source}")
+    } else {
+        source.replace('\\', "/")
+    }
+}
+
+fn result(frame: &Frame, fingerprint: String) -> Value {
+    let mut result = json!({
+        "ruleId": RULE_ID,
+        "level": "error",
+        "message": { "text": frame.text.trim() },
+        "locations": [{
+            "physicalLocation": {
+                "artifactLocation": { "uri": artifact_uri(&frame.location.source) },
+                "region": {
+                    "startLine": frame.location.line,
+                    "startColumn": frame.location.column,
+                },
+            },
+        }],
+    });
+    if frame.column.is_none() {
+        // columns count from 1 in SARIF regardless of `--column-base`
+        result["locations"][0]["physicalLocation"]["region"]
+            .as_object_mut()
+            .unwrap()
+            .remove("startColumn");
+    }
+    result["partialFingerprints"] = json!({ "traceFingerprint/v1": fingerprint });
+    result["properties"] = json!({ "trace": frame.trace });
+    if let Some(panic) = &frame.panic {
+        result["properties"]["panic"] = json!(panic.message);
+        if let Some(location) = &panic.location {
+            result["properties"]["panicLocation"] = json!(location);
+        }
+    }
+    if let Some(module) = &frame.module {
+        result["properties"]["module"] = json!(module);
+    }
+    if let Some(link) = &frame.link {
+        result["properties"]["permalink"] = json!(link);
+    }
+    result
+}
+
+/// Render resolved frames as a SARIF 2.1.0 log
+pub fn render(frames: &[Frame]) -> Result<String> {
+    let log = json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": env!("CARGO_PKG_NAME"),
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "informationUri": env!("CARGO_PKG_REPOSITORY"),
+                    "rules": [{
+                        "id": RULE_ID,
+                        "shortDescription": { "text": "Frame of WASM traceback" },
+                    }],
+                },
+            },
+            "results": frames
+                .iter()
+                .zip(trace::fingerprints(frames))
+                .map(|(frame, fingerprint)| result(frame, fingerprint))
+                .collect::<Vec<_>>(),
+        }],
+    });
+    Ok(serde_json::to_string_pretty(&log)? + "\n")
+}
@@ -0,0 +1,178 @@
+//! Synthetic frames of tokens sampled across a map, run through the filter as a traceback would be
+//! and checked to resolve back to the locations of their tokens, telling whether a map works with
+//! a preset and the options given before it is needed for a real incident
+
+use crate::filter::{Filter, FilterArgs, Format};
+use crate::preset::Preset;
+use crate::wasm::Module;
+use crate::{locate, Columns, Maps};
+use eyre::{bail, Result};
+use regex::Regex;
+use std::collections::HashMap;
+use std::env::current_dir;
+use std::fmt;
+use std::sync::mpsc;
+
+/// Frame not resolving back to the location of its token
+#[derive(Debug)]
+pub struct Failure {
+    pub frame: String,
+    pub expected: String,
+    /// Line of output carrying the frame, or `None` if it is missing
+    pub got: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Report {
+    pub tokens: u32,
+    pub frames: usize,
+    pub failures: Vec<Failure>,
+}
+
+/// Frame of the preset for the address in the function, named `selftest_N` to find it in output
+fn frame(preset: Preset, n: usize, func: u32, addr: u32) -> String {
+    let name = format!("selftest_{n}");
+    match preset {
+        Preset::WasmBindgen => {
+            format!(
+                "    at {name} (http://localhost/pkg/app_bg.wasm:wasm-function[{func}]:{addr:#x})"
+            )
+        }
+        Preset::Deno => {
+            format!("    at {name} (file:///app/main.wasm:wasm-function[{func}]:{addr:#x})")
+        }
+        Preset::Emscripten => {
+            format!("    at {name} (http://localhost/app.wasm:wasm-function[{func}]:{addr:#x})")
+        }
+        Preset::Wasmer => format!("    at {name} (app.wasm[{func}]:{addr:#x})"),
+        Preset::Wazero => format!("    {name}()+{addr:#x}"),
+        _ => format!("    at {name} (wasm://wasm/00000000:wasm-function[{func}]:{addr:#x})"),
+    }
+}
+
+/// Check `samples` tokens spread over the map, and the same again as frames of older V8 giving
+/// offsets within functions if the preset has them and `--wasm` is given
+pub fn run(maps: &Maps, args: &FilterArgs, samples: usize) -> Result<Report> {
+    if args.preset == Preset::V8Log {
+        bail!("selftest does not support v8-log, which has no frames resolved to locations");
+    }
+    if args.format != Format::Text {
+        bail!("selftest checks text output, drop --format");
+    }
+    let module = args.wasm.as_deref().map(Module::open).transpose()?;
+    let cwd = if args.absolute_path {
+        None
+    } else {
+        Some(current_dir()?)
+    };
+    let columns = Columns {
+        base: args.column_base,
+        hidden: args.no_column,
+    };
+    let map = &maps.default;
+    let tokens = map.get_token_count();
+    let step = (tokens as usize / samples.max(1)).max(1);
+    let mut addrs: Vec<u32> = (0..tokens)
+        .step_by(step)
+        .filter_map(|i| map.get_token(i as usize))
+        .filter(|token| token.get_source().is_some())
+        .map(|token| token.get_dst_col())
+        .take(samples)
+        .collect();
+    addrs.dedup();
+    if addrs.is_empty() {
+        bail!("map has no mappings to sources");
+    }
+
+    let mut frames = Vec::new();
+    for &addr in &addrs {
+        let Some(token) = map.lookup_token(0, addr) else {
+            continue;
+        };
+        let location = locate(&token, &cwd);
+        let expected = columns.format(&location);
+        let func = module.as_ref().and_then(|module| module.function_at(addr));
+        frames.push((
+            frame(args.preset, frames.len(), func.unwrap_or_default(), addr),
+            expected.clone(),
+        ));
+        let start = module
+            .as_ref()
+            .zip(func)
+            .and_then(|(module, func)| Some((func, module.function_offset(func)?)));
+        if let (Some(_), Some((func, start))) = (args.preset.legacy(), start) {
+            let rel = addr - start;
+            let frame = format!(
+                "    at selftest_{} (wasm-function[{func}]:{rel})",
+                frames.len()
+            );
+            frames.push((frame, expected));
+        }
+    }
+
+    // room for the whole traceback, as held back by some options, taken without a thread
+    let (writer, written) = mpsc::sync_channel::<(bool, String)>(frames.len() * 4 + 64);
+    let mut filter = Filter::new(args, None)?.writer(writer);
+    let mut output = String::new();
+    filter.process(maps, "RuntimeError: unreachable\n");
+    for (frame, _) in &frames {
+        filter.process(maps, &format!("{frame}\n"));
+        output.extend(written.try_iter().map(|(_, s)| s));
+    }
+    filter.finish(maps)?;
+    output.extend(written.into_iter().map(|(_, s)| s));
+
+    let name = Regex::new(r"selftest_(\d+)").unwrap();
+    let mut lines = HashMap::new();
+    for line in output.lines() {
+        if let Some(n) = name
+            .captures(line)
+            .and_then(|caps| caps[1].parse::<usize>().ok())
+        {
+            lines.entry(n).or_insert(line);
+        }
+    }
+    let failures = frames
+        .iter()
+        .enumerate()
+        .filter_map(|(n, (frame, expected))| {
+            let got = lines.get(&n).copied();
+            let resolved = got.is_some_and(|line| {
+                line.match_indices(expected.as_str()).any(|(i, _)| {
+                    !line[i + expected.len()..].starts_with(|c: char| c.is_ascii_digit())
+                })
+            });
+            (!resolved).then(|| Failure {
+                frame: frame.trim().to_owned(),
+                expected: expected.clone(),
+                got: got.map(|line| line.trim().to_owned()),
+            })
+        })
+        .collect();
+    Ok(Report {
+        tokens,
+        frames: frames.len(),
+        failures,
+    })
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for failure in &self.failures {
+            writeln!(f, "{}", failure.frame)?;
+            writeln!(f, "  expected  {}", failure.expected)?;
+            writeln!(
+                f,
+                "  got       {}",
+                failure.got.as_deref().unwrap_or("no output")
+            )?;
+        }
+        writeln!(
+            f,
+            "{} of {} frames from {} tokens resolved back to their locations",
+            self.frames - self.failures.len(),
+            self.frames,
+            self.tokens
+        )
+    }
+}
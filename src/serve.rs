@@ -0,0 +1,514 @@
+//! HTTP server resolving log lines sent over a WebSocket at `/ws`, each message of lines answered by
+//! the output of its own filter as it comes, with `/healthz` and Prometheus `/metrics` for monitoring,
+//! source maps uploaded to `/maps` swapped in while running, and the maps of releases loaded from a
+//! symbol store on demand, wasm frames of error-tracking webhook payloads posted to `/webhook`
+//! and addresses of crash reports posted to `/crash` resolved in the response
+
+use crate::cache::Cache;
+use crate::crash::{self, Crash};
+use crate::filter::{Filter, FilterArgs};
+use crate::http::Client;
+use crate::store;
+use crate::webhook::Webhook;
+use crate::websocket::{self, Message};
+use crate::Maps;
+use debugid::DebugId;
+use eyre::{bail, eyre, Result};
+use sourcemap::SourceMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+/// Output queued for the client of each connection
+const QUEUE: usize = 4096;
+
+/// Largest source map accepted by upload
+const MAX_UPLOAD: usize = 256 << 20;
+
+/// Largest request line and headers together, larger ones refused with 431
+const MAX_HEAD: u64 = 64 << 10;
+
+/// Upper bounds in seconds of the buckets of the latency histogram
+const BUCKETS: [f64; 8] = [0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0];
+
+/// Counters since the server started
+#[derive(Default)]
+struct Metrics {
+    requests: AtomicU64,
+    connections: AtomicU64,
+    messages: AtomicU64,
+    resolved: AtomicU64,
+    unresolved: AtomicU64,
+    /// Messages resolved within each bucket, the last one counting all
+    latency: [AtomicU64; BUCKETS.len() + 1],
+    latency_micros: AtomicU64,
+}
+
+impl Metrics {
+    fn observe(&self, seconds: f64) {
+        for (bucket, count) in BUCKETS.iter().chain([&f64::INFINITY]).zip(&self.latency) {
+            if seconds <= *bucket {
+                count.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.latency_micros
+            .fetch_add((seconds * 1e6) as u64, Ordering::Relaxed);
+    }
+
+    /// Counters in Prometheus text format
+    fn render(&self) -> String {
+        let mut out = String::new();
+        for (name, help, counter) in [
+            ("requests", "HTTP requests served", &self.requests),
+            (
+                "connections",
+                "WebSocket connections accepted",
+                &self.connections,
+            ),
+            (
+                "messages",
+                "WebSocket messages of lines resolved",
+                &self.messages,
+            ),
+            (
+                "resolved",
+                "Addresses resolved to a location",
+                &self.resolved,
+            ),
+            ("unresolved", "Addresses left unresolved", &self.unresolved),
+        ] {
+            let value = counter.load(Ordering::Relaxed);
+            writeln!(out, "# HELP mbtmap_{name}_total {help}").unwrap();
+            writeln!(out, "# TYPE mbtmap_{name}_total counter").unwrap();
+            writeln!(out, "mbtmap_{name}_total {value}").unwrap();
+        }
+        let name = "mbtmap_message_duration_seconds";
+        writeln!(out, "# HELP {name} Time of resolving a WebSocket message").unwrap();
+        writeln!(out, "# TYPE {name} histogram").unwrap();
+        for (bucket, count) in BUCKETS.iter().zip(&self.latency) {
+            let count = count.load(Ordering::Relaxed);
+            writeln!(out, "{name}_bucket{{le=\"{bucket}\"}} {count}").unwrap();
+        }
+        let count = self.latency[BUCKETS.len()].load(Ordering::Relaxed);
+        writeln!(out, "{name}_bucket{{le=\"+Inf\"}} {count}").unwrap();
+        let sum = self.latency_micros.load(Ordering::Relaxed) as f64 / 1e6;
+        writeln!(out, "{name}_sum {sum}").unwrap();
+        writeln!(out, "{name}_count {count}").unwrap();
+        out
+    }
+}
+
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Request line and headers, or `None` if larger than [`MAX_HEAD`]
+    fn read(r: &mut impl BufRead) -> Result<Option<Self>> {
+        let mut head = r.take(MAX_HEAD);
+        let mut line = String::new();
+        head.read_line(&mut line)?;
+        if head.limit() == 0 {
+            return Ok(None);
+        }
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+            bail!("malformed HTTP request line");
+        };
+        let (path, query) = path.split_once('?').unwrap_or((path, ""));
+        let query = url::form_urlencoded::parse(query.as_bytes())
+            .into_owned()
+            .collect();
+        let (method, path) = (method.to_owned(), path.to_owned());
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            if head.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+                break;
+            }
+            if head.limit() == 0 {
+                return Ok(None);
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                headers.push((name.trim().to_ascii_lowercase(), value.trim().to_owned()));
+            }
+        }
+        Ok(Some(Self {
+            method,
+            path,
+            query,
+            headers,
+        }))
+    }
+
+    fn param(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// How the server takes uploads and loads releases
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Bearer token required to upload source maps, which are refused without one
+    pub token: Option<String>,
+    /// Symbol store holding the releases of `/ws?release=VERSION`
+    pub symbol_store: Option<String>,
+    /// Client fetching from the symbol store
+    pub client: Client,
+    /// Estimated memory the maps of releases and uploads are evicted to stay within
+    pub max_map_memory: usize,
+}
+
+/// Counters of the cache of releases in Prometheus text format
+fn render_cache(cache: &Cache) -> String {
+    let stats = cache.stats();
+    let mut out = String::new();
+    for (name, help, value) in [
+        ("hits", "Lookups of releases already loaded", stats.hits),
+        (
+            "misses",
+            "Lookups of releases loaded from the symbol store",
+            stats.misses,
+        ),
+        (
+            "evictions",
+            "Releases and uploads evicted to stay within --max-map-memory",
+            stats.evictions,
+        ),
+    ] {
+        writeln!(out, "# HELP mbtmap_map_cache_{name}_total {help}").unwrap();
+        writeln!(out, "# TYPE mbtmap_map_cache_{name}_total counter").unwrap();
+        writeln!(out, "mbtmap_map_cache_{name}_total {value}").unwrap();
+    }
+    writeln!(
+        out,
+        "# HELP mbtmap_map_cache_bytes Estimated memory of releases loaded and maps uploaded"
+    )
+    .unwrap();
+    writeln!(out, "# TYPE mbtmap_map_cache_bytes gauge").unwrap();
+    writeln!(out, "mbtmap_map_cache_bytes {}", stats.bytes).unwrap();
+    out
+}
+
+/// Shared by the connections of the server
+struct State<'a> {
+    args: &'a FilterArgs,
+    maps: &'a Maps,
+    metrics: Metrics,
+    options: Options,
+    /// Maps of releases by `release:VERSION` and maps uploaded by `upload:KEY`, the key being a
+    /// module URL or debug ID, in place of the default one of `maps`
+    cache: Cache,
+}
+
+impl State<'_> {
+    fn uploaded(&self, key: &str) -> Option<Arc<Maps>> {
+        self.cache.peek(&format!("upload:{key}"))
+    }
+}
+
+/// Whether the bearer token of `authorization` is `token`, in time independent of the bytes
+/// they share
+fn authorized(authorization: Option<&str>, token: &str) -> bool {
+    let expected = format!("Bearer {token}");
+    let Some(given) = authorization.map(str::as_bytes) else {
+        return false;
+    };
+    given.len() == expected.len()
+        && given
+            .iter()
+            .zip(expected.as_bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) -> Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    Ok(())
+}
+
+/// Listen on `addr`, serving each connection on a thread of its own until the process is killed
+pub fn serve(addr: &str, args: &FilterArgs, maps: &Maps, options: Options) -> Result<()> {
+    let listener = TcpListener::bind(addr).map_err(|e| eyre!("failed to listen on {addr}: {e}"))?;
+    eprintln!("listening on ws://{}/ws", listener.local_addr()?);
+    let state = State {
+        args,
+        maps,
+        metrics: Metrics::default(),
+        cache: Cache::new(options.max_map_memory),
+        options,
+    };
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let state = &state;
+            scope.spawn(move || {
+                let peer = stream
+                    .peer_addr()
+                    .map(|peer| peer.to_string())
+                    .unwrap_or_default();
+                if let Err(e) = handle(stream, state) {
+                    eprintln!("warning: {peer}: {e}");
+                }
+            });
+        }
+        Ok(())
+    })
+}
+
+/// Body of the request, or `None` once responded that its length is missing or too large
+fn read_body(
+    stream: &mut TcpStream,
+    reader: &mut impl Read,
+    request: &Request,
+) -> Result<Option<Vec<u8>>> {
+    let len = request
+        .header("content-length")
+        .and_then(|len| len.parse::<usize>().ok())
+        .filter(|&len| len <= MAX_UPLOAD);
+    let Some(len) = len else {
+        respond(
+            stream,
+            "413 Payload Too Large",
+            "text/plain",
+            &format!("Content-Length of at most {MAX_UPLOAD} bytes required\n"),
+        )?;
+        return Ok(None);
+    };
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Respond with the webhook payload of the request, its wasm frames resolved by the maps of the key
+fn webhook(
+    stream: &mut TcpStream,
+    reader: &mut impl Read,
+    request: &Request,
+    state: &State,
+) -> Result<()> {
+    let Some(body) = read_body(stream, reader, request)? else {
+        return Ok(());
+    };
+    let mut payload = match serde_json::from_slice(&body) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let message = format!("invalid payload: {e}\n");
+            return respond(stream, "400 Bad Request", "text/plain", &message);
+        }
+    };
+    let uploaded = request.param("key").and_then(|key| state.uploaded(key));
+    let resolved =
+        Webhook::default().symbolicate(&mut payload, uploaded.as_deref().unwrap_or(state.maps));
+    state
+        .metrics
+        .resolved
+        .fetch_add(resolved as u64, Ordering::Relaxed);
+    respond(
+        stream,
+        "200 OK",
+        "application/json",
+        &format!("{payload}\n"),
+    )
+}
+
+fn crash(
+    stream: &mut TcpStream,
+    reader: &mut impl Read,
+    request: &Request,
+    state: &State,
+) -> Result<()> {
+    let Some(body) = read_body(stream, reader, request)? else {
+        return Ok(());
+    };
+    let crash = match Crash::parse(&body) {
+        Ok(crash) => crash,
+        Err(e) => return respond(stream, "400 Bad Request", "text/plain", &format!("{e}\n")),
+    };
+    let uploaded = request.param("key").and_then(|key| state.uploaded(key));
+    let report = crash.symbolicate(uploaded.as_deref().unwrap_or(state.maps), &None);
+    state
+        .metrics
+        .resolved
+        .fetch_add(crash::resolved(&report) as u64, Ordering::Relaxed);
+    respond(stream, "200 OK", "application/json", &format!("{report}\n"))
+}
+
+/// Validate the source map uploaded and swap it in for its key
+fn upload(
+    stream: &mut TcpStream,
+    reader: &mut impl Read,
+    request: &Request,
+    state: &State,
+) -> Result<()> {
+    let authorized = (state.options.token.as_ref())
+        .is_some_and(|token| authorized(request.header("authorization"), token));
+    if !authorized {
+        return respond(stream, "401 Unauthorized", "text/plain", "unauthorized\n");
+    }
+    let Some(key) = request.param("key") else {
+        return respond(stream, "400 Bad Request", "text/plain", "missing key\n");
+    };
+    let Some(body) = read_body(stream, reader, request)? else {
+        return Ok(());
+    };
+    let map = match SourceMap::from_slice(&body) {
+        Ok(map) => map,
+        Err(e) => {
+            let message = format!("invalid source map: {e}\n");
+            return respond(stream, "422 Unprocessable Entity", "text/plain", &message);
+        }
+    };
+    if let (Ok(id), Some(debug_id)) = (key.parse::<DebugId>(), map.get_debug_id()) {
+        if id != debug_id {
+            let message = format!("debug ID of the source map is {debug_id}\n");
+            return respond(stream, "422 Unprocessable Entity", "text/plain", &message);
+        }
+    }
+    let maps = state.maps.with_default(map);
+    let (_, replaced) = state.cache.insert(&format!("upload:{key}"), maps);
+    eprintln!("source map uploaded for {key}");
+    if replaced {
+        respond(stream, "200 OK", "text/plain", "replaced\n")
+    } else {
+        respond(stream, "201 Created", "text/plain", "created\n")
+    }
+}
+
+fn handle(mut stream: TcpStream, state: &State) -> Result<()> {
+    let State { args, metrics, .. } = state;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let Some(request) = Request::read(&mut reader)? else {
+        let message = "request line and headers too large\n";
+        return respond(
+            &mut stream,
+            "431 Request Header Fields Too Large",
+            "text/plain",
+            message,
+        );
+    };
+    metrics.requests.fetch_add(1, Ordering::Relaxed);
+    match (request.method.as_str(), request.path.as_str()) {
+        (_, "/ws") => {}
+        (_, "/healthz") => return respond(&mut stream, "200 OK", "text/plain", "ok\n"),
+        (_, "/metrics") => {
+            let mut body = metrics.render();
+            body.push_str(&render_cache(&state.cache));
+            return respond(&mut stream, "200 OK", "text/plain; version=0.0.4", &body);
+        }
+        ("POST" | "PUT", "/maps") => return upload(&mut stream, &mut reader, &request, state),
+        ("POST", "/webhook") => return webhook(&mut stream, &mut reader, &request, state),
+        ("POST", "/crash") => return crash(&mut stream, &mut reader, &request, state),
+        _ => return respond(&mut stream, "404 Not Found", "text/plain", "not found\n"),
+    }
+    // map uploaded for the key, looked up again for each message to pick up uploads
+    let map_key = request.param("key").map(str::to_owned);
+    let release = match (request.param("release"), &state.options.symbol_store) {
+        (None, _) => None,
+        (Some(_), None) => {
+            let message = "no symbol store to load releases from\n";
+            return respond(&mut stream, "404 Not Found", "text/plain", message);
+        }
+        (Some(release), Some(symbol_store)) => {
+            let client = &state.options.client;
+            let loaded = state.cache.get(&format!("release:{release}"), || {
+                store::lookup(client, symbol_store, release)?.into_maps(client, &args.map_for)
+            });
+            match loaded {
+                Ok(maps) => Some(maps),
+                Err(e) => {
+                    let message = format!("{e}\n");
+                    return respond(&mut stream, "404 Not Found", "text/plain", &message);
+                }
+            }
+        }
+    };
+    let uploaded = || map_key.as_ref().and_then(|key| state.uploaded(key));
+    let uploaded = || uploaded().or_else(|| release.clone());
+    let key = request.header("sec-websocket-key");
+    let upgrade = request
+        .header("upgrade")
+        .is_some_and(|upgrade| upgrade.eq_ignore_ascii_case("websocket"));
+    let (Some(key), true, "GET") = (key, upgrade, request.method.as_str()) else {
+        return respond(
+            &mut stream,
+            "426 Upgrade Required",
+            "text/plain",
+            "WebSocket only\n",
+        );
+    };
+    write!(
+        stream,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {}\r\n\r\n",
+        websocket::accept(key)
+    )?;
+    metrics.connections.fetch_add(1, Ordering::Relaxed);
+    let stream = Mutex::new(stream);
+    let (writer, output) = mpsc::sync_channel::<(bool, String)>(QUEUE);
+    let mut filter = Filter::new(args, None)?.writer(writer);
+    thread::scope(|scope| {
+        let writing = scope.spawn(|| -> Result<()> {
+            for (_, s) in output {
+                websocket::write_frame(&mut *stream.lock().unwrap(), 0x1, s.as_bytes(), false)?;
+            }
+            Ok(())
+        });
+        let result = (|| -> Result<()> {
+            let mut fragments = Vec::new();
+            while let Some(message) = websocket::read_message(&mut reader, &mut fragments)? {
+                match message {
+                    Message::Text(mut lines) => {
+                        if !lines.ends_with('\n') {
+                            lines.push('\n');
+                        }
+                        let (start, before) = (Instant::now(), filter.coverage());
+                        let uploaded = uploaded();
+                        filter.process(uploaded.as_deref().unwrap_or(state.maps), &lines);
+                        let after = filter.coverage();
+                        metrics.observe(start.elapsed().as_secs_f64());
+                        metrics.messages.fetch_add(1, Ordering::Relaxed);
+                        let resolved = after.resolved - before.resolved;
+                        let unresolved = after.addresses - before.addresses - resolved;
+                        metrics
+                            .resolved
+                            .fetch_add(resolved as u64, Ordering::Relaxed);
+                        metrics
+                            .unresolved
+                            .fetch_add(unresolved as u64, Ordering::Relaxed);
+                    }
+                    Message::Ping(payload) => {
+                        websocket::write_frame(&mut *stream.lock().unwrap(), 0xa, &payload, false)?
+                    }
+                    Message::Close => break,
+                }
+            }
+            Ok(())
+        })();
+        let uploaded = uploaded();
+        let finished = filter.finish(uploaded.as_deref().unwrap_or(state.maps));
+        writing.join().unwrap()?;
+        _ = websocket::write_frame(&mut *stream.lock().unwrap(), 0x8, &[], false);
+        result.and(finished)
+    })
+}
@@ -0,0 +1,245 @@
+//! `mbtmap serve` kept running in the background for the user, started at login by the service
+//! manager of the platform, for apps symbolicating against a local endpoint at all times
+//!
+//! Units are a systemd user unit on Linux, a launchd agent on macOS, and on Windows a task
+//! scheduled at logon, as a service run by the service control manager must answer its control
+//! protocol.
+
+use eyre::{bail, eyre, Result};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Name of the unit, the launchd label and the scheduled task
+pub const NAME: &str = "mbtmap";
+const LABEL: &str = "io.github.nkid00.mbtmap";
+
+/// Service manager of the platform
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Manager {
+    Systemd,
+    Launchd,
+    TaskScheduler,
+}
+
+/// `mbtmap serve` with the arguments, run in the directory installed from
+pub struct Service {
+    program: PathBuf,
+    args: Vec<String>,
+    dir: PathBuf,
+}
+
+fn home() -> Result<PathBuf> {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .ok_or_else(|| eyre!("HOME is not set"))
+}
+
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|e| eyre!("failed to run {program}: {e}"))?;
+    if !status.success() {
+        bail!("{program} {} failed: {status}", args.join(" "));
+    }
+    Ok(())
+}
+
+/// Argument of `ExecStart=`, quoted with specifiers and variables escaped
+fn systemd_quote(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Argument of a batch file, quoted unless plain, with `%` doubled against expansion
+fn cmd_quote(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+    if !arg.is_empty() && !arg.contains([' ', '\t', '"', '&', '|', '<', '>', '^']) {
+        return arg;
+    }
+    format!("\"{}\"", arg.replace('"', "\"\""))
+}
+
+impl Manager {
+    pub fn current() -> Self {
+        if cfg!(windows) {
+            Manager::TaskScheduler
+        } else if cfg!(target_os = "macos") {
+            Manager::Launchd
+        } else {
+            Manager::Systemd
+        }
+    }
+
+    /// Path of the unit file, the batch file run by the task on Windows
+    pub fn path(self) -> Result<PathBuf> {
+        Ok(match self {
+            Manager::Systemd => match env::var_os("XDG_CONFIG_HOME") {
+                Some(dir) => PathBuf::from(dir),
+                None => home()?.join(".config"),
+            }
+            .join("systemd/user")
+            .join(format!("{NAME}.service")),
+            Manager::Launchd => home()?
+                .join("Library/LaunchAgents")
+                .join(format!("{LABEL}.plist")),
+            Manager::TaskScheduler => env::var_os("LOCALAPPDATA")
+                .map(PathBuf::from)
+                .ok_or_else(|| eyre!("LOCALAPPDATA is not set"))?
+                .join(NAME)
+                .join("service.cmd"),
+        })
+    }
+
+    /// Stop the service and remove its unit, if installed
+    pub fn uninstall(self) -> Result<()> {
+        let path = self.path()?;
+        let path_str = path.to_string_lossy();
+        match self {
+            Manager::Systemd => {
+                let unit = format!("{NAME}.service");
+                // stopped for the unit to go even if it was never enabled
+                run("systemctl", &["--user", "disable", "--now", &unit]).ok();
+                remove(&path)?;
+                run("systemctl", &["--user", "daemon-reload"])
+            }
+            Manager::Launchd => {
+                run("launchctl", &["unload", "-w", &path_str]).ok();
+                remove(&path)
+            }
+            Manager::TaskScheduler => {
+                run("schtasks", &["/End", "/TN", NAME]).ok();
+                run("schtasks", &["/Delete", "/F", "/TN", NAME])?;
+                remove(&path)
+            }
+        }
+    }
+}
+
+fn remove(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+            Err(eyre!("failed to remove {}: {e}", path.display()))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Service {
+    /// Service running this executable with the arguments of `serve`, in the current directory so
+    /// that relative paths among them resolve as they do now
+    pub fn new(args: Vec<String>) -> Result<Self> {
+        Ok(Self {
+            program: env::current_exe()?,
+            args,
+            dir: env::current_dir()?,
+        })
+    }
+
+    fn command(&self) -> Vec<String> {
+        let mut command = vec![
+            self.program.to_string_lossy().into_owned(),
+            "serve".to_owned(),
+        ];
+        command.extend(self.args.iter().cloned());
+        command
+    }
+
+    /// Contents of the unit file for the manager
+    pub fn unit(&self, manager: Manager) -> Result<String> {
+        let command = self.command();
+        let dir = self.dir.to_string_lossy();
+        Ok(match manager {
+            Manager::Systemd => {
+                let exec = command
+                    .iter()
+                    .map(|arg| systemd_quote(arg))
+                    .collect::<Vec<_>>();
+                format!(
+                    "[Unit]\nDescription=mbtmap symbolication server\n\n\
+                     [Service]\nExecStart={}\nWorkingDirectory={}\nRestart=on-failure\n\n\
+                     [Install]\nWantedBy=default.target\n",
+                    exec.join(" "),
+                    dir.replace('%', "%%"),
+                )
+            }
+            Manager::Launchd => {
+                let log = home()?.join("Library/Logs/mbtmap.log");
+                let log = xml_escape(&log.to_string_lossy());
+                let mut args = String::new();
+                for arg in &command {
+                    args.push_str(&format!("\t\t<string>{}</string>\n", xml_escape(arg)));
+                }
+                format!(
+                    "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+                     <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+                     <plist version=\"1.0\">\n<dict>\n\
+                     \t<key>Label</key>\n\t<string>{LABEL}</string>\n\
+                     \t<key>ProgramArguments</key>\n\t<array>\n{args}\t</array>\n\
+                     \t<key>WorkingDirectory</key>\n\t<string>{}</string>\n\
+                     \t<key>RunAtLoad</key>\n\t<true/>\n\
+                     \t<key>KeepAlive</key>\n\t<true/>\n\
+                     \t<key>StandardOutPath</key>\n\t<string>{log}</string>\n\
+                     \t<key>StandardErrorPath</key>\n\t<string>{log}</string>\n\
+                     </dict>\n</plist>\n",
+                    xml_escape(&dir),
+                )
+            }
+            Manager::TaskScheduler => {
+                let exec = command.iter().map(|arg| cmd_quote(arg)).collect::<Vec<_>>();
+                let log = manager.path()?.with_file_name("service.log");
+                format!(
+                    "@echo off\r\ncd /d {}\r\n{} >> {} 2>&1\r\n",
+                    cmd_quote(&dir),
+                    exec.join(" "),
+                    cmd_quote(&log.to_string_lossy()),
+                )
+            }
+        })
+    }
+
+    /// Write the unit, replacing one installed before, and start it now and at each login
+    pub fn install(&self, manager: Manager) -> Result<PathBuf> {
+        let path = manager.path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(&path, self.unit(manager)?)
+            .map_err(|e| eyre!("failed to write {}: {e}", path.display()))?;
+        let path_str = path.to_string_lossy();
+        match manager {
+            Manager::Systemd => {
+                let unit = format!("{NAME}.service");
+                run("systemctl", &["--user", "daemon-reload"])?;
+                run("systemctl", &["--user", "enable", &unit])?;
+                run("systemctl", &["--user", "restart", &unit])?;
+            }
+            Manager::Launchd => {
+                run("launchctl", &["unload", &path_str]).ok();
+                run("launchctl", &["load", "-w", &path_str])?;
+            }
+            Manager::TaskScheduler => {
+                let task = format!("\"{path_str}\"");
+                run(
+                    "schtasks",
+                    &["/Create", "/F", "/SC", "ONLOGON", "/TN", NAME, "/TR", &task],
+                )?;
+                run("schtasks", &["/Run", "/TN", NAME])?;
+            }
+        }
+        Ok(path)
+    }
+}
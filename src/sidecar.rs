@@ -0,0 +1,159 @@
+//! Metadata shipped next to a source map in `<map>.meta.json` or `<map>.meta.toml`, describing how
+//! to resolve with it in place of flags given on each invocation
+//!
+//! The keys are `base`, the address the module is loaded at added to every address of the map,
+//! `debug_id`, `release`, and `path_map`, rules of `FROM=TO` replacing prefixes of source paths.
+//! TOML is read as far as strings, integers and arrays of strings on a line of their own.
+
+use crate::parse_addr;
+use crate::rewrite::Rewrite;
+use debugid::DebugId;
+use eyre::{bail, eyre, Result};
+use serde_json::{Map, Value};
+use sourcemap::SourceMap;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Default, Clone)]
+pub struct Sidecar {
+    pub base: u32,
+    pub debug_id: Option<DebugId>,
+    pub release: Option<String>,
+    pub path_map: Vec<(String, String)>,
+}
+
+/// Value of a TOML line, a string, an integer or an array of strings
+fn toml_value(value: &str) -> Option<Value> {
+    let string = |value: &str| {
+        let value = value.trim();
+        let quoted = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'')?.strip_suffix('\''))?;
+        Some(quoted.replace("\\\\", "\\").replace("\\\"", "\""))
+    };
+    if let Some(items) = value.strip_prefix('[').and_then(|v| v.strip_suffix(']')) {
+        let items = items.trim().trim_end_matches(',');
+        if items.trim().is_empty() {
+            return Some(Value::Array(Vec::new()));
+        }
+        return items
+            .split(',')
+            .map(|item| string(item).map(Value::String))
+            .collect::<Option<_>>()
+            .map(Value::Array);
+    }
+    string(value)
+        .map(Value::String)
+        .or_else(|| Some(Value::from(value.replace('_', "").parse::<i64>().ok()?)))
+        .or_else(|| Some(Value::from(parse_addr(&value.replace('_', ""))?)))
+}
+
+fn parse_toml(text: &str, path: &Path) -> Result<Map<String, Value>> {
+    let mut keys = Map::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let value = line.split_once('=').and_then(|(key, value)| {
+            let value = match value.find(" #") {
+                Some(comment) if !value.trim_start().starts_with(['"', '\'']) => &value[..comment],
+                _ => value,
+            };
+            Some((key.trim().to_owned(), toml_value(value.trim())?))
+        });
+        let Some((key, value)) = value else {
+            bail!("unsupported line {} of {}", i + 1, path.display());
+        };
+        keys.insert(key, value);
+    }
+    Ok(keys)
+}
+
+impl Sidecar {
+    /// Sidecar next to the source map or WASM module at `path`, if any
+    pub fn find(path: &str) -> Result<Option<Self>> {
+        for extension in ["json", "toml"] {
+            let sidecar = format!("{path}.meta.{extension}");
+            let sidecar = Path::new(&sidecar);
+            if sidecar.is_file() {
+                return Self::open(sidecar).map(Some);
+            }
+        }
+        Ok(None)
+    }
+
+    pub fn open(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let keys = if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            parse_toml(&text, path)?
+        } else {
+            match serde_json::from_str(&text)? {
+                Value::Object(keys) => keys,
+                _ => bail!("{} is not a JSON object", path.display()),
+            }
+        };
+        let invalid = |key: &str| eyre!("invalid {key} in {}", path.display());
+        let mut sidecar = Self::default();
+        for (key, value) in &keys {
+            match key.as_str() {
+                "base" => {
+                    sidecar.base = match value {
+                        Value::String(base) => parse_addr(base),
+                        value => value.as_u64().and_then(|base| base.try_into().ok()),
+                    }
+                    .ok_or_else(|| invalid(key))?
+                }
+                "debug_id" => {
+                    let id = value.as_str().and_then(|id| id.parse().ok());
+                    sidecar.debug_id = Some(id.ok_or_else(|| invalid(key))?);
+                }
+                "release" => {
+                    sidecar.release = Some(value.as_str().ok_or_else(|| invalid(key))?.to_owned())
+                }
+                "path_map" => {
+                    sidecar.path_map = value
+                        .as_array()
+                        .ok_or_else(|| invalid(key))?
+                        .iter()
+                        .map(|rule| {
+                            let (from, to) = rule.as_str()?.split_once('=')?;
+                            Some((from.to_owned(), to.to_owned()))
+                        })
+                        .collect::<Option<_>>()
+                        .ok_or_else(|| invalid(key))?
+                }
+                _ => bail!("unknown key {key} in {}", path.display()),
+            }
+        }
+        Ok(sidecar)
+    }
+
+    /// The map rebased and with source paths replaced, refusing one of another debug ID
+    pub fn apply(&self, map: SourceMap) -> Result<SourceMap> {
+        if let (Some(expected), Some(debug_id)) = (self.debug_id, map.get_debug_id()) {
+            if expected != debug_id {
+                bail!("debug ID of the source map is {debug_id}, not {expected} as its sidecar declares");
+            }
+        }
+        if self.base == 0
+            && self.path_map.is_empty()
+            && self
+                .debug_id
+                .is_none_or(|id| map.get_debug_id() == Some(id))
+        {
+            return Ok(map);
+        }
+        Rewrite {
+            path_map: self.path_map.clone(),
+            strip_sources_content: false,
+            debug_id: self.debug_id.map(Some),
+            offset: self.base.into(),
+        }
+        .apply(&map)
+    }
+}
@@ -0,0 +1,220 @@
+//! Local copies of the WASM modules and JS bundles of a deployment along with their source maps,
+//! for resolving traces after the assets are replaced
+//!
+//! Assets are mirrored under `<host>/<path>` of their URLs, so that the `sourceMappingURL` of each
+//! one still leads to its map, or laid out flat under `<release>/` as a symbol store expects.
+
+use crate::http::{self, Client};
+use crate::wasm::Module;
+use crate::{decode_data_url, object};
+use eyre::{bail, eyre, Result};
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Counts of the files saved
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Snapshot {
+    pub assets: usize,
+    pub maps: usize,
+}
+
+enum Kind {
+    Bundle,
+    Module,
+    Map,
+    Page,
+}
+
+fn kind(url: &Url) -> Kind {
+    let path = url.path();
+    if path.ends_with(".wasm") {
+        Kind::Module
+    } else if path.ends_with(".map") {
+        Kind::Map
+    } else if [".js", ".mjs", ".cjs"]
+        .iter()
+        .any(|ext| path.ends_with(ext))
+    {
+        Kind::Bundle
+    } else {
+        Kind::Page
+    }
+}
+
+/// Where the file of the URL is saved under `dir`
+fn local_path(dir: &Path, url: &Url, release: Option<&str>) -> Result<PathBuf> {
+    let segments = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|segment| !segment.is_empty() && *segment != "." && *segment != "..")
+        .collect::<Vec<_>>();
+    let Some(name) = segments.last() else {
+        bail!("{url}: no file name");
+    };
+    Ok(match release {
+        Some(release) => dir.join(release).join(name),
+        None => {
+            let mut host = url.host_str().unwrap_or("local").to_owned();
+            if let Some(port) = url.port() {
+                host.push_str(&format!("_{port}"));
+            }
+            segments
+                .iter()
+                .fold(dir.join(host), |path, segment| path.join(segment))
+        }
+    })
+}
+
+struct Snapshotter<'a> {
+    client: &'a Client,
+    dir: &'a Path,
+    release: Option<&'a str>,
+    seen: HashSet<String>,
+    snapshot: Snapshot,
+    /// Assets referred to by pages, such as `<script src>` and `<link href>`
+    page_asset: Regex,
+    /// Modules loaded by JS glue, such as `new URL("app_bg.wasm", import.meta.url)`
+    module_literal: Regex,
+}
+
+impl Snapshotter<'_> {
+    fn save(&mut self, url: &Url, data: &[u8], map: bool) -> Result<()> {
+        let path = local_path(self.dir, url, self.release)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, data)?;
+        eprintln!("saved {url} to {}", path.display());
+        if map {
+            self.snapshot.maps += 1;
+        } else {
+            self.snapshot.assets += 1;
+        }
+        Ok(())
+    }
+
+    /// Save the map the asset refers to, decoded into `<asset>.map` if inline in a store
+    fn save_map(&mut self, asset: &Url, reference: &str) -> Result<()> {
+        if reference.starts_with("data:") {
+            if self.release.is_some() {
+                let url = Url::parse(&format!("{asset}.map"))?;
+                self.save(&url, &decode_data_url(reference)?, true)?;
+            }
+            return Ok(());
+        }
+        self.fetch(asset.join(reference)?.as_str(), true)
+    }
+
+    fn fetch(&mut self, url: &str, map: bool) -> Result<()> {
+        if !self.seen.insert(url.to_owned()) {
+            return Ok(());
+        }
+        let (location, data) = self.client.read_redirected(url)?;
+        let location = Url::parse(&location)?;
+        // maps are referred to by any name
+        let kind = if map { Kind::Map } else { kind(&location) };
+        match kind {
+            Kind::Map => self.save(&location, &data, true)?,
+            Kind::Module => {
+                let module = Module::parse(&data)?;
+                match module.source_mapping_url() {
+                    Some(reference) => {
+                        self.save(&location, &data, false)?;
+                        self.save_map(&location, reference)?;
+                    }
+                    // of no use to a store, where it would be taken for an entry without a map
+                    None if self.release.is_some() => {
+                        eprintln!("warning: skipped {location} without sourceMappingURL section")
+                    }
+                    None => {
+                        self.save(&location, &data, false)?;
+                        eprintln!("warning: {location} has no sourceMappingURL section");
+                    }
+                }
+            }
+            Kind::Bundle => {
+                self.save(&location, &data, false)?;
+                match sourcemap::locate_sourcemap_reference_slice(&data)? {
+                    Some(reference) => self.save_map(&location, reference.get_url())?,
+                    None => eprintln!("warning: {location} has no sourceMappingURL comment"),
+                }
+                let text = String::from_utf8_lossy(&data);
+                let modules = self
+                    .module_literal
+                    .captures_iter(&text)
+                    .filter_map(|caps| location.join(&caps[1]).ok())
+                    .collect::<Vec<_>>();
+                for module in modules {
+                    self.fetch(module.as_str(), false)?;
+                }
+            }
+            Kind::Page => {
+                let text = String::from_utf8_lossy(&data);
+                let assets = self
+                    .page_asset
+                    .captures_iter(&text)
+                    .filter_map(|caps| location.join(&caps[1]).ok())
+                    .collect::<Vec<_>>();
+                if assets.is_empty() {
+                    bail!("{location}: no scripts or WASM modules found in the page");
+                }
+                for asset in assets {
+                    self.fetch(asset.as_str(), false)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// URLs listed in the file, one per line, skipping blank lines and `#` comments
+fn asset_list(path: &str) -> Result<Vec<String>> {
+    let list = fs::read_to_string(path).map_err(|e| eyre!("failed to read {path}: {e}"))?;
+    Ok(list
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_owned)
+        .collect())
+}
+
+/// Save the assets of the pages or asset URLs, or of files listing asset URLs, along with the
+/// maps they refer to, under `dir`
+pub fn snapshot(
+    client: &Client,
+    sources: &[String],
+    dir: &Path,
+    release: Option<&str>,
+) -> Result<Snapshot> {
+    if let Some(release) =
+        release.filter(|release| release.contains(['/', '\\']) || *release == "..")
+    {
+        bail!("invalid release {release}");
+    }
+    let mut snapshotter = Snapshotter {
+        client,
+        dir,
+        release,
+        seen: HashSet::new(),
+        snapshot: Snapshot::default(),
+        page_asset: Regex::new(
+            r#"(?:src|href)\s*=\s*["']([^"']+\.(?:m?js|wasm)(?:\?[^"']*)?)["']"#,
+        )?,
+        module_literal: Regex::new(r#"["'`]([^"'`\s]+\.wasm)["'`]"#)?,
+    };
+    for source in sources {
+        let urls = if http::is_url(source) || object::is_url(source) {
+            vec![source.clone()]
+        } else {
+            asset_list(source)?
+        };
+        for url in urls {
+            snapshotter.fetch(&url, false)?;
+        }
+    }
+    Ok(snapshotter.snapshot)
+}
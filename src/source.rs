@@ -0,0 +1,70 @@
+use crate::normalize_source;
+use sourcemap::{SourceMap, Token};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Number of lines shown before and after the resolved line in a snippet
+const CONTEXT: u32 = 2;
+
+/// Lines of source around a resolved location
+#[derive(Debug, Clone)]
+pub struct Snippet {
+    /// 1-based line number of the first line in `lines`
+    pub first_line: u32,
+    /// 1-based line number of the resolved line
+    pub line: u32,
+    pub lines: Vec<String>,
+}
+
+/// Cache of source files read from disk, falling back to `sourcesContent` of the map
+#[derive(Debug, Default)]
+pub struct Sources {
+    files: HashMap<String, Option<Vec<String>>>,
+}
+
+impl Sources {
+    fn lines(
+        &mut self,
+        map: &SourceMap,
+        token: &Token,
+        cwd: &Option<PathBuf>,
+    ) -> Option<&[String]> {
+        let source = token.get_source()?;
+        self.files
+            .entry(source.to_owned())
+            .or_insert_with(|| {
+                let source = normalize_source(source);
+                let path = match cwd {
+                    Some(cwd) => cwd.join(&*source),
+                    None => PathBuf::from(&*source),
+                };
+                let content = match fs::read_to_string(path) {
+                    Ok(content) => content,
+                    Err(_) => map.get_source_contents(token.get_src_id())?.to_owned(),
+                };
+                Some(content.lines().map(str::to_owned).collect())
+            })
+            .as_deref()
+    }
+
+    pub fn snippet(
+        &mut self,
+        map: &SourceMap,
+        token: &Token,
+        cwd: &Option<PathBuf>,
+    ) -> Option<Snippet> {
+        let lines = self.lines(map, token, cwd)?;
+        let line = token.get_src_line();
+        if line as usize >= lines.len() {
+            return None;
+        }
+        let first = line.saturating_sub(CONTEXT);
+        let last = (line + CONTEXT).min(lines.len() as u32 - 1);
+        Some(Snippet {
+            first_line: first + 1,
+            line: line + 1,
+            lines: lines[first as usize..=last as usize].to_vec(),
+        })
+    }
+}
@@ -0,0 +1,71 @@
+//! Symbol store holding the maps of each release, keyed by release version or debug ID
+//!
+//! A store is a directory, or an HTTP, `s3://` or `gs://` URL, holding a bundle `<release>.symbols` per release. A local
+//! store may instead hold a directory `<release>/` of source maps or WASM modules, the default
+//! chosen as `--auto` does.
+
+use crate::bundle::{entry_name, Bundle};
+use crate::http::{self, Client};
+use crate::object;
+use eyre::{bail, Result};
+use std::fs;
+use std::path::Path;
+
+fn is_map(path: &Path) -> bool {
+    let path = path.to_string_lossy();
+    path.ends_with(".map") || path.ends_with(".wasm")
+}
+
+/// Bundle of the release from the store
+pub fn lookup(client: &Client, store: &str, release: &str) -> Result<Bundle> {
+    if release.contains(['/', '\\']) || release == ".." {
+        bail!("invalid release {release}");
+    }
+    if http::is_url(store) || object::is_url(store) {
+        let url = format!("{}/{release}.symbols", store.trim_end_matches('/'));
+        return Bundle::parse(&client.read(&url)?, &url);
+    }
+    let bundle = Path::new(store).join(format!("{release}.symbols"));
+    if bundle.is_file() {
+        return Bundle::open(&bundle.to_string_lossy());
+    }
+    let dir = Path::new(store).join(release);
+    let Ok(entries) = fs::read_dir(&dir) else {
+        bail!("release {release} not found in symbol store {store}");
+    };
+    let mut paths = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| is_map(path))
+        .collect::<Vec<_>>();
+    paths.sort();
+    let rank = |path: &Path| {
+        let path = path.to_string_lossy();
+        if path.ends_with("_bg.wasm.map") {
+            0
+        } else if path.ends_with(".wasm.map") {
+            1
+        } else if path.ends_with(".map") {
+            2
+        } else {
+            3
+        }
+    };
+    paths.sort_by_key(|path| rank(path));
+    let mut names = Vec::new();
+    // a map and the module referring to it make the same entry
+    paths.retain(|path| {
+        let name = entry_name(&path.to_string_lossy());
+        let new = !names.contains(&name);
+        names.push(name);
+        new
+    });
+    if paths.is_empty() {
+        bail!("no source map found for release {release} in symbol store {store}");
+    }
+    let paths = paths
+        .into_iter()
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    Bundle::create(&paths, &[("release".to_owned(), release.to_owned())])
+}
@@ -0,0 +1,50 @@
+use crate::resolver::{FrameResolver, Lookup};
+use crate::wasm::Module;
+use eyre::Result;
+use std::collections::HashMap;
+use std::fs;
+
+/// Function names from symbol map emitted by `emcc --emit-symbol-map`, keyed by function index
+/// or minified name
+#[derive(Debug, Default)]
+pub struct Symbols(HashMap<String, String>);
+
+impl Symbols {
+    pub fn open(path: &str) -> Result<Self> {
+        Ok(Self(
+            fs::read_to_string(path)?
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .map(|(key, name)| (key.trim().to_owned(), name.trim().to_owned()))
+                .collect(),
+        ))
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+}
+
+/// Resolver naming addresses by the symbol map entry of the function of the module they fall in,
+/// for builds shipping no source map
+pub struct SymbolFile {
+    symbols: Symbols,
+    module: Module,
+}
+
+impl SymbolFile {
+    pub fn new(symbols: Symbols, module: Module) -> Self {
+        Self { symbols, module }
+    }
+}
+
+impl FrameResolver for SymbolFile {
+    fn resolve_frame(&self, addr: u32) -> Option<Lookup> {
+        let index = self.module.function_at(addr)?;
+        let name = self.symbols.get(&index.to_string())?;
+        Some(Lookup {
+            location: None,
+            name: Some(name.to_owned()),
+        })
+    }
+}
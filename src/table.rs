@@ -0,0 +1,113 @@
+use crate::Frame;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, HashMap};
+
+const HEADER: [&str; 7] = [
+    "addr", "module", "source", "line", "column", "name", "count",
+];
+
+/// Quote the field for CSV if needed, or replace separators in it for TSV
+fn field(value: &str, separator: char) -> String {
+    if separator == '\t' {
+        value.replace(['\t', '\r', '\n'], " ")
+    } else if value.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+/// Render distinct resolved frames, in order of their first occurrence, with how many times each
+/// occurs, the same address in different modules being different frames
+pub fn render(frames: &[Frame], separator: char) -> String {
+    // JS function name of the frame
+    let name = Regex::new(r"^\s*at (?P<name>[^\s()]+) \(").unwrap();
+    let mut rows: Vec<([String; 6], usize)> = Vec::new();
+    // index of each distinct row in `rows`, kept in order of first occurrence
+    let mut index: HashMap<[String; 6], usize> = HashMap::new();
+    for frame in frames {
+        let row = [
+            format!("{:#x}", frame.addr),
+            frame.module.clone().unwrap_or_default(),
+            frame.location.source.clone(),
+            frame.location.line.to_string(),
+            frame
+                .column
+                .map_or_else(String::new, |column| column.to_string()),
+            frame.name.clone().unwrap_or_else(|| {
+                name.captures(&frame.text)
+                    .map_or_else(String::new, |caps| caps["name"].to_owned())
+            }),
+        ];
+        match index.get(&row) {
+            Some(&i) => rows[i].1 += 1,
+            None => {
+                index.insert(row.clone(), rows.len());
+                rows.push((row, 1));
+            }
+        }
+    }
+    let mut out = HEADER.join(&separator.to_string()) + "\n";
+    for (row, count) in rows {
+        for value in &row {
+            out.push_str(&field(value, separator));
+            out.push(separator);
+        }
+        out.push_str(&format!("{count}\n"));
+    }
+    out
+}
+
+/// Frames and distinct addresses of each module, in order of first occurrence, if the frames come
+/// from more than one module
+pub fn modules(frames: &[Frame]) -> Option<String> {
+    let mut modules: Vec<(&str, usize, BTreeSet<u32>)> = Vec::new();
+    let mut index = HashMap::new();
+    for frame in frames {
+        let module = frame.module.as_deref().unwrap_or("(unknown)");
+        let i = *index.entry(module).or_insert_with(|| {
+            modules.push((module, 0, BTreeSet::new()));
+            modules.len() - 1
+        });
+        modules[i].1 += 1;
+        modules[i].2.insert(frame.addr);
+    }
+    if modules.len() < 2 {
+        return None;
+    }
+    let mut out = "frames by module:\n".to_owned();
+    for (module, count, addrs) in modules {
+        out.push_str(&format!(
+            "  {module}: {count} frames at {} distinct addresses\n",
+            addrs.len()
+        ));
+    }
+    Some(out)
+}
+
+/// Breakdown of frames by their owners, each frame counted for every owner of its source
+pub fn owners(frames: &[Frame]) -> String {
+    let mut owners: Vec<(&str, usize)> = Vec::new();
+    let mut index = HashMap::new();
+    for frame in frames {
+        let names = if frame.owners.is_empty() {
+            vec!["(unowned)"]
+        } else {
+            frame.owners.iter().map(String::as_str).collect()
+        };
+        for name in names {
+            let i = *index.entry(name).or_insert_with(|| {
+                owners.push((name, 0));
+                owners.len() - 1
+            });
+            owners[i].1 += 1;
+        }
+    }
+    owners.sort_by_key(|(_, count)| Reverse(*count));
+    let mut out = "frames by owner:\n".to_owned();
+    for (owner, count) in owners {
+        out.push_str(&format!("  {owner}: {count} frames\n"));
+    }
+    out
+}
@@ -0,0 +1,181 @@
+use crate::{sha256, Frame};
+use eyre::Result;
+use regex::Regex;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::mem;
+
+/// Blank line, or header of a new JS error or Rust panic
+pub const DEFAULT_DELIMITER: &str = r"^(?:[ \t]*|\S*Error\b.*|.*panicked at.*)$";
+
+/// Message and location of a Rust panic, as printed by the default hook or
+/// `console_error_panic_hook` before the traceback
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Panic {
+    pub message: String,
+    pub location: Option<String>,
+}
+
+impl Panic {
+    /// Panic of `panicked at 'MESSAGE', LOCATION`, or of `panicked at LOCATION:` followed by the
+    /// message on the next line since Rust 1.73
+    fn parse(line: &str, next: Option<&str>) -> Option<Self> {
+        let (_, rest) = line.split_once("panicked at ")?;
+        let rest = rest.trim_end();
+        if let Some((message, location)) = rest
+            .strip_prefix('\'')
+            .and_then(|quoted| quoted.rsplit_once("', "))
+        {
+            return Some(Self {
+                message: message.to_owned(),
+                location: Some(location.to_owned()),
+            });
+        }
+        let location = rest.strip_suffix(':').unwrap_or(rest);
+        Some(Self {
+            message: next.map_or_else(String::new, |next| next.trim().to_owned()),
+            location: Some(location.to_owned()).filter(|location| !location.is_empty()),
+        })
+    }
+}
+
+/// Boundaries of tracebacks in the input, told by lines matching the delimiter
+pub struct Traces {
+    delimiter: Regex,
+    /// Index of the current traceback, assigned at its first frame
+    current: Option<usize>,
+    count: usize,
+    /// Panic seen since the last traceback with frames, taken by the next one
+    pending: Option<Panic>,
+    panic: Option<Panic>,
+}
+
+impl Traces {
+    pub fn new(delimiter: &str) -> Result<Self> {
+        Ok(Self {
+            delimiter: Regex::new(delimiter)?,
+            current: None,
+            count: 0,
+            pending: None,
+            panic: None,
+        })
+    }
+
+    /// Split `text` before each line matching the delimiter, telling whether each chunk starts a
+    /// new traceback
+    pub fn split<'a>(&self, text: &'a str) -> Vec<(bool, &'a str)> {
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut boundary = false;
+        let mut pos = 0;
+        for line in text.split_inclusive('\n') {
+            let content = line.trim_end_matches(['\n', '\r']);
+            if self.delimiter.is_match(content) {
+                if pos > start {
+                    chunks.push((boundary, &text[start..pos]));
+                }
+                start = pos;
+                boundary = true;
+            }
+            pos += line.len();
+        }
+        if pos > start {
+            chunks.push((boundary, &text[start..pos]));
+        }
+        chunks
+    }
+
+    /// Look for a panic in `text` read before the frames it leads
+    pub fn observe(&mut self, text: &str) {
+        let mut lines = text.lines();
+        while let Some(line) = lines.next() {
+            if line.contains("panicked at ") {
+                self.pending = Panic::parse(line, lines.clone().next());
+            }
+        }
+    }
+
+    /// End the current traceback
+    pub fn end(&mut self) {
+        if self.current.take().is_some() {
+            self.panic = None;
+        }
+    }
+
+    /// Index of the current traceback among those with frames
+    pub fn index(&mut self) -> usize {
+        *self.current.get_or_insert_with(|| {
+            self.panic = self.pending.take();
+            self.count += 1;
+            self.count - 1
+        })
+    }
+
+    /// Panic leading the current traceback
+    pub fn panic(&self) -> Option<&Panic> {
+        self.panic.as_ref()
+    }
+}
+
+/// Collapsing of identical consecutive tracebacks into one followed by a note
+#[derive(Default)]
+pub struct Dedupe {
+    current: String,
+    /// The last traceback printed, trimmed
+    last: Option<String>,
+    repeats: usize,
+    /// Blank lines between tracebacks, dropped along with the repeated traceback
+    gap: String,
+}
+
+impl Dedupe {
+    pub fn push(&mut self, text: &str) {
+        self.current.push_str(text);
+    }
+
+    fn note(&mut self) -> String {
+        match mem::take(&mut self.repeats) {
+            0 => String::new(),
+            repeats => format!("[repeated {} times]\n", repeats + 1),
+        }
+    }
+
+    /// End the current traceback, returning what to print
+    pub fn end(&mut self) -> String {
+        let trace = mem::take(&mut self.current);
+        if trace.trim().is_empty() {
+            self.gap.push_str(&trace);
+            return String::new();
+        }
+        if self.last.as_deref() == Some(trace.trim()) {
+            self.repeats += 1;
+            self.gap.clear();
+            return String::new();
+        }
+        self.last = Some(trace.trim().to_owned());
+        self.note() + &mem::take(&mut self.gap) + &trace
+    }
+
+    pub fn finish(&mut self) -> String {
+        self.end() + &self.note() + &mem::take(&mut self.gap)
+    }
+}
+
+/// Fingerprint of the traceback of each frame, from the panic leading it and the locations of its
+/// frames, telling apart tracebacks of the same frames failing different assertions
+pub fn fingerprints(frames: &[Frame]) -> Vec<String> {
+    let mut traces: HashMap<usize, String> = HashMap::new();
+    for frame in frames {
+        let key = traces
+            .entry(frame.trace)
+            .or_insert_with(|| match &frame.panic {
+                Some(panic) => format!("{}\n", panic.message),
+                None => "\n".to_owned(),
+            });
+        writeln!(key, "{}:{}", frame.location.source, frame.location.line).unwrap();
+    }
+    frames
+        .iter()
+        .map(|frame| sha256::hex(&sha256::sha256(traces[&frame.trace].as_bytes()))[..16].to_owned())
+        .collect()
+}
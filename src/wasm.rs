@@ -0,0 +1,267 @@
+use eyre::{bail, eyre, Result};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+
+/// Functions of a WASM module, in the function index space
+#[derive(Debug, Default, Clone)]
+pub struct Module {
+    /// Number of imported functions, which precede defined functions in the index space
+    imported: u32,
+    /// Offset of the first instruction of each defined function, relative to the module
+    code: Vec<u32>,
+    /// URL of the source map from the `sourceMappingURL` custom section
+    source_mapping_url: Option<String>,
+    /// Function names from the `name` custom section, by function index
+    names: HashMap<u32, String>,
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn eof(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        let b = *self
+            .data
+            .get(self.pos)
+            .ok_or_else(|| eyre!("unexpected end of WASM module"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn leb(&mut self) -> Result<u64> {
+        let mut result = 0u64;
+        let mut shift = 0;
+        loop {
+            let b = self.byte()?;
+            if shift < 64 {
+                result |= ((b & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if b & 0x80 == 0 {
+                return Ok(result);
+            }
+        }
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(self.leb()? as u32)
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let end = self.pos + len;
+        if end > self.data.len() {
+            bail!("unexpected end of WASM module");
+        }
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn name(&mut self) -> Result<String> {
+        let len = self.u32()? as usize;
+        Ok(String::from_utf8_lossy(self.bytes(len)?).into_owned())
+    }
+
+    fn limits(&mut self) -> Result<()> {
+        let flags = self.byte()?;
+        self.leb()?;
+        if flags & 1 != 0 {
+            self.leb()?;
+        }
+        Ok(())
+    }
+}
+
+fn imported_functions(section: &mut Reader) -> Result<u32> {
+    let mut functions = 0;
+    for _ in 0..section.u32()? {
+        section.name()?;
+        section.name()?;
+        match section.byte()? {
+            0x00 => {
+                section.u32()?;
+                functions += 1;
+            }
+            0x01 => {
+                section.byte()?;
+                section.limits()?;
+            }
+            0x02 => section.limits()?,
+            0x03 => {
+                section.byte()?;
+                section.byte()?;
+            }
+            0x04 => {
+                section.byte()?;
+                section.u32()?;
+            }
+            kind => bail!("unknown import kind {kind:#x}"),
+        }
+    }
+    Ok(functions)
+}
+
+fn code(section: &mut Reader) -> Result<Vec<u32>> {
+    let count = section.u32()?;
+    let mut code = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let size = section.u32()? as usize;
+        let end = section.pos + size;
+        for _ in 0..section.u32()? {
+            section.u32()?;
+            section.byte()?;
+        }
+        code.push(section.pos as u32);
+        section.pos = end;
+    }
+    Ok(code)
+}
+
+fn function_names(section: &mut Reader) -> Result<HashMap<u32, String>> {
+    let mut names = HashMap::new();
+    while !section.eof() {
+        let id = section.byte()?;
+        let size = section.u32()? as usize;
+        let end = section.pos + size;
+        if id == 1 {
+            for _ in 0..section.u32()? {
+                let index = section.u32()?;
+                names.insert(index, section.name()?);
+            }
+        }
+        section.pos = end;
+    }
+    Ok(names)
+}
+
+pub(crate) struct Sections<'a> {
+    /// Offset of the code section contents
+    pub code: Option<u32>,
+    /// Contents of each custom section by name
+    pub custom: HashMap<String, &'a [u8]>,
+}
+
+pub(crate) fn sections(data: &[u8]) -> Result<Sections<'_>> {
+    if data.len() < 8 || &data[..4] != b"\0asm" {
+        bail!("not a WASM module");
+    }
+    let mut code = None;
+    let mut custom = HashMap::new();
+    let mut reader = Reader::new(data, 8);
+    while !reader.eof() {
+        let id = reader.byte()?;
+        let size = reader.u32()? as usize;
+        let end = reader.pos + size;
+        match id {
+            0 => {
+                let mut section = Reader::new(&data[..end.min(data.len())], reader.pos);
+                let name = section.name()?;
+                custom.insert(name, section.bytes(end - section.pos)?);
+            }
+            10 => code = Some(reader.pos as u32),
+            _ => {}
+        }
+        reader.pos = end;
+    }
+    Ok(Sections { code, custom })
+}
+
+impl Module {
+    pub fn open(path: &str) -> Result<Self> {
+        Self::parse(&fs::read(path)?)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self> {
+        if data.len() < 8 || &data[..4] != b"\0asm" {
+            bail!("not a WASM module");
+        }
+        let mut module = Self::default();
+        let mut reader = Reader::new(data, 8);
+        while !reader.eof() {
+            let id = reader.byte()?;
+            let size = reader.u32()? as usize;
+            let end = reader.pos + size;
+            let mut section = Reader::new(&data[..end.min(data.len())], reader.pos);
+            match id {
+                0 => match section.name()?.as_str() {
+                    "sourceMappingURL" => module.source_mapping_url = Some(section.name()?),
+                    // names are best effort, a malformed section does not fail the module
+                    "name" => module.names = function_names(&mut section).unwrap_or_default(),
+                    _ => {}
+                },
+                2 => module.imported = imported_functions(&mut section)?,
+                10 => module.code = code(&mut section)?,
+                _ => {}
+            }
+            reader.pos = end;
+        }
+        Ok(module)
+    }
+
+    pub fn source_mapping_url(&self) -> Option<&str> {
+        self.source_mapping_url.as_deref()
+    }
+
+    /// Name of the function from the `name` section
+    pub fn function_name(&self, index: u32) -> Option<&str> {
+        self.names.get(&index).map(String::as_str)
+    }
+
+    /// Offset of the first instruction of the function
+    pub fn function_offset(&self, index: u32) -> Option<u32> {
+        self.code
+            .get(index.checked_sub(self.imported)? as usize)
+            .copied()
+    }
+
+    /// Index of the defined function whose code the offset falls in
+    pub fn function_at(&self, offset: u32) -> Option<u32> {
+        let i = self.code.partition_point(|&start| start <= offset);
+        Some(self.imported + i.checked_sub(1)? as u32)
+    }
+
+    /// Function table stored in symbol bundle
+    pub(crate) fn to_json(&self) -> Value {
+        let names: serde_json::Map<_, _> = self
+            .names
+            .iter()
+            .map(|(index, name)| (index.to_string(), json!(name)))
+            .collect();
+        json!({ "imported": self.imported, "code": self.code, "names": names })
+    }
+
+    pub(crate) fn from_json(value: &Value) -> Result<Self> {
+        let malformed = || eyre!("malformed function table in symbol bundle");
+        let code = value["code"]
+            .as_array()
+            .ok_or_else(malformed)?
+            .iter()
+            .map(|offset| Some(offset.as_u64()? as u32))
+            .collect::<Option<_>>()
+            .ok_or_else(malformed)?;
+        let names = value["names"]
+            .as_object()
+            .ok_or_else(malformed)?
+            .iter()
+            .map(|(index, name)| Some((index.parse().ok()?, name.as_str()?.to_owned())))
+            .collect::<Option<_>>()
+            .ok_or_else(malformed)?;
+        Ok(Self {
+            imported: value["imported"].as_u64().ok_or_else(malformed)? as u32,
+            code,
+            source_mapping_url: None,
+            names,
+        })
+    }
+}
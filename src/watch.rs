@@ -0,0 +1,85 @@
+//! Changes to the files of a project, found by polling their modification times
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Interval of polling for changes
+const POLL: Duration = Duration::from_millis(500);
+
+/// Directories of build outputs, dependencies and version control never watched
+const IGNORED: &[&str] = &["target", "node_modules", "pkg", "dist", "_build"];
+
+#[derive(Debug)]
+pub struct Watcher {
+    roots: Vec<PathBuf>,
+    /// Number of files and sum of their modification times, changing along with any of them
+    snapshot: (usize, u128),
+}
+
+fn scan(dir: &Path, snapshot: &mut (usize, u128)) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if metadata.is_dir() {
+            if !name.starts_with('.') && !IGNORED.contains(&name.as_ref()) {
+                scan(&entry.path(), snapshot);
+            }
+        } else {
+            let modified = metadata.modified().unwrap_or(UNIX_EPOCH);
+            snapshot.0 += 1;
+            snapshot.1 = snapshot.1.wrapping_add(
+                modified
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_nanos(),
+            );
+        }
+    }
+}
+
+impl Watcher {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        let mut watcher = Self {
+            roots,
+            snapshot: (0, 0),
+        };
+        watcher.snapshot = watcher.scan();
+        watcher
+    }
+
+    fn scan(&self) -> (usize, u128) {
+        let mut snapshot = (0, 0);
+        for root in &self.roots {
+            scan(root, &mut snapshot);
+        }
+        snapshot
+    }
+
+    /// Block until files change, and then until they stop changing for an interval of polling
+    pub fn wait(&mut self) {
+        loop {
+            thread::sleep(POLL);
+            let snapshot = self.scan();
+            if snapshot != self.snapshot {
+                self.snapshot = snapshot;
+                break;
+            }
+        }
+        loop {
+            thread::sleep(POLL);
+            let snapshot = self.scan();
+            if snapshot == self.snapshot {
+                return;
+            }
+            self.snapshot = snapshot;
+        }
+    }
+}
@@ -0,0 +1,138 @@
+//! Exports for a web page loading the crate built for `wasm32-unknown-unknown`, wrapped by
+//! `web/mbtmap.js`
+//!
+//! Strings are passed in as pointer and length into memory allocated by [`mbtmap_alloc`], and
+//! returned as handles read by [`mbtmap_string_ptr`] and [`mbtmap_string_len`]. Resolvers are
+//! released by `mbtmap_close` of [`crate::ffi`], shared with the C ABI, whose names the others
+//! leave to it.
+
+use crate::preset::Preset;
+use crate::resolver::Resolver;
+use serde_json::json;
+use sourcemap::SourceMap;
+use std::ptr;
+
+unsafe fn bytes<'a>(ptr: *const u8, len: usize) -> &'a [u8] {
+    if len == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(ptr, len)
+}
+
+unsafe fn str<'a>(ptr: *const u8, len: usize) -> Option<&'a str> {
+    std::str::from_utf8(bytes(ptr, len)).ok()
+}
+
+/// # Safety
+///
+/// Memory returned must be released by [`mbtmap_dealloc`] with the same length.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// # Safety
+///
+/// `ptr` must be returned by [`mbtmap_alloc`] with the same length.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_dealloc(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// Parse the source map for frames in shape of the preset, empty for the default
+///
+/// Null is returned if the source map or preset is malformed.
+///
+/// # Safety
+///
+/// Both strings must be valid for their length.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_open(
+    map: *const u8,
+    map_len: usize,
+    preset: *const u8,
+    preset_len: usize,
+) -> *mut Resolver {
+    let preset = match str(preset, preset_len) {
+        Some("") => Preset::default(),
+        Some(preset) => match preset.parse() {
+            Ok(preset) => preset,
+            Err(_) => return ptr::null_mut(),
+        },
+        None => return ptr::null_mut(),
+    };
+    let Ok(map) = SourceMap::from_slice(bytes(map, map_len)) else {
+        return ptr::null_mut();
+    };
+    match Resolver::new(map, preset) {
+        Ok(resolver) => Box::into_raw(Box::new(resolver)),
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// Traceback with source location appended to each frame
+///
+/// # Safety
+///
+/// `resolver` must be returned by [`mbtmap_open`], `text` must be valid for its length.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_rewrite_str(
+    resolver: *const Resolver,
+    text: *const u8,
+    len: usize,
+) -> *mut String {
+    let (Some(resolver), Some(text)) = (resolver.as_ref(), str(text, len)) else {
+        return ptr::null_mut();
+    };
+    Box::into_raw(Box::new(resolver.rewrite(text)))
+}
+
+/// Source location of the address as JSON `{ "source", "line", "column" }`, `null` if the
+/// address does not resolve
+///
+/// # Safety
+///
+/// `resolver` must be returned by [`mbtmap_open`].
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_resolve_json(resolver: *const Resolver, addr: u32) -> *mut String {
+    let Some(resolver) = resolver.as_ref() else {
+        return ptr::null_mut();
+    };
+    let location = resolver.resolve(addr).map(|location| {
+        json!({
+            "source": location.source,
+            "line": location.line,
+            "column": location.column,
+        })
+    });
+    Box::into_raw(Box::new(json!(location).to_string()))
+}
+
+/// # Safety
+///
+/// `s` must be returned by this module and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_string_ptr(s: *const String) -> *const u8 {
+    (&*s).as_ptr()
+}
+
+/// # Safety
+///
+/// `s` must be returned by this module and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_string_len(s: *const String) -> usize {
+    (&*s).len()
+}
+
+/// # Safety
+///
+/// `s` must be returned by this module and not yet freed.
+#[no_mangle]
+pub unsafe extern "C" fn mbtmap_string_free(s: *mut String) {
+    if !s.is_null() {
+        drop(Box::from_raw(s));
+    }
+}
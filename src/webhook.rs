@@ -0,0 +1,80 @@
+//! Payloads of error-tracking webhooks, with the wasm frames of their stack frames resolved in place
+//!
+//! Frames are objects carrying `filename` or `abs_path`, with the address of a wasm frame taken from
+//! `instruction_addr`, from the `:0x` offset ending the filename, or from `colno` of a wasm filename,
+//! which V8 reports as the byte offset plus one.
+
+use crate::{locate, parse_addr, Maps};
+use regex::Regex;
+use serde_json::{json, Value};
+
+/// Resolver of the frames of payloads by the source maps of their filenames
+pub struct Webhook {
+    offset: Regex,
+}
+
+impl Default for Webhook {
+    fn default() -> Self {
+        Self {
+            offset: Regex::new(r":(0x[[:xdigit:]]+)$").unwrap(),
+        }
+    }
+}
+
+impl Webhook {
+    fn address(&self, frame: &serde_json::Map<String, Value>, filename: &str) -> Option<u32> {
+        if let Some(addr) = frame.get("instruction_addr").and_then(Value::as_str) {
+            return parse_addr(addr);
+        }
+        if let Some(caps) = self.offset.captures(filename) {
+            return parse_addr(&caps[1]);
+        }
+        let wasm = filename.starts_with("wasm://")
+            || filename.ends_with(".wasm")
+            || filename.contains("wasm-function[");
+        let colno = frame
+            .get("colno")
+            .and_then(Value::as_u64)
+            .filter(|_| wasm)?;
+        u32::try_from(colno.checked_sub(1)?).ok()
+    }
+
+    /// Resolve wasm frames anywhere in the payload, returning how many are resolved
+    pub fn symbolicate(&self, payload: &mut Value, maps: &Maps) -> usize {
+        match payload {
+            Value::Array(values) => values
+                .iter_mut()
+                .map(|value| self.symbolicate(value, maps))
+                .sum(),
+            Value::Object(object) => {
+                let filename = ["filename", "abs_path"]
+                    .iter()
+                    .find_map(|key| object.get(*key)?.as_str())
+                    .map(str::to_owned);
+                let resolved = filename.and_then(|filename| {
+                    let addr = self.address(object, &filename)?;
+                    let location = locate(&maps.get(Some(&filename)).lookup_token(0, addr)?, &None);
+                    for key in ["filename", "abs_path"] {
+                        if key == "filename" || object.contains_key(key) {
+                            object.insert(key.to_owned(), json!(location.source));
+                        }
+                    }
+                    object.insert("lineno".to_owned(), json!(location.line));
+                    object.insert("colno".to_owned(), json!(location.column));
+                    object.insert(
+                        "mbtmap".to_owned(),
+                        json!({ "address": format!("{addr:#x}"), "filename": filename }),
+                    );
+                    Some(1)
+                });
+                resolved.unwrap_or_else(|| {
+                    object
+                        .values_mut()
+                        .map(|value| self.symbolicate(value, maps))
+                        .sum()
+                })
+            }
+            _ => 0,
+        }
+    }
+}
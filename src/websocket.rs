@@ -0,0 +1,348 @@
+//! WebSocket connections, just enough for exchanging text messages
+
+use data_encoding::BASE64;
+use eyre::{bail, eyre, Result};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Appended to the key of the client to accept the handshake
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Largest message accepted
+const MAX_MESSAGE: usize = 16 << 20;
+
+pub(crate) enum Message {
+    Text(String),
+    Ping(Vec<u8>),
+    Close,
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend((data.len() as u64 * 8).to_be_bytes());
+    for block in padded.chunks_exact(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(word.try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..20 => ((b & c) | (!b & d), 0x5a827999),
+                20..40 => (b ^ c ^ d, 0x6ed9eba1),
+                40..60 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let t = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*w);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    let mut digest = [0; 20];
+    for (bytes, s) in digest.chunks_exact_mut(4).zip(state) {
+        bytes.copy_from_slice(&s.to_be_bytes());
+    }
+    digest
+}
+
+/// `Sec-WebSocket-Accept` responding to the `Sec-WebSocket-Key` of the client
+pub(crate) fn accept(key: &str) -> String {
+    BASE64.encode(&sha1(format!("{}{GUID}", key.trim()).as_bytes()))
+}
+
+/// Unpredictable bytes for the key and masks of a client, hashed by the keys `RandomState` seeds
+/// from the randomness of the OS, each `RandomState` keyed anew
+fn nonce() -> [u8; 16] {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos();
+    let mut nonce = [0; 16];
+    for half in nonce.chunks_exact_mut(8) {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write_u128(nanos);
+        half.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    nonce
+}
+
+/// Connect to a `ws://` URL, returning the connection to read messages from and to write frames to
+pub(crate) fn connect(url: &str) -> Result<(BufReader<TcpStream>, TcpStream)> {
+    let parsed = url::Url::parse(url)?;
+    if parsed.scheme() != "ws" {
+        bail!("{url}: only ws:// is supported");
+    }
+    let host = parsed
+        .host_str()
+        .ok_or_else(|| eyre!("{url}: missing host"))?;
+    let port = parsed.port().unwrap_or(80);
+    let mut stream =
+        TcpStream::connect((host, port)).map_err(|e| eyre!("failed to connect to {url}: {e}"))?;
+    let key = BASE64.encode(&nonce());
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {host}:{port}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Key: {key}\r\nSec-WebSocket-Version: 13\r\n\r\n",
+        &parsed[url::Position::BeforePath..]
+    )?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if line.split(' ').nth(1) != Some("101") {
+        bail!("{url}: handshake refused with {}", line.trim());
+    }
+    let mut accepted = false;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 || line.trim_end().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            accepted |= name.trim().eq_ignore_ascii_case("sec-websocket-accept")
+                && value.trim() == accept(&key);
+        }
+    }
+    if !accepted {
+        bail!("{url}: handshake not accepted");
+    }
+    Ok((reader, stream))
+}
+
+/// Write a frame of a single fragment, masked as required of a client if `masked`
+pub(crate) fn write_frame(
+    w: &mut impl Write,
+    opcode: u8,
+    payload: &[u8],
+    masked: bool,
+) -> Result<()> {
+    let mut header = vec![0x80 | opcode];
+    let bit = if masked { 0x80 } else { 0 };
+    match payload.len() {
+        len @ 0..126 => header.push(bit | len as u8),
+        len @ 126..=0xffff => {
+            header.push(bit | 126);
+            header.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            header.push(bit | 127);
+            header.extend((len as u64).to_be_bytes());
+        }
+    }
+    if masked {
+        let mask: [u8; 4] = nonce()[..4].try_into().unwrap();
+        header.extend(mask);
+        w.write_all(&header)?;
+        let payload = payload
+            .iter()
+            .enumerate()
+            .map(|(i, byte)| byte ^ mask[i % 4])
+            .collect::<Vec<_>>();
+        w.write_all(&payload)?;
+    } else {
+        w.write_all(&header)?;
+        w.write_all(payload)?;
+    }
+    Ok(w.flush()?)
+}
+
+/// Read the next message, reassembled from its fragments, or `None` once the connection is closed
+///
+/// Control frames between the fragments of a message are returned as they come, the fragments read
+/// so far kept in `message` until the rest of them are read by the next calls.
+pub(crate) fn read_message(r: &mut impl Read, message: &mut Vec<u8>) -> Result<Option<Message>> {
+    loop {
+        let mut header = [0; 2];
+        match r.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let (fin, opcode, masked) = (
+            header[0] & 0x80 != 0,
+            header[0] & 0x0f,
+            header[1] & 0x80 != 0,
+        );
+        let len = match header[1] & 0x7f {
+            126 => {
+                let mut len = [0; 2];
+                r.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as usize
+            }
+            127 => {
+                let mut len = [0; 8];
+                r.read_exact(&mut len)?;
+                usize::try_from(u64::from_be_bytes(len)).unwrap_or(usize::MAX)
+            }
+            len => len as usize,
+        };
+        if message.len().saturating_add(len) > MAX_MESSAGE {
+            bail!("message larger than {MAX_MESSAGE} bytes");
+        }
+        // frames from the server are not masked
+        let mut mask = [0; 4];
+        if masked {
+            r.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; len];
+        r.read_exact(&mut payload)?;
+        for (i, byte) in payload.iter_mut().enumerate() {
+            *byte ^= mask[i % 4];
+        }
+        match opcode {
+            0x8 => return Ok(Some(Message::Close)),
+            0x9 => return Ok(Some(Message::Ping(payload))),
+            0xa => continue,
+            // continuation, text and binary; binary taken as text as well
+            0x0..=0x2 => message.extend(payload),
+            _ => bail!("unknown opcode {opcode:#x}"),
+        }
+        if fin {
+            let text = String::from_utf8_lossy(message).into_owned();
+            message.clear();
+            return Ok(Some(Message::Text(text)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(message: Option<Message>) -> String {
+        match message {
+            Some(Message::Text(text)) => text,
+            _ => panic!("expected a text message"),
+        }
+    }
+
+    #[test]
+    fn accepts_key() {
+        // example of RFC 6455
+        assert_eq!(
+            accept("dGhlIHNhbXBsZSBub25jZQ=="),
+            "s3pPLMBiTxaQ9kYGzzhZRbK+xOo="
+        );
+    }
+
+    #[test]
+    fn reads_frames() {
+        // examples of RFC 6455, unmasked, masked, and fragmented
+        let unmasked = [0x81, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        assert_eq!(
+            text(read_message(&mut &unmasked[..], &mut Vec::new()).unwrap()),
+            "Hello"
+        );
+        let masked = [
+            0x81, 0x85, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x51, 0x58,
+        ];
+        assert_eq!(
+            text(read_message(&mut &masked[..], &mut Vec::new()).unwrap()),
+            "Hello"
+        );
+        let fragmented = [0x01, 0x03, 0x48, 0x65, 0x6c, 0x80, 0x02, 0x6c, 0x6f];
+        assert_eq!(
+            text(read_message(&mut &fragmented[..], &mut Vec::new()).unwrap()),
+            "Hello"
+        );
+        let ping = [0x89, 0x05, 0x48, 0x65, 0x6c, 0x6c, 0x6f];
+        assert!(matches!(
+            read_message(&mut &ping[..], &mut Vec::new()).unwrap(),
+            Some(Message::Ping(payload)) if payload == b"Hello"
+        ));
+        assert!(matches!(
+            read_message(&mut &[0x88, 0x00][..], &mut Vec::new()).unwrap(),
+            Some(Message::Close)
+        ));
+        assert!(read_message(&mut &[][..], &mut Vec::new())
+            .unwrap()
+            .is_none());
+        assert!(read_message(&mut &[0x83, 0x00][..], &mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn ping_between_fragments() {
+        let frames = [
+            &[0x01, 0x03, 0x48, 0x65, 0x6c][..],
+            &[0x89, 0x02, 0x68, 0x69],
+            &[0x80, 0x02, 0x6c, 0x6f],
+            &[0x81, 0x02, 0x6f, 0x6b],
+        ]
+        .concat();
+        let (mut r, mut fragments) = (&frames[..], Vec::new());
+        assert!(matches!(
+            read_message(&mut r, &mut fragments).unwrap(),
+            Some(Message::Ping(payload)) if payload == b"hi"
+        ));
+        assert_eq!(fragments, b"Hel");
+        assert_eq!(text(read_message(&mut r, &mut fragments).unwrap()), "Hello");
+        assert!(fragments.is_empty());
+        assert_eq!(text(read_message(&mut r, &mut fragments).unwrap()), "ok");
+    }
+
+    #[test]
+    fn nonces_differ() {
+        assert_ne!(nonce(), nonce());
+    }
+
+    #[test]
+    fn round_trips() {
+        for len in [0, 125, 126, 0xffff, 0x10000] {
+            let payload = "x".repeat(len);
+            for masked in [false, true] {
+                let mut frame = Vec::new();
+                write_frame(&mut frame, 0x1, payload.as_bytes(), masked).unwrap();
+                let header = match len {
+                    0..126 => 2,
+                    126..=0xffff => 4,
+                    _ => 10,
+                } + if masked { 4 } else { 0 };
+                assert_eq!(frame.len(), header + len, "{len} bytes, masked {masked}");
+                assert_eq!(frame[0], 0x81);
+                assert_eq!(frame[1] & 0x80 != 0, masked);
+                match len {
+                    0..126 => assert_eq!(frame[1] & 0x7f, len as u8),
+                    126..=0xffff => {
+                        assert_eq!(frame[1] & 0x7f, 126);
+                        assert_eq!(u16::from_be_bytes([frame[2], frame[3]]), len as u16);
+                    }
+                    _ => {
+                        assert_eq!(frame[1] & 0x7f, 127);
+                        let extended = u64::from_be_bytes(frame[2..10].try_into().unwrap());
+                        assert_eq!(extended, len as u64);
+                    }
+                }
+                if masked && len > 0 {
+                    assert_ne!(&frame[header..], payload.as_bytes());
+                }
+                assert_eq!(
+                    text(read_message(&mut &frame[..], &mut Vec::new()).unwrap()),
+                    payload
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_oversized() {
+        let mut frame = vec![0x81, 127];
+        frame.extend((MAX_MESSAGE as u64 + 1).to_be_bytes());
+        assert!(read_message(&mut &frame[..], &mut Vec::new()).is_err());
+    }
+}
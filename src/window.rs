@@ -0,0 +1,213 @@
+use regex::{Captures, Regex};
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Timestamp prefixing a log line, in ISO 8601 or syslog form, the latter without year
+#[derive(Clone, Copy, Debug)]
+pub struct Timestamp {
+    year: Option<i64>,
+    month: i64,
+    day: i64,
+    seconds: i64,
+    nanos: i64,
+    /// Offset from UTC in seconds, lines without zone taken as in the same zone as the window
+    offset: i64,
+}
+
+/// Days since 1970-01-01 of the proleptic Gregorian date
+pub(crate) fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Proleptic Gregorian date of the days since 1970-01-01
+pub(crate) fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Seconds and nanoseconds since the epoch of the RFC 3339 timestamp in UTC prefixing `line`
+pub(crate) fn rfc3339(line: &str) -> Option<(i64, i64)> {
+    let timestamp = line.split(' ').next()?.strip_suffix('Z')?;
+    let (date, time) = timestamp.split_once('T')?;
+    let mut date = date.splitn(3, '-').map(str::parse::<i64>);
+    let (year, month, day) = (date.next()?.ok()?, date.next()?.ok()?, date.next()?.ok()?);
+    let (time, fraction) = time.split_once('.').unwrap_or((time, ""));
+    let mut time = time.splitn(3, ':').map(str::parse::<i64>);
+    let seconds = time.next()?.ok()? * 3600 + time.next()?.ok()? * 60 + time.next()?.ok()?;
+    let nanos = format!("{fraction:0<9}").get(..9)?.parse().ok()?;
+    Some((days_from_civil(year, month, day) * 86400 + seconds, nanos))
+}
+
+/// RFC 3339 timestamp in UTC of the seconds and nanoseconds since the epoch
+pub(crate) fn format_rfc3339((seconds, nanos): (i64, i64)) -> String {
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.{nanos:09}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Timestamp prefixes recognized
+pub(crate) struct Patterns {
+    iso_8601: Regex,
+    syslog: Regex,
+}
+
+impl Patterns {
+    pub(crate) fn new() -> Self {
+        Self {
+            iso_8601: Regex::new(
+                r"^\[?(?P<year>\d{4})-(?P<month>\d{2})-(?P<day>\d{2})(?:[T ](?P<hour>\d{2}):(?P<minute>\d{2})(?::(?P<second>\d{2})(?:[.,](?P<fraction>\d+))?)?(?P<zone>Z|[+-]\d{2}:?\d{2})?)?",
+            )
+            .unwrap(),
+            syslog: Regex::new(
+                r"^\[?(?P<month>Jan|Feb|Mar|Apr|May|Jun|Jul|Aug|Sep|Oct|Nov|Dec) +(?P<day>\d{1,2}) (?P<hour>\d{2}):(?P<minute>\d{2}):(?P<second>\d{2})",
+            )
+            .unwrap(),
+        }
+    }
+
+    /// Whether `line` starts with a timestamp
+    pub(crate) fn is_match(&self, line: &str) -> bool {
+        self.iso_8601.is_match(line) || self.syslog.is_match(line)
+    }
+
+    /// Timestamp at the start of `line`, and its length
+    fn parse(&self, line: &str) -> Option<(Timestamp, usize)> {
+        let number = |captures: &Captures, name| {
+            captures
+                .name(name)
+                .map_or(Some(0), |m| m.as_str().parse::<i64>().ok())
+        };
+        if let Some(captures) = self.iso_8601.captures(line) {
+            let fraction = captures.name("fraction").map_or("", |m| m.as_str());
+            let nanos = format!("{fraction:0<9}")[..9].parse().ok()?;
+            let offset = match captures.name("zone").map(|m| m.as_str()) {
+                None | Some("Z") => 0,
+                Some(zone) => {
+                    let digits = zone[1..].replace(':', "");
+                    let minutes =
+                        digits[..2].parse::<i64>().ok()? * 60 + digits[2..].parse::<i64>().ok()?;
+                    if zone.starts_with('-') {
+                        -minutes * 60
+                    } else {
+                        minutes * 60
+                    }
+                }
+            };
+            let timestamp = Timestamp {
+                year: Some(number(&captures, "year")?),
+                month: number(&captures, "month")?,
+                day: number(&captures, "day")?,
+                seconds: number(&captures, "hour")? * 3600
+                    + number(&captures, "minute")? * 60
+                    + number(&captures, "second")?,
+                nanos,
+                offset,
+            };
+            return Some((timestamp, captures[0].len()));
+        }
+        let captures = self.syslog.captures(line)?;
+        let timestamp = Timestamp {
+            year: None,
+            month: MONTHS.iter().position(|m| *m == &captures["month"])? as i64 + 1,
+            day: number(&captures, "day")?,
+            seconds: number(&captures, "hour")? * 3600
+                + number(&captures, "minute")? * 60
+                + number(&captures, "second")?,
+            nanos: 0,
+            offset: 0,
+        };
+        Some((timestamp, captures[0].len()))
+    }
+}
+
+impl Timestamp {
+    /// Seconds and nanoseconds since the epoch, in `year` if the timestamp has none
+    fn instant(&self, year: i64) -> (i64, i64) {
+        let days = days_from_civil(self.year.unwrap_or(year), self.month, self.day);
+        (days * 86400 + self.seconds - self.offset, self.nanos)
+    }
+}
+
+/// Parse `--since` or `--until`, a whole timestamp rather than a prefix
+pub fn parse_bound(s: &str) -> Result<Timestamp, String> {
+    Patterns::new()
+        .parse(s)
+        .filter(|(_, len)| *len == s.len())
+        .map(|(timestamp, _)| timestamp)
+        .ok_or_else(|| format!("expected ISO 8601 or syslog timestamp, found `{s}`"))
+}
+
+/// Lines of the input within `--since` and `--until`, those without timestamp taking after the
+/// last line with one
+pub struct Window {
+    patterns: Patterns,
+    since: Option<Timestamp>,
+    until: Option<Timestamp>,
+    /// Year of syslog timestamps, taken from the bounds
+    year: i64,
+    inside: bool,
+}
+
+impl Window {
+    pub fn new(since: Option<Timestamp>, until: Option<Timestamp>) -> Self {
+        Self {
+            patterns: Patterns::new(),
+            since,
+            until,
+            year: since.or(until).and_then(|bound| bound.year).unwrap_or(1970),
+            inside: false,
+        }
+    }
+
+    fn contains(&self, timestamp: &Timestamp) -> bool {
+        let instant = timestamp.instant(self.year);
+        self.since
+            .is_none_or(|since| since.instant(self.year) <= instant)
+            && self
+                .until
+                .is_none_or(|until| instant <= until.instant(self.year))
+    }
+
+    /// Split `text` into runs of lines, telling whether each run is inside the window
+    pub fn split<'a>(&mut self, text: &'a str) -> Vec<(bool, &'a str)> {
+        let mut runs: Vec<(bool, &str)> = Vec::new();
+        let mut start = 0;
+        let mut pos = 0;
+        for line in text.split_inclusive('\n') {
+            let inside = match self.patterns.parse(line) {
+                Some((timestamp, _)) => self.contains(&timestamp),
+                None => self.inside,
+            };
+            if inside != self.inside && pos > start {
+                runs.push((self.inside, &text[start..pos]));
+                start = pos;
+            }
+            self.inside = inside;
+            pos += line.len();
+        }
+        if pos > start {
+            runs.push((self.inside, &text[start..pos]));
+        }
+        runs
+    }
+}
@@ -0,0 +1,104 @@
+use serde_json::Value;
+
+/// Piece of input with JSON log envelopes of `wrangler tail --format json` unwrapped
+#[derive(Debug)]
+pub enum Item {
+    Text(String),
+    Envelope {
+        script: Option<String>,
+        text: String,
+    },
+}
+
+/// Accumulator of possibly pretty-printed JSON envelopes spanning several lines
+#[derive(Debug, Default)]
+pub struct Envelopes {
+    pending: String,
+}
+
+fn message(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values.iter().map(message).collect::<Vec<_>>().join(" "),
+        value => value.to_string(),
+    }
+}
+
+fn unwrap(envelope: &Value) -> Option<Item> {
+    let object = envelope.as_object()?;
+    if !["scriptName", "logs", "exceptions"]
+        .iter()
+        .any(|key| object.contains_key(*key))
+    {
+        return None;
+    }
+    let mut text = String::new();
+    for log in object
+        .get("logs")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        text.push_str(&message(log.get("message").unwrap_or(log)));
+        text.push('\n');
+    }
+    for exception in object
+        .get("exceptions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+    {
+        let stack = exception.get("stack").and_then(Value::as_str);
+        let name = exception
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or("Error");
+        let message = exception.get("message").map(message).unwrap_or_default();
+        match stack {
+            Some(stack) if stack.starts_with(&format!("{name}:")) => text.push_str(stack),
+            Some(stack) => text.push_str(&format!("{name}: {message}\n{stack}")),
+            None => text.push_str(&format!("{name}: {message}")),
+        }
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+    }
+    Some(Item::Envelope {
+        script: object
+            .get("scriptName")
+            .and_then(Value::as_str)
+            .map(str::to_owned),
+        text,
+    })
+}
+
+impl Envelopes {
+    pub fn push(&mut self, input: &str) -> Vec<Item> {
+        let mut items = Vec::new();
+        for line in input.split_inclusive('\n') {
+            if self.pending.is_empty() && !line.trim_start().starts_with('{') {
+                items.push(Item::Text(line.to_owned()));
+                continue;
+            }
+            self.pending.push_str(line);
+            match serde_json::from_str::<Value>(&self.pending) {
+                Ok(envelope) => {
+                    let pending = std::mem::take(&mut self.pending);
+                    items.push(unwrap(&envelope).unwrap_or(Item::Text(pending)));
+                }
+                Err(e) if e.is_eof() => {}
+                Err(_) => items.push(Item::Text(std::mem::take(&mut self.pending))),
+            }
+        }
+        items
+    }
+
+    /// Incomplete envelope left at the end of input
+    pub fn finish(&mut self) -> Option<Item> {
+        if self.pending.is_empty() {
+            None
+        } else {
+            Some(Item::Text(std::mem::take(&mut self.pending)))
+        }
+    }
+}
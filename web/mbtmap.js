@@ -0,0 +1,79 @@
+// Resolver running in the page, over mbtmap built with
+// `cargo build --lib --release --target wasm32-unknown-unknown --no-default-features --features web`
+
+const encoder = new TextEncoder();
+const decoder = new TextDecoder();
+
+export class Mbtmap {
+  static async load(url) {
+    const { instance } = await WebAssembly.instantiateStreaming(fetch(url));
+    return new Mbtmap(instance.exports);
+  }
+
+  constructor(exports) {
+    this.exports = exports;
+  }
+
+  // Pass the string into memory of the module for the duration of `f`
+  withString(s, f) {
+    const bytes = encoder.encode(s);
+    const ptr = this.exports.mbtmap_alloc(bytes.length);
+    new Uint8Array(this.exports.memory.buffer, ptr, bytes.length).set(bytes);
+    try {
+      return f(ptr, bytes.length);
+    } finally {
+      this.exports.mbtmap_dealloc(ptr, bytes.length);
+    }
+  }
+
+  // Take the string returned by the module
+  takeString(handle) {
+    if (handle === 0) {
+      return null;
+    }
+    const ptr = this.exports.mbtmap_string_ptr(handle);
+    const len = this.exports.mbtmap_string_len(handle);
+    const s = decoder.decode(new Uint8Array(this.exports.memory.buffer, ptr, len));
+    this.exports.mbtmap_string_free(handle);
+    return s;
+  }
+
+  // Resolver of the source map passed as text, frames in shape of the preset
+  open(sourceMap, preset = "moonbit") {
+    const resolver = this.withString(sourceMap, (map, mapLen) =>
+      this.withString(preset, (preset, presetLen) =>
+        this.exports.mbtmap_open(map, mapLen, preset, presetLen),
+      ),
+    );
+    if (resolver === 0) {
+      throw new Error("malformed source map or unknown preset");
+    }
+    return new Resolver(this, resolver);
+  }
+}
+
+export class Resolver {
+  constructor(mbtmap, handle) {
+    this.mbtmap = mbtmap;
+    this.handle = handle;
+  }
+
+  // Append source location to each frame in the traceback
+  rewrite(text) {
+    return this.mbtmap.takeString(
+      this.mbtmap.withString(text, (ptr, len) =>
+        this.mbtmap.exports.mbtmap_rewrite_str(this.handle, ptr, len),
+      ),
+    );
+  }
+
+  // Source location of the address, `{ source, line, column }` or `null`
+  resolve(addr) {
+    return JSON.parse(this.mbtmap.takeString(this.mbtmap.exports.mbtmap_resolve_json(this.handle, addr)));
+  }
+
+  close() {
+    this.mbtmap.exports.mbtmap_close(this.handle);
+    this.handle = 0;
+  }
+}